@@ -0,0 +1,58 @@
+use crate::{ray::Ray, vec3::Vec3};
+
+/// axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3<f64>,
+    pub max: Vec3<f64>,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3<f64>, max: Vec3<f64>) -> Self {
+        Aabb{min, max}
+    }
+
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            Vec3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            Vec3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        )
+    }
+
+    pub fn centroid(&self) -> Vec3<f64> {
+        (self.min + self.max).scale(0.5)
+    }
+
+    pub fn contains(&self, p: &Vec3<f64>) -> bool {
+        (self.min.x..=self.max.x).contains(&p.x) && (self.min.y..=self.max.y).contains(&p.y) && (self.min.z..=self.max.z).contains(&p.z)
+    }
+
+    /// the t range the ray spends inside the box, clipped to `t_min..t_max`.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut t_enter = t_min;
+        let mut t_exit = t_max;
+        for (origin, direction, min, max) in [
+            (ray.origin.x, ray.direction.x, self.min.x, self.max.x),
+            (ray.origin.y, ray.direction.y, self.min.y, self.max.y),
+            (ray.origin.z, ray.direction.z, self.min.z, self.max.z),
+        ] {
+            let inverse = 1.0 / direction;
+            let mut t0 = (min - origin) * inverse;
+            let mut t1 = (max - origin) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > t_enter {
+                t_enter = t0;
+            }
+            if t1 < t_exit {
+                t_exit = t1;
+            }
+            // 0 * inf for a ray lying exactly in a slab plane, counted as a miss.
+            if t_exit < t_enter || t0.is_nan() || t1.is_nan() {
+                return None;
+            }
+        }
+        Some((t_enter, t_exit))
+    }
+}
@@ -0,0 +1,89 @@
+/// picks among weighted items in constant time, walker's alias method as built by vose (1991).
+#[derive(Debug, Clone, Default)]
+pub struct AliasTable {
+    // the chance of keeping the item of the slot picked rather than taking its alias.
+    keep: Vec<f64>,
+    aliases: Vec<usize>,
+    probabilities: Vec<f64>,
+}
+
+impl AliasTable {
+    /// weights must not be negative. when they are all zero every item is as likely.
+    pub fn new(weights: &[f64]) -> Self {
+        let n = weights.len();
+        let total: f64 = weights.iter().sum();
+        let probabilities: Vec<f64> = if total > 0.0 {
+            weights.iter().map(|weight| weight / total).collect()
+        } else {
+            vec![1.0 / n as f64; n]
+        };
+        // each slot holds 1 / n of the probability, from its own item and one alias filling it up.
+        let mut scaled: Vec<f64> = probabilities.iter().map(|p| p * n as f64).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) = (0..n).partition(|&i| scaled[i] < 1.0);
+        let mut keep = vec![1.0; n];
+        let mut aliases: Vec<usize> = (0..n).collect();
+        while let (Some(&less), Some(&more)) = (small.last(), large.last()) {
+            small.pop();
+            keep[less] = scaled[less];
+            aliases[less] = more;
+            scaled[more] -= 1.0 - scaled[less];
+            if scaled[more] < 1.0 {
+                large.pop();
+                small.push(more);
+            }
+        }
+        // whatever is left over is 1 up to rounding.
+        AliasTable{keep, aliases, probabilities}
+    }
+
+    pub fn len(&self) -> usize {
+        self.probabilities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.probabilities.is_empty()
+    }
+
+    /// the item for `u` uniform in 0..1.
+    pub fn sample(&self, u: f64) -> usize {
+        let scaled = u * self.len() as f64;
+        let slot = (scaled as usize).min(self.len() - 1);
+        if scaled - (slot as f64) < self.keep[slot] { slot } else { self.aliases[slot] }
+    }
+
+    /// the chance of `sample` picking `item`.
+    pub fn pdf(&self, item: usize) -> f64 {
+        self.probabilities[item]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use super::*;
+
+    #[test]
+    fn picks_in_proportion() {
+        let weights = [1.0, 0.0, 5.0, 2.0, 0.5, 1.5];
+        let table = AliasTable::new(&weights);
+        let n = 100_000;
+        let mut counts = [0; 6];
+        for i in 0..n {
+            counts[table.sample((i as f64 + 0.5) / n as f64)] += 1;
+        }
+        for (i, weight) in weights.iter().enumerate() {
+            assert_float_absolute_eq!(table.pdf(i), weight / 10.0, 1e-12);
+            assert_float_absolute_eq!(counts[i] as f64 / n as f64, weight / 10.0, 1e-3);
+        }
+    }
+
+    #[test]
+    fn degenerate_weights() {
+        let uniform = AliasTable::new(&[0.0, 0.0, 0.0, 0.0]);
+        assert_eq!((0..4).map(|i| uniform.sample(i as f64 / 4.0 + 0.1)).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+        let single = AliasTable::new(&[3.0]);
+        assert_eq!((single.sample(0.0), single.sample(0.999), single.pdf(0)), (0, 0, 1.0));
+        // right at the top end.
+        assert_eq!(AliasTable::new(&[1.0, 1.0]).sample(1.0), 1);
+    }
+}
@@ -0,0 +1,530 @@
+use crate::{bsdf::{Bsdf, Frame}, light::power_heuristic, medium::{PhaseFunction, Scattering, SubsurfaceWalk}, ray::{Ray, MAX_WALK_STEPS, SHADOW_GAP}, scene::Scene, spectrum::ColorMode, vec3::Vec3};
+
+#[derive(Debug, Clone)]
+enum Kind {
+    /// the pinhole.
+    Camera,
+    /// where a light subpath starts, with the radiance the light gives off there.
+    Light{light: usize, emission: Vec3<f64>},
+    Surface{bsdf: Bsdf, frame: Frame, emission: Vec3<f64>, object: usize},
+    Medium{albedo: Vec3<f64>, phase: PhaseFunction},
+}
+
+/// a point of a camera or light subpath.
+#[derive(Debug, Clone)]
+struct Vertex {
+    kind: Kind,
+    position: Vec3<f64>,
+    /// the geometric normal, zero in a medium.
+    normal: Vec3<f64>,
+    /// the unit direction back along the path, toward the vertex before.
+    incoming: Vec3<f64>,
+    /// what the subpath brings up to here, over the density it was sampled with.
+    throughput: Vec3<f64>,
+    /// per unit area, the density the subpath got here with and the other one's coming back.
+    pdf_forward: f64,
+    pdf_reverse: f64,
+    /// the path went on from here by a specular bounce, nothing can be joined to it there.
+    delta: bool,
+    /// reached by a walk through a subsurface object, not a segment subpaths could be joined by.
+    walked: bool,
+    disperses: bool,
+}
+
+impl Vertex {
+    fn new(kind: Kind, position: Vec3<f64>, normal: Vec3<f64>, throughput: Vec3<f64>) -> Self {
+        let zero = Vec3::new(0., 0., 0.);
+        Vertex{kind, position, normal, incoming: zero, throughput, pdf_forward: 0.0, pdf_reverse: 0.0, delta: false, walked: false, disperses: false}
+    }
+
+    // whether the other subpath can be joined here, which specular bsdfs rule out.
+    fn connectible(&self) -> bool {
+        match &self.kind {
+            Kind::Surface{bsdf, ..} => !bsdf.is_specular(),
+            _ => true,
+        }
+    }
+
+    // which of the scene's lights this is on.
+    fn light(&self, scene: &Scene) -> Option<usize> {
+        match &self.kind {
+            Kind::Light{light, ..} => Some(*light),
+            Kind::Surface{object, ..} => scene.lights().by_object(*object),
+            _ => None,
+        }
+    }
+
+    // the bsdf or phase function for light arriving from `toward_light` and leaving toward `toward_eye`, no cosines.
+    fn scatter(&self, toward_eye: &Vec3<f64>, toward_light: &Vec3<f64>) -> Vec3<f64> {
+        match &self.kind {
+            Kind::Surface{bsdf, frame, ..} => {
+                let (wo, wi) = (frame.to_local(toward_eye), frame.to_local(toward_light));
+                // the geometric surface decides which side either is on, whatever the shading normal says.
+                if self.normal.dot(toward_eye) * wo.z <= 0.0 || self.normal.dot(toward_light) * wi.z <= 0.0 {
+                    return Vec3::new(0., 0., 0.);
+                }
+                bsdf.evaluate(&wo, &wi).scale(1.0 / wi.z.abs())
+            }
+            Kind::Medium{albedo, phase} => albedo.clone().scale(phase.evaluate(&-*toward_light, toward_eye)),
+            Kind::Camera | Kind::Light{..} => Vec3::new(0., 0., 0.),
+        }
+    }
+
+    // the radiance given off toward `direction`.
+    fn emitted(&self, scene: &Scene, direction: &Vec3<f64>) -> Vec3<f64> {
+        match &self.kind {
+            Kind::Light{light, emission} if scene.lights().direction_pdf(*light, &self.position, direction) > 0.0 => *emission,
+            Kind::Surface{emission, ..} => *emission,
+            _ => Vec3::new(0., 0., 0.),
+        }
+    }
+
+    // the cosine going into the measure of light leaving or arriving along the unit `direction`.
+    fn cosine(&self, direction: &Vec3<f64>) -> f64 {
+        match &self.kind {
+            Kind::Surface{frame, ..} => frame.to_local(direction).z.abs(),
+            Kind::Light{..} => self.normal.dot(direction).abs(),
+            Kind::Camera | Kind::Medium{..} => 1.0,
+        }
+    }
+
+    // the density of going on in the unit `direction` from here, per unit solid angle, having come from
+    // `toward_previous`.
+    fn direction_pdf(&self, scene: &Scene, toward_previous: Option<&Vec3<f64>>, direction: &Vec3<f64>) -> f64 {
+        match (&self.kind, toward_previous) {
+            (Kind::Camera, _) => scene.camera.direction_pdf(direction),
+            (Kind::Light{light, ..}, _) => scene.lights().direction_pdf(*light, &self.position, direction),
+            (Kind::Surface{bsdf, frame, ..}, Some(previous)) => bsdf.pdf(&frame.to_local(previous), &frame.to_local(direction)),
+            (Kind::Medium{phase, ..}, Some(previous)) => phase.evaluate(&-*previous, direction),
+            _ => 0.0,
+        }
+    }
+
+    // as `direction_pdf`, per unit area at `next`.
+    fn pdf(&self, scene: &Scene, toward_previous: Option<&Vec3<f64>>, next: &Vertex) -> f64 {
+        let direction = (next.position - self.position).normalize();
+        to_area(self.direction_pdf(scene, toward_previous, &direction), &self.position, next)
+    }
+
+    // the density of a light subpath starting here, per unit area.
+    fn pdf_light_origin(&self, scene: &Scene) -> f64 {
+        self.light(scene).map_or(0.0, |light| scene.lights().position_pdf(light))
+    }
+
+    // the density of a light subpath starting here going on to `next`, per unit area.
+    fn pdf_light(&self, scene: &Scene, next: &Vertex) -> f64 {
+        let Some(light) = self.light(scene) else {
+            return 0.0;
+        };
+        let direction = (next.position - self.position).normalize();
+        to_area(scene.lights().direction_pdf(light, &self.position, &direction), &self.position, next)
+    }
+}
+
+// a density per unit solid angle at `from`, as one per unit area at `to`.
+fn to_area(pdf: f64, from: &Vec3<f64>, to: &Vertex) -> f64 {
+    let offset = to.position - *from;
+    let distance_squared = offset.length_squared();
+    if distance_squared == 0.0 {
+        return 0.0;
+    }
+    let cos = if to.normal == Vec3::new(0., 0., 0.) { 1.0 } else { to.normal.dot(&offset).abs() / distance_squared.sqrt() };
+    pdf * cos / distance_squared
+}
+
+// how much light gets from `origin` to `distance` along the unit `direction`.
+fn visibility(scene: &Scene, origin: &Vec3<f64>, direction: &Vec3<f64>, distance: f64, time: f64, t_min: f64) -> f64 {
+    // stopping just short of the other end.
+    let distance = distance * (1.0 - SHADOW_GAP);
+    let mut visibility = scene.transmittance(&Ray{origin: *origin, direction: *direction, time}, t_min, distance);
+    if let Some(fog) = &scene.fog {
+        visibility *= fog.transmittance(origin, direction, distance);
+    }
+    visibility
+}
+
+// what a path through `vertices` owes to dispersion, only the hero wavelength makes it past a dispersive surface.
+fn dispersion<'a>(mode: ColorMode, mut vertices: impl Iterator<Item = &'a Vertex>) -> Vec3<f64> {
+    let mut mode = mode;
+    if vertices.any(|vertex| vertex.disperses) { mode.terminate_secondary() } else { Vec3::new(1., 1., 1.) }
+}
+
+// extends `path` from its last vertex along `ray`, picked with density `pdf` per unit solid angle, until it
+// has `max_vertices` or ends. returns the direction the path left the scene in, the density it was picked
+// with, zero where light sampling couldn't have found what's out there, and what the path brings from there.
+#[allow(clippy::too_many_arguments)]
+fn extend(scene: &Scene, mode: &ColorMode, mut ray: Ray, mut throughput: Vec3<f64>, mut pdf: f64, t_min: f64, max_vertices: usize, from_light: bool, path: &mut Vec<Vertex>) -> Option<(Vec3<f64>, f64, Vec3<f64>)> {
+    // the subsurface scattering object the path is inside of.
+    let mut subsurface: Option<SubsurfaceWalk> = None;
+    let mut rng = rand::thread_rng();
+    loop {
+        let mut hit = scene.hit(&ray, t_min, f64::INFINITY);
+        let mut walked = false;
+        if let Some(walk) = &subsurface {
+            // the walk inside makes no vertices, the path goes on from where it comes out.
+            let mut steps = 0;
+            while let Some(medium) = walk.medium() {
+                let (weight, scattered) = medium.step(&ray, hit.map_or(f64::INFINITY, |hit_return| hit_return.t), &mut rng);
+                throughput = throughput * weight;
+                let Some(t) = scattered else {
+                    break;
+                };
+                steps += 1;
+                if steps >= MAX_WALK_STEPS {
+                    return None;
+                }
+                let direction = medium.phase.sample(&ray.direction.normalize(), &mut rng);
+                ray = Ray{origin: ray.origin + ray.direction.clone().scale(t), direction, time: ray.time};
+                walked = true;
+                hit = scene.hit(&ray, t_min, f64::INFINITY);
+            }
+        } else if let Some(fog) = &scene.fog {
+            let t_surface = hit.map_or(f64::INFINITY, |hit_return| hit_return.t);
+            if let Some(t) = fog.sample_scatter(&ray, t_min, t_surface, &mut rng) {
+                hit = Some(Scattering{albedo: fog.albedo, phase: fog.phase}.hit_at(&ray, t));
+            }
+        }
+        let Some(hit_return) = hit else {
+            return Some((ray.direction.normalize(), if walked { 0.0 } else { pdf }, throughput));
+        };
+
+        let incoming = -ray.direction.normalize();
+        let material = scene.material(hit_return.material);
+        let mut vertex = match hit_return.medium {
+            Some(Scattering{albedo, phase}) => Vertex::new(Kind::Medium{albedo: mode.color(&albedo), phase}, hit_return.hit_position, Vec3::new(0., 0., 0.), throughput),
+            None => {
+                let frame = Frame::new(material.shading_normal(&hit_return), hit_return.tangent);
+                let emission = mode.color(&material.emission.evaluate(hit_return.uv, &hit_return.hit_position));
+                let kind = Kind::Surface{bsdf: material.bsdf(&hit_return, mode), frame, emission, object: hit_return.object_id};
+                Vertex{disperses: material.disperses(), ..Vertex::new(kind, hit_return.hit_position, hit_return.normal, throughput)}
+            }
+        };
+        vertex.incoming = incoming;
+        vertex.walked = walked;
+        vertex.pdf_forward = if walked { 0.0 } else { to_area(pdf, &ray.origin, &vertex) };
+        path.push(vertex);
+        if path.len() >= max_vertices {
+            return None;
+        }
+
+        let vertex = &path[path.len() - 1];
+        // where the path goes next, with the weight and the densities of going there and of coming back.
+        let (direction, weight, next_pdf, reverse_pdf, specular) = match &vertex.kind {
+            Kind::Medium{albedo, phase} => {
+                let direction = phase.sample(&-incoming, &mut rng);
+                let pdf = phase.evaluate(&-incoming, &direction);
+                (direction, *albedo, pdf, pdf, false)
+            }
+            Kind::Surface{bsdf, frame, ..} => {
+                let wo = frame.to_local(&incoming);
+                let sample = bsdf.sample(&wo, &mut rng)?;
+                let direction = frame.to_world(&sample.direction);
+                // sent across the geometric surface by a bent shading normal.
+                if direction.dot(&hit_return.normal) * sample.direction.z <= 0.0 {
+                    return None;
+                }
+                let weight = if from_light { sample.weight.clone().scale(bsdf.adjoint_scale(&wo, &sample.direction)) } else { sample.weight };
+                subsurface = SubsurfaceWalk::cross(subsurface.take(), &hit_return, &direction, material, mode);
+                (direction, weight, sample.pdf, bsdf.pdf(&sample.direction, &wo), sample.specular)
+            }
+            Kind::Camera | Kind::Light{..} => return None,
+        };
+        throughput = throughput * weight;
+        let (next_pdf, reverse_pdf) = if specular { (0.0, 0.0) } else { (next_pdf, reverse_pdf) };
+        let n = path.len();
+        path[n - 1].delta = specular;
+        if !walked {
+            path[n - 2].pdf_reverse = to_area(reverse_pdf, &hit_return.hit_position, &path[n - 2]);
+        }
+        if throughput == Vec3::new(0., 0., 0.) {
+            return None;
+        }
+        pdf = next_pdf;
+        ray = Ray{origin: hit_return.hit_position, direction, time: ray.time};
+    }
+}
+
+/// bidirectional path tracing (veach 1997, chapter 10), light subpaths' hits on other pixels go to `splat`.
+pub fn radiance(scene: &Scene, ray: &Ray, mode: ColorMode, t_min: f64, max_depth: u32, splat: &mut dyn FnMut(usize, Vec3<f64>)) -> Vec3<f64> {
+    let lights = scene.lights();
+    let max_depth = max_depth as usize;
+    let direction = ray.direction.normalize();
+    let mut camera_path = vec![Vertex::new(Kind::Camera, ray.origin, scene.camera.z_axis, Vec3::new(1., 1., 1.))];
+    let camera_ray = Ray{origin: ray.origin, direction, time: ray.time};
+    let escaped = extend(scene, &mode, camera_ray, Vec3::new(1., 1., 1.), scene.camera.direction_pdf(&direction), t_min, max_depth + 2, false, &mut camera_path);
+
+    let mut radiance = Vec3::new(0., 0., 0.);
+    if let Some((direction, pdf, throughput)) = escaped {
+        let sun = scene.sky().sun_radiance(&direction);
+        // the sun was sampled from wherever the path scattered last, not from the camera.
+        let weight = if camera_path.len() > 1 && pdf > 0.0 && !lights.is_empty() { power_heuristic(pdf, lights.sun_pdf(scene, &direction)) } else { 1.0 };
+        radiance = throughput * mode.color(&(scene.sky().radiance(&direction) + sun.clone().scale(weight))) * dispersion(mode, camera_path.iter());
+    }
+
+    let mut light_path = vec![];
+    if let Some(emission) = lights.sample_emission(scene, ray.time, &mut rand::thread_rng()) {
+        let throughput = Vec3::new(1., 1., 1.).scale(1.0 / emission.position_pdf);
+        let mut light = Vertex::new(Kind::Light{light: emission.light, emission: mode.color(&emission.radiance)}, emission.position, emission.normal, throughput);
+        light.pdf_forward = emission.position_pdf;
+        light_path.push(light);
+        let cos = emission.normal.dot(&emission.direction);
+        let throughput = mode.color(&emission.radiance).scale(cos / (emission.position_pdf * emission.direction_pdf));
+        let light_ray = Ray{origin: emission.position, direction: emission.direction, time: ray.time};
+        extend(scene, &mode, light_ray, throughput, emission.direction_pdf, t_min, max_depth + 1, true, &mut light_path);
+    }
+
+    for t in 1..=camera_path.len() {
+        // light sampling needs no light subpath, there is none when the sun was picked for it.
+        for s in 0..=light_path.len().max(1) {
+            if s + t < 2 || s + t - 2 > max_depth {
+                continue;
+            }
+            if t == 1 {
+                if s > light_path.len() {
+                    continue;
+                }
+                if let Some((pixel, contribution)) = light_tracing(scene, mode, &mut camera_path, &mut light_path, s, ray.time, t_min) {
+                    splat(pixel, contribution);
+                }
+                continue;
+            }
+            radiance = radiance + connect(scene, mode, &mut camera_path, &mut light_path, s, t, ray.time, t_min);
+        }
+    }
+    mode.to_rgb(&radiance)
+}
+
+// the first `s` vertices of the light subpath joined to the first `t` of the camera subpath, for `t` of at
+// least 2. with `s` 0 the camera subpath ran into a light itself, with 1 a light is sampled for it anew.
+#[allow(clippy::too_many_arguments)]
+fn connect(scene: &Scene, mode: ColorMode, camera_path: &mut [Vertex], light_path: &mut [Vertex], s: usize, t: usize, time: f64, t_min: f64) -> Vec3<f64> {
+    let zero = Vec3::new(0., 0., 0.);
+    let pt = &camera_path[t - 1];
+    let mut sampled = None;
+    let contribution = match s {
+        0 => {
+            let emission = pt.emitted(scene, &pt.incoming);
+            if emission == zero {
+                return zero;
+            }
+            pt.throughput * emission
+        }
+        1 => {
+            if !pt.connectible() {
+                return zero;
+            }
+            let Some(light) = scene.lights().sample(scene, &pt.position, time, &mut rand::thread_rng()) else {
+                return zero;
+            };
+            let f = pt.scatter(&pt.incoming, &light.direction).scale(pt.cosine(&light.direction));
+            if f == zero {
+                return zero;
+            }
+            let visibility = visibility(scene, &pt.position, &light.direction, light.distance, time, t_min);
+            let contribution = (pt.throughput * f * mode.color(&light.radiance)).scale(visibility / light.pdf);
+            if light.distance.is_infinite() {
+                // the sun, weighed against the camera subpath running into it as `Ray::color` does.
+                let pdf = pt.direction_pdf(scene, Some(&pt.incoming), &light.direction);
+                let weight = if light.delta { 1.0 } else { power_heuristic(light.pdf, pdf) };
+                return (contribution * dispersion(mode, camera_path[..t].iter())).scale(weight);
+            }
+            let position = pt.position + light.direction.clone().scale(light.distance);
+            let normal = scene.lights().normal(light.light, &position);
+            let mut vertex = Vertex::new(Kind::Light{light: light.light, emission: mode.color(&light.radiance)}, position, normal, zero);
+            vertex.pdf_forward = scene.lights().position_pdf(light.light);
+            sampled = Some(vertex);
+            contribution
+        }
+        _ => {
+            let qs = &light_path[s - 1];
+            if !pt.connectible() || !qs.connectible() {
+                return zero;
+            }
+            let offset = pt.position - qs.position;
+            let distance = offset.length();
+            let direction = offset.clone().scale(1.0 / distance);
+            let f = pt.scatter(&pt.incoming, &-direction).scale(pt.cosine(&direction)) * qs.scatter(&direction, &qs.incoming).scale(qs.cosine(&direction));
+            if f == zero {
+                return zero;
+            }
+            let visibility = visibility(scene, &qs.position, &direction, distance, time, t_min);
+            (qs.throughput * f * pt.throughput).scale(visibility / (distance * distance))
+        }
+    };
+    if contribution == zero {
+        return zero;
+    }
+    let light = match &mut sampled {
+        Some(vertex) => std::slice::from_mut(vertex),
+        None => &mut light_path[..s],
+    };
+    let weight = mis_weight(scene, &mut camera_path[..t], light);
+    (contribution * dispersion(mode, camera_path[..t].iter().chain(&light_path[..s.min(light_path.len())]))).scale(weight)
+}
+
+// the first `s` vertices of the light subpath seen by the camera directly, as the pixel it lands on and what it brings there.
+fn light_tracing(scene: &Scene, mode: ColorMode, camera_path: &mut [Vertex], light_path: &mut [Vertex], s: usize, time: f64, t_min: f64) -> Option<(usize, Vec3<f64>)> {
+    let camera = &scene.camera;
+    let qs = &light_path[s - 1];
+    if !qs.connectible() {
+        return None;
+    }
+    let offset = camera.position - qs.position;
+    let distance = offset.length();
+    let direction = offset.clone().scale(1.0 / distance);
+    let (u, v) = camera.film_position(&-direction)?;
+    let f = if s == 1 { qs.emitted(scene, &direction) } else { qs.scatter(&direction, &qs.incoming) }.scale(qs.cosine(&direction));
+    if f == Vec3::new(0., 0., 0.) {
+        return None;
+    }
+    let visibility = visibility(scene, &qs.position, &direction, distance, time, t_min);
+    if visibility == 0.0 {
+        return None;
+    }
+    // the camera's importance, spread over the whole film since any pixel could have been the one.
+    let importance = 1.0 / (camera.film_area() * (-direction).dot(&camera.z_axis).powi(3) * distance * distance);
+    let weight = mis_weight(scene, &mut camera_path[..1], &mut light_path[..s]);
+    let contribution = (light_path[s - 1].throughput * f * dispersion(mode, light_path[..s].iter())).scale(importance * visibility * weight);
+    let (width, height) = (camera.window_width, camera.window_height);
+    let x = (((u + 1.0) / 2.0 * width as f64) as usize).min(width - 1);
+    let row = (((v + 1.0) / 2.0 * height as f64) as usize).min(height - 1);
+    Some((row * width + x, mode.to_rgb(&contribution)))
+}
+
+// the power heuristic's weight for the path joining the `s` light vertices to the `t` camera ones, from the
+// ratios of the densities every other way of joining subpaths would have found it with (veach 1997, 10.2).
+// the subpaths get the densities around the new segment just while the ratios are summed, what was there
+// before is put back after as every other join shares them.
+fn mis_weight(scene: &Scene, camera: &mut [Vertex], light: &mut [Vertex]) -> f64 {
+    let (s, t) = (light.len(), camera.len());
+    // what isn't one of the lights could only have been found by running into it.
+    if s == 0 && camera[t - 1].light(scene).is_none() {
+        return 1.0;
+    }
+
+    // the densities around the new segment, which neither subpath had.
+    let pt = &camera[t - 1];
+    let qs = light.last();
+    let pt_reverse = match qs {
+        Some(qs) => qs.pdf(scene, (s > 1).then_some(&qs.incoming), pt),
+        None => pt.pdf_light_origin(scene),
+    };
+    let before_pt_reverse = (t > 1).then(|| match qs {
+        _ if pt.walked => 0.0,
+        Some(qs) => pt.pdf(scene, Some(&(qs.position - pt.position).normalize()), &camera[t - 2]),
+        None => pt.pdf_light(scene, &camera[t - 2]),
+    });
+    let qs_reverse = qs.map(|qs| pt.pdf(scene, (t > 1).then_some(&pt.incoming), qs));
+    let before_qs_reverse = qs.filter(|_| s > 1).map(|qs| if qs.walked { 0.0 } else { qs.pdf(scene, Some(&(pt.position - qs.position).normalize()), &light[s - 2]) });
+
+    let saved_camera = (
+        std::mem::replace(&mut camera[t - 1].pdf_reverse, pt_reverse),
+        std::mem::replace(&mut camera[t - 1].delta, false),
+        before_pt_reverse.map(|pdf| std::mem::replace(&mut camera[t - 2].pdf_reverse, pdf)),
+    );
+    let saved_light = qs_reverse.map(|pdf| (
+        std::mem::replace(&mut light[s - 1].pdf_reverse, pdf),
+        std::mem::replace(&mut light[s - 1].delta, false),
+        before_qs_reverse.map(|pdf| std::mem::replace(&mut light[s - 2].pdf_reverse, pdf)),
+    ));
+
+    let weight = 1.0 / (1.0 + ratio_sum(camera, light));
+
+    (camera[t - 1].pdf_reverse, camera[t - 1].delta) = (saved_camera.0, saved_camera.1);
+    if let Some(pdf) = saved_camera.2 {
+        camera[t - 2].pdf_reverse = pdf;
+    }
+    if let Some((pdf, delta, before)) = saved_light {
+        (light[s - 1].pdf_reverse, light[s - 1].delta) = (pdf, delta);
+        if let Some(pdf) = before {
+            light[s - 2].pdf_reverse = pdf;
+        }
+    }
+    weight
+}
+
+// the squared density ratios of every other join, with the new segment's densities in place.
+fn ratio_sum(camera: &[Vertex], light: &[Vertex]) -> f64 {
+    // specular vertices have no density, the ratios step over them.
+    let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+    let mut sum = 0.0;
+    let mut ratio = 1.0;
+    for i in (1..camera.len()).rev() {
+        ratio *= (remap(camera[i].pdf_reverse) / remap(camera[i].pdf_forward)).powi(2);
+        if !camera[i].delta && !camera[i - 1].delta && !camera[i].walked {
+            sum += ratio;
+        }
+    }
+    ratio = 1.0;
+    for i in (0..light.len()).rev() {
+        ratio *= (remap(light[i].pdf_reverse) / remap(light[i].pdf_forward)).powi(2);
+        let delta_before = i > 0 && light[i - 1].delta;
+        if !light[i].delta && !delta_before && !light[i].walked {
+            sum += ratio;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bsdf::Metal, hittable::Sphere, material::Material, quad::Quad, scene::{Integrator, Object}, sky::Sky};
+    use super::*;
+
+    // a lamp over a floor with a metal ball and a glass one on it, in the dark.
+    fn lamp_lit(integrator: Integrator) -> Scene {
+        let mut scene = Scene::new(16, 9);
+        scene.set_sky(Sky::Flat{color: Vec3::new(0., 0., 0.), sun_direction: Vec3::new(0., 1., 0.), sun_irradiance: 0.0});
+        let floor = scene.add_material(Material::diffuse(Vec3::new(0.5, 0.5, 0.5)));
+        let metal = scene.add_material(Material::conductor(Metal::Gold, 0.3));
+        let lamp = scene.add_material(Material::emissive(Vec3::new(8., 8., 8.)));
+        scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(-20., -1., -20.), u: Vec3::new(0., 0., 40.), v: Vec3::new(40., 0., 0.), material: floor})));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.8, center: Vec3::new(0.5, -0.2, 0.), material: metal}));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.3, center: Vec3::new(-0.6, 0.6, 1.), material: lamp}));
+        let glass = scene.add_material(Material::dielectric(1.5, 0.0));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.4, center: Vec3::new(-0.6, -0.3, 1.5), material: glass}));
+        scene.integrator = integrator;
+        scene.max_depth = 3;
+        scene
+    }
+
+    fn mean(image: &[Vec3<f64>]) -> f64 {
+        image.iter().map(|color| color.y).sum::<f64>() / image.len() as f64
+    }
+
+    #[test]
+    fn agrees_with_the_path_tracer() {
+        // over the whole image, light tracing puts what it finds anywhere. the path tracer's rays go through
+        // the same random points of the pixels, a low resolution render is all aliasing otherwise.
+        let mut scene = lamp_lit(Integrator::Bidirectional);
+        scene.samples_per_pixel = 256;
+        let image = scene.render_linear();
+        let camera = &scene.camera;
+        let n = 400_000;
+        let reference = (0..n).map(|_| {
+            let direction = camera.direction_at(rand::random::<f64>() * 2.0 - 1.0, rand::random::<f64>() * 2.0 - 1.0);
+            Ray{origin: camera.position, direction, time: 0.0}.color(&scene, ColorMode::Rgb, 0.01, f64::INFINITY, scene.max_depth).y
+        }).sum::<f64>() / n as f64;
+        let color = mean(&image);
+        assert!((color / reference - 1.0).abs() < 0.03, "{color} against {reference}");
+        // the lamp itself is in view, partly found by light tracing.
+        assert!(image.iter().any(|color| color.y > 4.0));
+    }
+
+    #[test]
+    fn splats_land_where_the_camera_sees() {
+        // light subpath vertices go to the pixel whose camera ray passes through them.
+        let scene = lamp_lit(Integrator::Bidirectional);
+        let camera = &scene.camera;
+        let (width, height) = (camera.window_width, camera.window_height);
+        for (pixel, direction) in [(0, camera.direction_at(-0.99, -0.99)), (width * height - 1, camera.direction_at(0.99, 0.99)), (4 * width + 8, camera.direction_at(0.03, 0.01))] {
+            let (u, v) = camera.film_position(&direction.clone().scale(3.0)).unwrap();
+            let x = ((u + 1.0) / 2.0 * width as f64) as usize;
+            let row = ((v + 1.0) / 2.0 * height as f64) as usize;
+            assert_eq!(row * width + x, pixel);
+        }
+        // and the pixel with index 0 is the top left one the path tracer fills in first.
+        assert_eq!(camera.ray_directions[(height - 1) * width], camera.direction_at(-1.0, -1.0));
+        assert!(camera.direction_at(-1.0, -1.0).y > 0.0);
+    }
+}
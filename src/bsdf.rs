@@ -0,0 +1,671 @@
+use std::f64::consts::PI;
+
+use num::Complex;
+use rand::Rng;
+
+use crate::{principled::PrincipledBsdf, thin_film::Coated, vec3::Vec3};
+
+/// an orthonormal basis around a shading normal, which is z in it with the tangent along x.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    pub tangent: Vec3<f64>,
+    pub bitangent: Vec3<f64>,
+    pub normal: Vec3<f64>,
+}
+
+impl Frame {
+    /// `tangent` needn't be perpendicular to `normal`, only not parallel.
+    pub fn new(normal: Vec3<f64>, tangent: Vec3<f64>) -> Self {
+        let tangent = tangent - normal.clone().scale(normal.dot(&tangent));
+        let tangent = if tangent.length_squared() > 1e-12 { tangent.normalize() } else { normal.orthonormal_basis().0 };
+        Frame{tangent, bitangent: normal.cross(&tangent), normal}
+    }
+
+    pub fn to_local(self, v: &Vec3<f64>) -> Vec3<f64> {
+        Vec3::new(v.dot(&self.tangent), v.dot(&self.bitangent), v.dot(&self.normal))
+    }
+
+    pub fn to_world(self, v: &Vec3<f64>) -> Vec3<f64> {
+        self.tangent.clone().scale(v.x) + self.bitangent.clone().scale(v.y) + self.normal.clone().scale(v.z)
+    }
+}
+
+pub struct BsdfSample {
+    /// local, pointing away from the surface.
+    pub direction: Vec3<f64>,
+    /// bsdf times cosine over pdf, what the path throughput gets multiplied by.
+    pub weight: Vec3<f64>,
+    pub pdf: f64,
+    /// a mirror or smooth glass bounce, `pdf` is meaningless and `evaluate` never sees this direction.
+    pub specular: bool,
+}
+
+pub fn same_hemisphere(a: &Vec3<f64>, b: &Vec3<f64>) -> bool {
+    a.z * b.z > 0.0
+}
+
+pub fn reflect(wo: &Vec3<f64>, normal: &Vec3<f64>) -> Vec3<f64> {
+    normal.clone().scale(2.0 * wo.dot(normal)) - *wo
+}
+
+/// bends `wo` through an interface of relative `eta`, with the eta used. `None` on total internal reflection.
+pub fn refract(wo: &Vec3<f64>, normal: &Vec3<f64>, eta: f64) -> Option<(Vec3<f64>, f64)> {
+    let (mut normal, mut eta, mut cos_i) = (*normal, eta, wo.dot(normal));
+    if cos_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_i = -cos_i;
+        normal = -normal;
+    }
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((wo.clone().scale(-1.0 / eta) + normal.clone().scale(cos_i / eta - cos_t), eta))
+}
+
+/// unpolarized fresnel reflectance of a dielectric interface, `eta` as in `refract`.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let (mut cos_i, mut eta) = (cos_i.clamp(-1.0, 1.0), eta);
+    if cos_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_i = -cos_i;
+    }
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).max(0.0).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+/// the same for a conductor with complex index of refraction `eta + i k`, coming from air.
+pub fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let eta = Complex::new(eta, k);
+    let sin2_t = Complex::new(1.0 - cos_i * cos_i, 0.0) / (eta * eta);
+    let cos_t = (Complex::new(1.0, 0.0) - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (-eta * cos_t + cos_i) / (eta * cos_t + cos_i);
+    (parallel.norm_sqr() + perpendicular.norm_sqr()) / 2.0
+}
+
+fn fresnel_conductor_rgb(cos_i: f64, eta: &Vec3<f64>, k: &Vec3<f64>) -> Vec3<f64> {
+    Vec3::new(
+        fresnel_conductor(cos_i, eta.x, k.x),
+        fresnel_conductor(cos_i, eta.y, k.y),
+        fresnel_conductor(cos_i, eta.z, k.z),
+    )
+}
+
+// rougher than this in neither direction and a surface counts as perfectly smooth.
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+/// the trowbridge-reitz (ggx) microfacet distribution, with the height correlated smith masking that goes with it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    /// from artists' roughness. single scattering only, so rough surfaces lose some light.
+    pub fn from_roughness(roughness: f64) -> Self {
+        let alpha = roughness * roughness;
+        Ggx{alpha_x: alpha, alpha_y: alpha}
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    /// density of microfacet normals `h`.
+    pub fn d(&self, h: &Vec3<f64>) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let e = (h.x / self.alpha_x).powi(2) + (h.y / self.alpha_y).powi(2) + h.z * h.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    fn lambda(&self, w: &Vec3<f64>) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = ((self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2)) / cos2;
+        ((1.0 + tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// the fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: &Vec3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// the fraction visible from both directions, correlated through the height of the microfacet.
+    pub fn g(&self, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// density of the microfacet normals visible from `w`.
+    pub fn visible_d(&self, w: &Vec3<f64>, h: &Vec3<f64>) -> f64 {
+        self.g1(w) / w.z.abs() * self.d(h) * w.dot(h).abs()
+    }
+
+    /// samples `visible_d` for `w` (heitz 2018), the result always has z >= 0.
+    pub fn sample_visible(&self, w: &Vec3<f64>, u: (f64, f64)) -> Vec3<f64> {
+        // stretch to the hemisphere configuration.
+        let mut wh = Vec3::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z).normalize();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+        let length2 = wh.x * wh.x + wh.y * wh.y;
+        let t1 = if length2 > 0.0 { Vec3::new(-wh.y, wh.x, 0.0).scale(1.0 / length2.sqrt()) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2 = wh.cross(&t1);
+
+        // a uniform point on the disk, squeezed onto the part of it the projected hemisphere covers.
+        let r = u.0.sqrt();
+        let phi = 2.0 * PI * u.1;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + wh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        let nh = t1.clone().scale(p1) + t2.clone().scale(p2) + wh.clone().scale(p3);
+
+        // and back.
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize()
+    }
+}
+
+/// how light scatters off a surface point, in the shading frame with `wo` toward the viewer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bsdf {
+    Lambertian{albedo: Vec3<f64>},
+    /// a metal with complex index of refraction `eta + i k` per channel, tinted by `tint`.
+    Conductor{distribution: Ggx, eta: Vec3<f64>, k: Vec3<f64>, tint: Vec3<f64>},
+    /// glass and the like, `eta` is below the surface over above it. `tint` colors what is transmitted.
+    Dielectric{distribution: Ggx, eta: f64, tint: Vec3<f64>},
+    /// a subsurface object's boundary, glossy fresnel reflection and the rest going through diffusely.
+    Subsurface{distribution: Ggx, eta: f64},
+    Principled(PrincipledBsdf),
+    /// any of the others under a thin film.
+    Coated(Box<Coated>),
+}
+
+impl Bsdf {
+    /// the bsdf times the cosine of `wi`. zero for specular bsdfs, which only `sample` knows about.
+    pub fn evaluate(&self, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Vec3<f64> {
+        match self {
+            Bsdf::Lambertian{albedo} => {
+                if !same_hemisphere(wo, wi) {
+                    return Vec3::new(0., 0., 0.);
+                }
+                albedo.clone().scale(wi.z.abs() / PI)
+            }
+            Bsdf::Conductor{distribution, eta, k, tint} => {
+                if distribution.is_smooth() {
+                    return Vec3::new(0., 0., 0.);
+                }
+                let Some((wm, specular)) = microfacet_reflection(distribution, wo, wi) else {
+                    return Vec3::new(0., 0., 0.);
+                };
+                (fresnel_conductor_rgb(wo.dot(&wm).abs(), eta, k) * *tint).scale(specular)
+            }
+            Bsdf::Dielectric{distribution, eta, tint} => {
+                if distribution.is_smooth() || wo.z == 0.0 || wi.z == 0.0 {
+                    return Vec3::new(0., 0., 0.);
+                }
+                let Some((wm, _)) = dielectric_half_vector(wo, wi, *eta) else {
+                    return Vec3::new(0., 0., 0.);
+                };
+                let fresnel = fresnel_dielectric(wo.dot(&wm), *eta);
+                if same_hemisphere(wo, wi) {
+                    let f = distribution.d(&wm) * distribution.g(wo, wi) * fresnel / (4.0 * wo.z.abs());
+                    Vec3::new(f, f, f)
+                } else {
+                    let f = microfacet_transmission(distribution, *eta, wo, wi).map_or(0.0, |(f, _)| f);
+                    tint.clone().scale(f * (1.0 - fresnel))
+                }
+            }
+            Bsdf::Subsurface{distribution, eta} => {
+                if same_hemisphere(wo, wi) {
+                    let Some((wm, specular)) = microfacet_reflection(distribution, wo, wi) else {
+                        return Vec3::new(0., 0., 0.);
+                    };
+                    let f = fresnel_dielectric(wo.dot(&wm).abs() * wo.z.signum(), *eta) * specular;
+                    Vec3::new(f, f, f)
+                } else {
+                    let f = (1.0 - fresnel_dielectric(wo.z, *eta)) * wi.z.abs() / PI;
+                    Vec3::new(f, f, f)
+                }
+            }
+            Bsdf::Principled(principled) => principled.evaluate(wo, wi),
+            Bsdf::Coated(coated) => coated.evaluate(wo, wi),
+        }
+    }
+
+    /// the density `sample` picks `wi` with, per unit solid angle. zero for specular bsdfs.
+    pub fn pdf(&self, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        match self {
+            Bsdf::Lambertian{..} => cosine_pdf(wo, wi),
+            Bsdf::Conductor{distribution, ..} => {
+                if distribution.is_smooth() {
+                    return 0.0;
+                }
+                reflection_pdf(distribution, wo, wi)
+            }
+            Bsdf::Dielectric{distribution, eta, ..} => {
+                if distribution.is_smooth() || wo.z == 0.0 || wi.z == 0.0 {
+                    return 0.0;
+                }
+                let Some((wm, _)) = dielectric_half_vector(wo, wi, *eta) else {
+                    return 0.0;
+                };
+                let reflectance = fresnel_dielectric(wo.dot(&wm), *eta);
+                if same_hemisphere(wo, wi) {
+                    distribution.visible_d(wo, &wm) / (4.0 * wo.dot(&wm).abs()) * reflectance
+                } else {
+                    microfacet_transmission(distribution, *eta, wo, wi).map_or(0.0, |(_, pdf)| pdf) * (1.0 - reflectance)
+                }
+            }
+            Bsdf::Subsurface{distribution, eta} => {
+                let reflectance = fresnel_dielectric(wo.z, *eta);
+                if same_hemisphere(wo, wi) {
+                    reflectance * reflection_pdf(distribution, wo, wi)
+                } else {
+                    (1.0 - reflectance) * wi.z.abs() / PI
+                }
+            }
+            Bsdf::Principled(principled) => principled.pdf(wo, wi),
+            Bsdf::Coated(coated) => coated.pdf(wo, wi),
+        }
+    }
+
+    /// picks a `wi` for `wo`, `None` if the path should end here.
+    pub fn sample(&self, wo: &Vec3<f64>, rng: &mut impl Rng) -> Option<BsdfSample> {
+        if wo.z == 0.0 {
+            return None;
+        }
+        let direction = match self {
+            Bsdf::Lambertian{..} => sample_cosine(wo, (rng.gen(), rng.gen())),
+            Bsdf::Conductor{distribution, eta, k, tint} => {
+                if distribution.is_smooth() {
+                    let wi = Vec3::new(-wo.x, -wo.y, wo.z);
+                    let weight = fresnel_conductor_rgb(wo.z.abs(), eta, k) * *tint;
+                    return Some(BsdfSample{direction: wi, weight, pdf: 1.0, specular: true});
+                }
+                sample_reflection(distribution, wo, (rng.gen(), rng.gen()))
+            }
+            Bsdf::Dielectric{distribution, eta, tint} => {
+                let wm = if distribution.is_smooth() {
+                    Vec3::new(0., 0., 1.)
+                } else {
+                    distribution.sample_visible(wo, (rng.gen(), rng.gen()))
+                };
+                let reflectance = fresnel_dielectric(wo.dot(&wm), *eta);
+                let reflecting = rng.gen::<f64>() < reflectance;
+                let direction = if reflecting { reflect(wo, &wm) } else { refract(wo, &wm, *eta)?.0 };
+                // a rough microfacet can send either across the surface, where the pdf means the other one.
+                if reflecting != same_hemisphere(wo, &direction) {
+                    return None;
+                }
+                if distribution.is_smooth() {
+                    // the fresnel weight and the chance of picking this branch cancel.
+                    let weight = if same_hemisphere(wo, &direction) {
+                        Vec3::new(1., 1., 1.)
+                    } else {
+                        let etap = if wo.z > 0.0 { *eta } else { 1.0 / eta };
+                        tint.clone().scale(1.0 / (etap * etap))
+                    };
+                    return Some(BsdfSample{direction, weight, pdf: 1.0, specular: true});
+                }
+                direction
+            }
+            Bsdf::Subsurface{distribution, eta} => {
+                if rng.gen::<f64>() < fresnel_dielectric(wo.z, *eta) {
+                    sample_reflection(distribution, wo, (rng.gen(), rng.gen()))
+                } else {
+                    -sample_cosine(wo, (rng.gen(), rng.gen()))
+                }
+            }
+            Bsdf::Principled(principled) => return principled.sample(wo, rng),
+            Bsdf::Coated(coated) => return coated.sample(wo, rng),
+        };
+        let pdf = self.pdf(wo, &direction);
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return None;
+        }
+        let weight = self.evaluate(wo, &direction).scale(1.0 / pdf);
+        Some(BsdfSample{direction, weight, pdf, specular: false})
+    }
+
+    /// what `sample`'s weight is off by for paths from the lights, flux isn't squeezed (veach 1997, 5.2).
+    pub fn adjoint_scale(&self, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        if same_hemisphere(wo, wi) {
+            return 1.0;
+        }
+        let eta = match self {
+            Bsdf::Dielectric{eta, ..} => *eta,
+            Bsdf::Principled(principled) => principled.eta(),
+            Bsdf::Coated(coated) => return coated.base.adjoint_scale(wo, wi),
+            _ => return 1.0,
+        };
+        let etap = if wo.z > 0.0 { eta } else { 1.0 / eta };
+        etap * etap
+    }
+
+    /// every direction a smooth surface sends light from `wo` on in, with its weight. none for rough ones.
+    pub fn specular_directions(&self, wo: &Vec3<f64>) -> Vec<(Vec3<f64>, Vec3<f64>)> {
+        if wo.z == 0.0 {
+            return vec![];
+        }
+        let mirrored = Vec3::new(-wo.x, -wo.y, wo.z);
+        let mut directions = match self {
+            Bsdf::Conductor{distribution, eta, k, tint} if distribution.is_smooth() => vec![(mirrored, fresnel_conductor_rgb(wo.z.abs(), eta, k) * *tint)],
+            Bsdf::Dielectric{distribution, eta, tint} if distribution.is_smooth() => {
+                let reflectance = fresnel_dielectric(wo.z, *eta);
+                let mut directions = vec![(mirrored, Vec3::new(reflectance, reflectance, reflectance))];
+                if let Some((direction, etap)) = refract(wo, &Vec3::new(0., 0., 1.), *eta) {
+                    directions.push((direction, tint.clone().scale((1.0 - reflectance) / (etap * etap))));
+                }
+                directions
+            }
+            Bsdf::Coated(coated) => coated.specular_directions(wo),
+            _ => vec![],
+        };
+        directions.retain(|(_, weight)| *weight != Vec3::new(0., 0., 0.));
+        directions
+    }
+
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Bsdf::Lambertian{..} | Bsdf::Subsurface{..} | Bsdf::Principled(_) => false,
+            Bsdf::Conductor{distribution, ..} | Bsdf::Dielectric{distribution, ..} => distribution.is_smooth(),
+            Bsdf::Coated(coated) => coated.base.is_specular(),
+        }
+    }
+}
+
+/// cosine weighted on `wo`'s side.
+pub fn sample_cosine(wo: &Vec3<f64>, u: (f64, f64)) -> Vec3<f64> {
+    let (r, phi) = (u.0.sqrt(), 2.0 * PI * u.1);
+    let z = (1.0 - r * r).max(0.0).sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), if wo.z > 0.0 { z } else { -z })
+}
+
+pub fn cosine_pdf(wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+    if !same_hemisphere(wo, wi) {
+        return 0.0;
+    }
+    wi.z.abs() / PI
+}
+
+// microfacet reflection is the same from either side of the surface, these work from above.
+fn upper(w: &Vec3<f64>) -> Vec3<f64> {
+    Vec3::new(w.x, w.y, w.z.abs())
+}
+
+/// reflects `wo` off a visible microfacet, which may send it below the surface.
+pub fn sample_reflection(distribution: &Ggx, wo: &Vec3<f64>, u: (f64, f64)) -> Vec3<f64> {
+    let wm = distribution.sample_visible(&upper(wo), u);
+    let wi = reflect(&upper(wo), &wm);
+    if wo.z < 0.0 { Vec3::new(wi.x, wi.y, -wi.z) } else { wi }
+}
+
+pub fn reflection_pdf(distribution: &Ggx, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+    if !same_hemisphere(wo, wi) {
+        return 0.0;
+    }
+    let (wo, wi) = (upper(wo), upper(wi));
+    let Some(wm) = half_vector(&wo, &wi, 1.0) else {
+        return 0.0;
+    };
+    distribution.visible_d(&wo, &wm) / (4.0 * wo.dot(&wm).abs())
+}
+
+/// the microfacet normal reflecting `wo` into `wi` and d g / (4 cos_o), the lobe without fresnel.
+pub fn microfacet_reflection(distribution: &Ggx, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Option<(Vec3<f64>, f64)> {
+    if !same_hemisphere(wo, wi) {
+        return None;
+    }
+    let (wo, wi) = (upper(wo), upper(wi));
+    let wm = half_vector(&wo, &wi, 1.0)?;
+    Some((wm, distribution.d(&wm) * distribution.g(&wo, &wi) / (4.0 * wo.z)))
+}
+
+/// a rough dielectric's refraction lobe without fresnel, and the density of sampling it.
+pub fn microfacet_transmission(distribution: &Ggx, eta: f64, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Option<(f64, f64)> {
+    if same_hemisphere(wo, wi) || wo.z == 0.0 || wi.z == 0.0 {
+        return None;
+    }
+    let (wm, etap) = dielectric_half_vector(wo, wi, eta)?;
+    let denominator = (wi.dot(&wm) + wo.dot(&wm) / etap).powi(2);
+    // radiance gets squeezed into a smaller solid angle going into the denser side.
+    let f = distribution.d(&wm) * distribution.g(wo, wi) * (wi.dot(&wm) * wo.dot(&wm) / (wo.z * denominator)).abs() / (etap * etap);
+    Some((f, distribution.visible_d(wo, &wm) * wi.dot(&wm).abs() / denominator))
+}
+
+// microfacet normal reflecting `wo` into `wi`, turned to the outside.
+fn half_vector(wo: &Vec3<f64>, wi: &Vec3<f64>, etap: f64) -> Option<Vec3<f64>> {
+    let wm = wi.clone().scale(etap) + *wo;
+    if wm.length_squared() == 0.0 {
+        return None;
+    }
+    let wm = wm.normalize();
+    Some(if wm.z < 0.0 { -wm } else { wm })
+}
+
+// the generalized half vector for reflection or refraction, and the relative eta across it.
+// `None` for configurations no microfacet can produce.
+fn dielectric_half_vector(wo: &Vec3<f64>, wi: &Vec3<f64>, eta: f64) -> Option<(Vec3<f64>, f64)> {
+    let etap = if same_hemisphere(wo, wi) { 1.0 } else if wo.z > 0.0 { eta } else { 1.0 / eta };
+    let wm = half_vector(wo, wi, etap)?;
+    // backfacing microfacets.
+    if wm.dot(wi) * wi.z < 0.0 || wm.dot(wo) * wo.z < 0.0 {
+        return None;
+    }
+    Some((wm, etap))
+}
+
+/// measured complex indices of refraction of common metals at roughly 650, 550 and 450nm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metal {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl Metal {
+    /// (eta, k)
+    pub fn ior(&self) -> (Vec3<f64>, Vec3<f64>) {
+        match self {
+            Metal::Gold => (Vec3::new(0.143, 0.374, 1.442), Vec3::new(3.983, 2.385, 1.603)),
+            Metal::Copper => (Vec3::new(0.200, 0.924, 1.102), Vec3::new(3.912, 2.452, 2.142)),
+            Metal::Aluminium => (Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.270, 4.837)),
+            Metal::Silver => (Vec3::new(0.155, 0.117, 0.138), Vec3::new(4.828, 3.122, 2.147)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use assert_float_eq::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use super::*;
+
+    // four standard errors of a mean of weights between 0 and 1 stay within the 1e-2 tolerances.
+    const SAMPLES: usize = 40_000;
+
+    type Region = fn(&Vec3<f64>) -> bool;
+
+    fn outgoing(theta_degrees: f64) -> Vec3<f64> {
+        let theta = theta_degrees.to_radians();
+        Vec3::new(theta.sin(), 0.0, theta.cos())
+    }
+
+    fn white_conductor(roughness: f64) -> Bsdf {
+        // a huge extinction coefficient reflects everything.
+        Bsdf::Conductor{distribution: Ggx::from_roughness(roughness), eta: Vec3::new(1., 1., 1.), k: Vec3::new(1e4, 1e4, 1e4), tint: Vec3::new(1., 1., 1.)}
+    }
+
+    fn glass(roughness: f64) -> Bsdf {
+        Bsdf::Dielectric{distribution: Ggx::from_roughness(roughness), eta: 1.5, tint: Vec3::new(1., 1., 1.)}
+    }
+
+    /// midpoints of a grid equal in area over the sphere, each cell covering `4 pi / n^2`.
+    pub fn sphere_grid(n: usize) -> impl Iterator<Item = Vec3<f64>> {
+        (0..n * n).map(move |i| {
+            let z = -1.0 + 2.0 * ((i / n) as f64 + 0.5) / n as f64;
+            let phi = 2.0 * PI * ((i % n) as f64 + 0.5) / n as f64;
+            let r = (1.0 - z * z).sqrt();
+            Vec3::new(r * phi.cos(), r * phi.sin(), z)
+        })
+    }
+
+    #[test]
+    fn fresnel() {
+        assert_float_absolute_eq!(fresnel_dielectric(1.0, 1.5), 0.04, 1e-12);
+        // leaving glass past the critical angle.
+        assert_eq!(fresnel_dielectric(-0.5, 1.5), 1.0);
+        // a conductor without extinction is a dielectric.
+        assert_float_absolute_eq!(fresnel_conductor(0.6, 1.5, 0.0), fresnel_dielectric(0.6, 1.5), 1e-12);
+        let (eta, k) = Metal::Gold.ior();
+        let normal = ((eta.x - 1.0).powi(2) + k.x * k.x) / ((eta.x + 1.0).powi(2) + k.x * k.x);
+        assert_float_absolute_eq!(fresnel_conductor(1.0, eta.x, k.x), normal, 1e-12);
+        // gold is yellow.
+        let f = fresnel_conductor_rgb(1.0, &eta, &k);
+        assert!(f.x > f.y && f.y > f.z);
+        assert_float_absolute_eq!(fresnel_conductor(0.0, eta.x, k.x), 1.0, 1e-9);
+    }
+
+    #[test]
+    fn refraction_follows_snell() {
+        let wo = outgoing(30.0);
+        let (wi, etap) = refract(&wo, &Vec3::new(0., 0., 1.), 1.5).unwrap();
+        assert_eq!(etap, 1.5);
+        assert!(wi.z < 0.0);
+        assert_float_absolute_eq!((-wi.x).atan2(-wi.z).sin() * 1.5, 30f64.to_radians().sin(), 1e-12);
+        assert!(refract(&Vec3::new(0.9, 0., -(1.0f64 - 0.81).sqrt()), &Vec3::new(0., 0., 1.), 1.5).is_none());
+    }
+
+    #[test]
+    fn specular_directions_carry_light() {
+        let wo = outgoing(30.0);
+        let glass = Bsdf::Dielectric{distribution: Ggx::from_roughness(0.0), eta: 1.5, tint: Vec3::new(1., 1., 1.)};
+        assert_eq!(glass.specular_directions(&wo).len(), 2);
+        // glass matching its surroundings reflects nothing, only the way straight through is left.
+        let unseen = Bsdf::Dielectric{distribution: Ggx::from_roughness(0.0), eta: 1.0, tint: Vec3::new(1., 1., 1.)};
+        let directions = unseen.specular_directions(&wo);
+        assert_eq!(directions.len(), 1);
+        assert_eq!(directions[0].0, -wo);
+    }
+
+    #[test]
+    fn white_furnace() {
+        // a perfectly reflecting rough metal loses the light that bounces between microfacets more than once,
+        // more the rougher it is, but never gains any.
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut albedo = |roughness: f64, theta: f64| {
+            let bsdf = white_conductor(roughness);
+            let wo = outgoing(theta);
+            (0..SAMPLES).filter_map(|_| bsdf.sample(&wo, &mut rng)).map(|sample| sample.weight.x).sum::<f64>() / SAMPLES as f64
+        };
+        for theta in [0.0f64, 45.0, 80.0] {
+            // at alpha 1 the normals are uniform, d = 1 / pi, and with g = 2 cos_o cos_i / (cos_o + cos_i)
+            // single scattering keeps the integral of cos_i / (cos_o + cos_i) over the cosine, in closed form.
+            let cos_o = theta.to_radians().cos();
+            let expected = 1.0 - cos_o * ((1.0 + cos_o) / cos_o).ln();
+            let rough = albedo(1.0, theta);
+            assert!((rough - expected).abs() < 1e-2, "roughness 1 at {theta} reflects {rough}, not {expected}");
+            // everything when almost smooth, and less the rougher in between.
+            let mut last = albedo(0.05, theta);
+            assert_float_absolute_eq!(last, 1.0, 1e-2);
+            for roughness in [0.3, 0.7, 1.0] {
+                let next = albedo(roughness, theta);
+                assert!(next <= last + 1e-2, "roughness {roughness} at {theta} reflects {next}, more than {last}");
+                last = next;
+            }
+        }
+    }
+
+    #[test]
+    fn glass_conserves_energy() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for roughness in [0.1, 0.5] {
+            for wo in [outgoing(20.0), outgoing(70.0), -outgoing(20.0)] {
+                let bsdf = glass(roughness);
+                // undo the radiance compression to count energy.
+                let total: f64 = (0..SAMPLES).filter_map(|_| bsdf.sample(&wo, &mut rng)).map(|sample| {
+                    let etap = if same_hemisphere(&wo, &sample.direction) { 1.0 } else if wo.z > 0.0 { 1.5 } else { 1.0 / 1.5 };
+                    sample.weight.x * etap * etap
+                }).sum();
+                let albedo = total / SAMPLES as f64;
+                assert!(albedo <= 1.0 + 1e-2 && albedo > 0.6, "roughness {roughness} from {wo:?} keeps {albedo}");
+            }
+        }
+    }
+
+    #[test]
+    fn sampling_matches_pdf() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // the share of samples landing in a region has to equal the pdf integrated over it.
+        let wo = outgoing(40.0);
+        let regions: [(Bsdf, Region); 4] = [
+            (white_conductor(0.5), |w| w.z > 0.8),
+            (white_conductor(0.3), |w| w.x < 0.0 && w.z > 0.0),
+            (glass(0.4), |w| w.z < -0.7),
+            (glass(0.4), |w| w.z > 0.0),
+        ];
+        for (bsdf, inside) in regions {
+            let sampled = (0..SAMPLES).filter_map(|_| bsdf.sample(&wo, &mut rng)).filter(|sample| inside(&sample.direction)).count() as f64 / SAMPLES as f64;
+            let integrated = sphere_grid(1000).filter(inside).map(|w| bsdf.pdf(&wo, &w)).sum::<f64>() * 4.0 * PI / 1e6;
+            assert_float_absolute_eq!(sampled, integrated, 1.5e-2);
+        }
+    }
+
+    #[test]
+    fn subsurface_boundary() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let boundary = Bsdf::Subsurface{distribution: Ggx::from_roughness(0.3), eta: 1.4};
+        for wo in [outgoing(10.0), outgoing(75.0)] {
+            let total: f64 = (0..SAMPLES).filter_map(|_| boundary.sample(&wo, &mut rng)).map(|sample| sample.weight.x).sum();
+            let albedo = total / SAMPLES as f64;
+            assert!(albedo <= 1.0 + 1e-2 && albedo > 0.9, "the boundary keeps {albedo} from {wo:?}");
+            let entering = (0..SAMPLES).filter_map(|_| boundary.sample(&wo, &mut rng)).filter(|sample| sample.direction.z < 0.0).count() as f64 / SAMPLES as f64;
+            assert_float_absolute_eq!(entering, 1.0 - fresnel_dielectric(wo.z, 1.4), 1e-2);
+            let integrated = sphere_grid(1000).map(|w| boundary.pdf(&wo, &w)).sum::<f64>() * 4.0 * PI / 1e6;
+            assert_float_absolute_eq!(integrated, 1.0, 2e-2);
+        }
+    }
+
+    #[test]
+    fn reciprocity() {
+        let (eta, k) = Metal::Copper.ior();
+        let copper = Bsdf::Conductor{distribution: Ggx{alpha_x: 0.2, alpha_y: 0.5}, eta, k, tint: Vec3::new(1., 1., 1.)};
+        let (a, b) = (outgoing(20.0), Vec3::new(-0.3, 0.4, 0.8).normalize());
+        let ab = copper.evaluate(&a, &b).scale(1.0 / b.z);
+        let ba = copper.evaluate(&b, &a).scale(1.0 / a.z);
+        assert!((ab - ba).length() < 1e-12);
+        assert!(ab.x > 0.0);
+    }
+
+    #[test]
+    fn smooth_surfaces_are_specular() {
+        let (eta, k) = Metal::Silver.ior();
+        let mirror = Bsdf::Conductor{distribution: Ggx::from_roughness(0.0), eta, k, tint: Vec3::new(1., 1., 1.)};
+        let wo = outgoing(30.0);
+        let mut rng = StdRng::seed_from_u64(0);
+        let sample = mirror.sample(&wo, &mut rng).unwrap();
+        assert!(sample.specular && mirror.is_specular());
+        assert!((sample.direction - Vec3::new(-wo.x, 0., wo.z)).length() < 1e-12);
+        assert_eq!(mirror.evaluate(&wo, &sample.direction), Vec3::new(0., 0., 0.));
+        assert_eq!(mirror.pdf(&wo, &sample.direction), 0.0);
+
+        // smooth glass picks reflection with the fresnel probability.
+        let reflected = (0..SAMPLES).filter_map(|_| glass(0.0).sample(&Vec3::new(0., 0., 1.), &mut rng)).filter(|sample| sample.direction.z > 0.0).count();
+        assert_float_absolute_eq!(reflected as f64 / SAMPLES as f64, 0.04, 5e-3);
+    }
+}
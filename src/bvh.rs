@@ -0,0 +1,188 @@
+use std::cell::Cell;
+
+use crate::{aabb::Aabb, hittable::{HitReturn, Hittable}, ray::Ray};
+
+enum BvhNode {
+    Leaf{bounds: Aabb, start: usize, end: usize},
+    Branch{bounds: Aabb, left: usize, right: usize},
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf{bounds, ..} | BvhNode::Branch{bounds, ..} => bounds,
+        }
+    }
+}
+
+const MAX_LEAF_SIZE: usize = 2;
+
+/// bounding volume hierarchy over hittables by index, objects without bounds are tested on every ray.
+#[derive(Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    // object indices, leaves own a contiguous range of these.
+    indices: Vec<usize>,
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new<H: Hittable>(objects: &[H]) -> Self {
+        let mut bounded = vec![];
+        let mut unbounded = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            match object.bounding_box() {
+                Some(bounds) => bounded.push((i, bounds)),
+                None => unbounded.push(i),
+            }
+        }
+        let mut bvh = Bvh{nodes: vec![], indices: Vec::with_capacity(bounded.len()), unbounded};
+        if !bounded.is_empty() {
+            bvh.build(&mut bounded);
+        }
+        bvh
+    }
+
+    // median split along the axis the centroids spread out the most on. returns the node index.
+    fn build(&mut self, items: &mut [(usize, Aabb)]) -> usize {
+        let bounds = items[1..].iter().fold(items[0].1, |bounds, (_, item)| bounds.surrounding(item));
+        if items.len() <= MAX_LEAF_SIZE {
+            let start = self.indices.len();
+            self.indices.extend(items.iter().map(|(i, _)| *i));
+            self.nodes.push(BvhNode::Leaf{bounds, start, end: self.indices.len()});
+            return self.nodes.len() - 1;
+        }
+
+        let first = items[0].1.centroid();
+        let centroids = items.iter().fold(Aabb::new(first, first), |bounds, (_, item)| {
+            let c = item.centroid();
+            bounds.surrounding(&Aabb::new(c, c))
+        });
+        let extent = centroids.max - centroids.min;
+        let key: fn(&Aabb) -> f64 = if extent.x >= extent.y && extent.x >= extent.z {
+            |bounds| bounds.centroid().x
+        } else if extent.y >= extent.z {
+            |bounds| bounds.centroid().y
+        } else {
+            |bounds| bounds.centroid().z
+        };
+        let middle = items.len() / 2;
+        items.select_nth_unstable_by(middle, |a, b| key(&a.1).total_cmp(&key(&b.1)));
+
+        // reserve our slot before the children so the root ends up first.
+        let node = self.nodes.len();
+        self.nodes.push(BvhNode::Leaf{bounds, start: 0, end: 0});
+        let (left_items, right_items) = items.split_at_mut(middle);
+        let left = self.build(left_items);
+        let right = self.build(right_items);
+        self.nodes[node] = BvhNode::Branch{bounds, left, right};
+        node
+    }
+
+    /// calls `visit` with every object whose box the ray enters before `t_max()`, which may shrink meanwhile.
+    fn traverse<H: Hittable>(&self, objects: &[H], ray: &Ray, t_min: f64, t_max: &dyn Fn() -> f64, visit: &mut dyn FnMut(usize, &H), tests: &mut u64) {
+        for i in &self.unbounded {
+            *tests += 1;
+            visit(*i, &objects[*i]);
+        }
+        if self.nodes.is_empty() {
+            return;
+        }
+        // median splits keep the tree balanced, so it is never nearly this deep.
+        let mut stack = [0; 64];
+        let mut len = 1;
+        while len > 0 {
+            len -= 1;
+            let node = &self.nodes[stack[len]];
+            *tests += 1;
+            if node.bounds().hit(ray, t_min, t_max()).is_none() {
+                continue;
+            }
+            match node {
+                BvhNode::Leaf{start, end, ..} => {
+                    for i in &self.indices[*start..*end] {
+                        *tests += 1;
+                        visit(*i, &objects[*i]);
+                    }
+                }
+                BvhNode::Branch{left, right, ..} => {
+                    stack[len] = *right;
+                    stack[len + 1] = *left;
+                    len += 2;
+                }
+            }
+        }
+    }
+
+    pub fn hit<H: Hittable>(&self, objects: &[H], ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        self.closest_hit(objects, ray, t_min, t_max, &mut 0)
+    }
+
+    /// how many bounding boxes and objects `hit` tests the ray against, for seeing where the tree does badly.
+    pub fn intersection_tests<H: Hittable>(&self, objects: &[H], ray: &Ray, t_min: f64, t_max: f64) -> u64 {
+        let mut tests = 0;
+        self.closest_hit(objects, ray, t_min, t_max, &mut tests);
+        tests
+    }
+
+    fn closest_hit<H: Hittable>(&self, objects: &[H], ray: &Ray, t_min: f64, t_max: f64, tests: &mut u64) -> Option<HitReturn> {
+        let closest = Cell::new(t_max);
+        let mut ret: Option<HitReturn> = None;
+        self.traverse(objects, ray, t_min, &|| closest.get(), &mut |i, object| {
+            if let Some(hit_return) = object.hit(ray, t_min, closest.get()) {
+                closest.set(hit_return.t);
+                ret = Some(HitReturn{object_id: i, ..hit_return});
+            }
+        }, tests);
+        ret
+    }
+
+    /// the product of what `through` says gets through each object the ray passes by.
+    pub fn transmittance<H: Hittable>(&self, objects: &[H], ray: &Ray, t_min: f64, t_max: f64, through: &dyn Fn(&H) -> f64) -> f64 {
+        let mut transmittance = 1.0;
+        self.traverse(objects, ray, t_min, &|| t_max, &mut |_, object| {
+            if transmittance > 0.0 {
+                transmittance *= through(object);
+            }
+        }, &mut 0);
+        transmittance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{hittable::Sphere, material::MaterialId, scene::Object, sdf::{Sdf, SdfObject}, vec3::Vec3};
+    use rand::Rng;
+    use super::*;
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = rand::thread_rng();
+        let mut objects: Vec<Object> = (0..200).map(|_| {
+            let center = Vec3::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+            Object::Sphere(Sphere{radius: rng.gen_range(0.1..1.0), center, material: MaterialId::default()})
+        }).collect();
+        objects.push(Object::Sdf(Box::new(SdfObject::new(Sdf::Sphere{radius: 0.5}, MaterialId::default()))));
+        let repeated = Sdf::Repeat{period: Vec3::new(40., 0., 0.), sdf: Box::new(Sdf::Sphere{radius: 0.5})};
+        objects.push(Object::Sdf(Box::new(SdfObject::new(repeated, MaterialId::default()))));
+        let bvh = Bvh::new(&objects);
+        assert_eq!(bvh.indices.len() + bvh.unbounded.len(), objects.len());
+        assert_eq!(bvh.unbounded, vec![201]);
+
+        for _ in 0..500 {
+            let ray = Ray{origin: Vec3::random().scale(30.0).shift(-15.0), direction: Vec3::random_unit_vector(), time: 0.};
+            let expected = ray.hit(&objects, 0.001, f64::INFINITY).map(|hit| (hit.t, hit.object_id));
+            let actual = bvh.hit(&objects, &ray, 0.001, f64::INFINITY).map(|hit| (hit.t, hit.object_id));
+            assert_eq!(expected, actual);
+            let expected = if expected.is_some() { 0.0 } else { 1.0 };
+            assert_eq!(bvh.transmittance(&objects, &ray, 0.001, f64::INFINITY, &|object| object.transmittance(&ray, 0.001, f64::INFINITY)), expected);
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let bvh = Bvh::new::<Object>(&[]);
+        let ray = Ray{origin: Vec3::new(0., 0., 0.), direction: Vec3::new(0., 0., 1.), time: 0.};
+        assert!(bvh.hit::<Object>(&[], &ray, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{vec3::Vec3, ray::Ray, ASPECT_RATIO, mat3::Mat3, quat::Quat};
+use rand::Rng;
+
+use crate::{vec3::Vec3, ASPECT_RATIO, quat::Quat};
 #[derive(Debug)]
 pub struct Camera {
     pub viewport_height: f64,
@@ -8,6 +10,9 @@ pub struct Camera {
     pub z_axis: Vec3<f64>,
     pub position: Vec3<f64>,
     pub ray_directions: Vec<Vec3<f64>>,
+    /// every ray gets a random time in `shutter_open..shutter_close`, equal values disable motion blur.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
 }
 
 impl Camera {
@@ -28,25 +33,73 @@ impl Camera {
             window_width,
             ray_directions,
             z_axis,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         };
         camera.calculate_ray_directions();
         camera
     }
     
     pub fn calculate_ray_directions(&mut self) {
-        let up = Vec3::new(0.0, 1.0, 0.0);
-        let right_direction = self.z_axis.cross(&up).normalize().scale(self.viewport_width/2.0);
-        let up_direction = self.z_axis.cross(&right_direction).normalize().scale(self.viewport_height/2.0);
         self.ray_directions = Vec::with_capacity(self.window_width * self.window_height);
         for y in (0..self.window_height).rev() {
             let v = y as f64 / self.window_height as f64 * 2.0 - 1.0;
             for x in 0..self.window_width {
                 let u = x as f64 / self.window_width as f64 * 2.0 - 1.0;
-                self.ray_directions.push(self.z_axis + right_direction.clone().scale(u) + up_direction.clone().scale(v));
+                self.ray_directions.push(self.direction_at(u, v));
             }
         }
     }
-    
+
+    // the film's axes, half its width and height long. the film sits one unit along `z_axis`, and its
+    // up axis points down the screen.
+    fn film_axes(&self) -> (Vec3<f64>, Vec3<f64>) {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let right_direction = self.z_axis.cross(&up).normalize().scale(self.viewport_width/2.0);
+        let up_direction = self.z_axis.cross(&right_direction).normalize().scale(self.viewport_height/2.0);
+        (right_direction, up_direction)
+    }
+
+    /// the direction through film coordinates `u` and `v`, both in -1..1.
+    pub fn direction_at(&self, u: f64, v: f64) -> Vec3<f64> {
+        let (right_direction, up_direction) = self.film_axes();
+        self.z_axis + right_direction.clone().scale(u) + up_direction.clone().scale(v)
+    }
+
+    /// where a ray leaving the camera in `direction` goes through the film, `None` if it misses it.
+    pub fn film_position(&self, direction: &Vec3<f64>) -> Option<(f64, f64)> {
+        let depth = direction.dot(&self.z_axis);
+        if depth <= 0.0 {
+            return None;
+        }
+        let on_film = direction.clone().scale(1.0 / depth) - self.z_axis;
+        let (right_direction, up_direction) = self.film_axes();
+        let u = on_film.dot(&right_direction) / right_direction.length_squared();
+        let v = on_film.dot(&up_direction) / up_direction.length_squared();
+        (u.abs() <= 1.0 && v.abs() <= 1.0).then_some((u, v))
+    }
+
+    /// the area of the film.
+    pub fn film_area(&self) -> f64 {
+        self.viewport_width * self.viewport_height
+    }
+
+    /// the density, per unit solid angle, of a uniform point on the film sending a ray along `direction`.
+    pub fn direction_pdf(&self, direction: &Vec3<f64>) -> f64 {
+        let cos_theta = direction.dot(&self.z_axis);
+        if self.film_position(direction).is_none() {
+            return 0.0;
+        }
+        1.0 / (self.film_area() * cos_theta.powi(3))
+    }
+
+    pub fn sample_time(&self) -> f64 {
+        if self.shutter_close <= self.shutter_open {
+            return self.shutter_open;
+        }
+        rand::thread_rng().gen_range(self.shutter_open..self.shutter_close)
+    }
+
     pub fn update_x_position(&mut self, x: f64) {
         let up_dir = Vec3::new(0.0, 1.0, 0.0);
         self.position = self.position + self.z_axis.cross(&up_dir).scale(x);
@@ -65,3 +118,27 @@ impl Camera {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::bsdf::tests::sphere_grid;
+    use super::*;
+
+    #[test]
+    fn film_positions() {
+        let mut camera = Camera::new(45.0, 32, 18);
+        camera.rotate(0.3, -0.2);
+        camera.calculate_ray_directions();
+        for (u, v) in [(0.0, 0.0), (-0.5, 0.9), (1.0, -1.0)] {
+            let (found_u, found_v) = camera.film_position(&camera.direction_at(u, v).normalize()).unwrap();
+            assert_float_absolute_eq!(found_u, u, 1e-9);
+            assert_float_absolute_eq!(found_v, v, 1e-9);
+        }
+        assert!(camera.film_position(&-camera.z_axis).is_none());
+        // the density of directions through the film adds up to 1.
+        let cell = 4.0 * std::f64::consts::PI / 1e6;
+        let total: f64 = sphere_grid(1000).map(|direction| camera.direction_pdf(&direction) * cell).sum();
+        assert_float_absolute_eq!(total, 1.0, 0.01);
+    }
+}
@@ -0,0 +1,117 @@
+//! checks every deterministic `Hittable` has to pass, shared by the tests of the individual shapes.
+
+use assert_float_eq::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{aabb::Aabb, hittable::{HitReturn, Hittable}, ray::Ray, vec3::Vec3};
+
+const RAYS: usize = 256;
+
+pub struct Conformance<'a> {
+    pub hittable: &'a dyn Hittable,
+    /// where rays get aimed, needn't be tight.
+    pub bounds: Aabb,
+    /// a point inside the shape, `None` for open surfaces which have no inside.
+    pub inside: Option<Vec3<f64>>,
+    /// convex shapes also get rays that pass just outside a tangent plane, which must miss.
+    pub convex: bool,
+}
+
+impl Conformance<'_> {
+    pub fn check(&self) {
+        let mut rng = StdRng::seed_from_u64(0);
+        let hits = self.check_from_outside(&mut rng);
+        assert!(hits.len() > RAYS / 8, "only {} of {RAYS} rays aimed at the shape hit it", hits.len());
+        for (ray, hit) in &hits {
+            self.check_t_bounds(ray, hit);
+            if self.convex {
+                self.check_tangent_rays(hit);
+            }
+        }
+        if let Some(inside) = self.inside {
+            self.check_from_inside(&mut rng, inside);
+        }
+    }
+
+    fn reach(&self) -> f64 {
+        (self.bounds.max - self.bounds.min).length().max(1.0)
+    }
+
+    /// rays from a sphere around the bounds, aimed at random points within them.
+    fn check_from_outside(&self, rng: &mut StdRng) -> Vec<(Ray, HitReturn)> {
+        let center = self.bounds.centroid();
+        let mut hits = vec![];
+        for i in 0..RAYS {
+            let origin = center + random_unit_vector(rng).scale(2.0 * self.reach());
+            let target = Vec3::new(
+                rng.gen_range(self.bounds.min.x..=self.bounds.max.x),
+                rng.gen_range(self.bounds.min.y..=self.bounds.max.y),
+                rng.gen_range(self.bounds.min.z..=self.bounds.max.z),
+            );
+            // vary the direction's length, t has to be measured in it.
+            let ray = Ray{origin, direction: (target - origin).scale(0.5 + (i % 4) as f64), time: 0.};
+            if let Some(hit) = self.hittable.hit(&ray, 0.001, f64::INFINITY) {
+                self.check_hit(&ray, 0.001, f64::INFINITY, &hit);
+                if self.inside.is_some() {
+                    assert!(hit.front_face, "entering a closed shape from outside must hit its front");
+                }
+                hits.push((ray, hit));
+            }
+        }
+        hits
+    }
+
+    fn check_from_inside(&self, rng: &mut StdRng, inside: Vec3<f64>) {
+        for _ in 0..RAYS {
+            let ray = Ray{origin: inside, direction: random_unit_vector(rng), time: 0.};
+            let hit = self.hittable.hit(&ray, 0.0, f64::INFINITY).expect("a ray from inside a closed shape has to leave it");
+            self.check_hit(&ray, 0.0, f64::INFINITY, &hit);
+            assert!(!hit.front_face, "leaving a closed shape must hit its back");
+        }
+    }
+
+    /// nothing lies in front of the closest hit, and nothing behind `t_min` is returned.
+    fn check_t_bounds(&self, ray: &Ray, hit: &HitReturn) {
+        let before = hit.t * (1.0 - 1e-6);
+        assert!(self.hittable.hit(ray, 0.001, before).is_none(), "hit before the closest hit at t = {}", hit.t);
+        let t_min = hit.t * (1.0 + 1e-6);
+        if let Some(next) = self.hittable.hit(ray, t_min, f64::INFINITY) {
+            self.check_hit(ray, t_min, f64::INFINITY, &next);
+        }
+    }
+
+    fn check_tangent_rays(&self, hit: &HitReturn) {
+        let offset = 1e-3 * self.reach();
+        let start = hit.hit_position - hit.tangent.clone().scale(2.0 * self.reach());
+        let grazing = Ray{origin: start + hit.normal.clone().scale(offset), direction: hit.tangent, time: 0.};
+        assert!(self.hittable.hit(&grazing, 0.0, f64::INFINITY).is_none(), "ray just outside the tangent plane at {:?} hit", hit.hit_position);
+        // right on the tangent plane anything goes, as long as it is a proper hit.
+        let touching = Ray{origin: start, direction: hit.tangent, time: 0.};
+        if let Some(touch) = self.hittable.hit(&touching, 0.0, f64::INFINITY) {
+            self.check_hit(&touching, 0.0, f64::INFINITY, &touch);
+        }
+    }
+
+    fn check_hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit: &HitReturn) {
+        assert!(hit.t.is_finite() && hit.t >= t_min && hit.t <= t_max, "t = {} outside {t_min}..{t_max}", hit.t);
+        let expected = ray.origin + ray.direction.clone().scale(hit.t);
+        let tolerance = 1e-6 * self.reach().max(ray.origin.length());
+        assert!((hit.hit_position - expected).length() <= tolerance, "hit at {:?} but the ray is at {:?} for t = {}", hit.hit_position, expected, hit.t);
+        assert_float_absolute_eq!(hit.normal.length(), 1.0, 1e-6);
+        // grazing hits may land on either side of perpendicular.
+        assert!(hit.normal.dot(&ray.direction.normalize()) <= 1e-9, "normal {:?} faces along the ray", hit.normal);
+        assert_float_absolute_eq!(hit.shading_normal.length(), 1.0, 1e-6);
+        assert!(hit.shading_normal.dot(&hit.normal) > 0.0, "shading normal {:?} is on the other side of {:?}", hit.shading_normal, hit.normal);
+        assert_float_absolute_eq!(hit.tangent.length(), 1.0, 1e-6);
+        assert_float_absolute_eq!(hit.tangent.dot(&hit.normal), 0.0, 1e-6);
+    }
+}
+
+fn random_unit_vector(rng: &mut StdRng) -> Vec3<f64> {
+    loop {
+        let v = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+        if (1e-6..1.0).contains(&v.length_squared()) {
+            return v.normalize();
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{aabb::Aabb, hittable::{HitReturn, Hittable, Interval}, ray::Ray, scene::Object};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    /// `left` with `right` carved out of it.
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// a boolean combination of two closed objects, itself closed so csg nodes nest.
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Object,
+    pub right: Object,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Object, right: Object) -> Self {
+        Csg{operation, left, right}
+    }
+}
+
+/// merges both interval lists in t order, keeping the boundaries where the combined state flips.
+fn combine(operation: CsgOperation, left: Vec<Interval>, right: Vec<Interval>) -> Vec<Interval> {
+    let mut in_left = left.first().is_some_and(|interval| interval.enter.is_none());
+    let mut in_right = right.first().is_some_and(|interval| interval.enter.is_none());
+
+    // (hit, belongs to left, entering)
+    let mut boundaries: Vec<(HitReturn, bool, bool)> = Vec::with_capacity(2 * (left.len() + right.len()));
+    for (intervals, is_left) in [(&left, true), (&right, false)] {
+        for interval in intervals {
+            boundaries.extend(interval.enter.map(|hit_return| (hit_return, is_left, true)));
+            boundaries.extend(interval.exit.map(|hit_return| (hit_return, is_left, false)));
+        }
+    }
+    boundaries.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+    let mut inside = operation.inside(in_left, in_right);
+    let mut current = Interval{enter: None, exit: None};
+    let mut result = vec![];
+    for (hit_return, is_left, entering) in boundaries {
+        if is_left {
+            in_left = entering;
+        } else {
+            in_right = entering;
+        }
+        let now_inside = operation.inside(in_left, in_right);
+        if now_inside == inside {
+            continue;
+        }
+        // hit normals always face the incoming ray so only the side of the boundary needs fixing,
+        // e.g. entering the carved out object means leaving the difference.
+        let hit_return = HitReturn{front_face: now_inside, ..hit_return};
+        if now_inside {
+            current = Interval{enter: Some(hit_return), exit: None};
+        } else {
+            result.push(Interval{exit: Some(hit_return), ..current});
+        }
+        inside = now_inside;
+    }
+    if inside {
+        result.push(current);
+    }
+    close_seams(result)
+}
+
+// boundaries closer than this are treated as the same surface.
+const COINCIDENT: f64 = 1e-9;
+
+fn coincident(a: f64, b: f64) -> bool {
+    a.is_finite() && b.is_finite() && (a - b).abs() <= COINCIDENT * a.abs().max(1.0)
+}
+
+/// drops the zero length gaps and intervals touching objects leave behind, they aren't surfaces.
+fn close_seams(intervals: Vec<Interval>) -> Vec<Interval> {
+    let mut result: Vec<Interval> = Vec::with_capacity(intervals.len());
+    for interval in intervals {
+        if coincident(interval.t_enter(), interval.t_exit()) {
+            continue;
+        }
+        match result.last_mut() {
+            Some(last) if coincident(last.t_exit(), interval.t_enter()) => last.exit = interval.exit,
+            _ => result.push(interval),
+        }
+    }
+    result
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        let first = *self.intervals(ray, t_min, t_max).first()?;
+        first.enter.or(first.exit)
+    }
+
+    fn intervals(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<Interval> {
+        let left = self.left.intervals(ray, t_min, t_max);
+        // nothing of the left object means nothing of an intersection or difference either.
+        if left.is_empty() && self.operation != CsgOperation::Union {
+            return vec![];
+        }
+        let right = self.right.intervals(ray, t_min, t_max);
+        combine(self.operation, left, right)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match self.operation {
+            CsgOperation::Union => Some(self.left.bounding_box()?.surrounding(&self.right.bounding_box()?)),
+            CsgOperation::Intersection | CsgOperation::Difference => self.left.bounding_box(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use std::sync::Arc;
+    use crate::{conformance::Conformance, hittable::Sphere, instance::Instance, mat4::Mat4, material::MaterialId, vec3::Vec3};
+    use super::*;
+
+    fn unit_sphere_at(center: Vec3<f64>) -> Object {
+        let sphere = Arc::new(Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(0., 0., 0.), material: MaterialId::default()}));
+        Object::Instance(Box::new(Instance::new(sphere, Mat4::translation(center)).unwrap()))
+    }
+
+    // two unit spheres overlapping between x = 0 and x = 1.
+    fn csg(operation: CsgOperation) -> Csg {
+        Csg::new(operation, unit_sphere_at(Vec3::new(0., 0., 0.)), unit_sphere_at(Vec3::new(1., 0., 0.)))
+    }
+
+    fn along_x() -> Ray {
+        Ray{origin: Vec3::new(-5., 0., 0.), direction: Vec3::new(1., 0., 0.), time: 0.}
+    }
+
+    fn spans(intervals: &[Interval]) -> Vec<(f64, f64)> {
+        intervals.iter().map(|interval| (interval.t_enter(), interval.t_exit())).collect()
+    }
+
+    fn assert_spans(intervals: &[Interval], expected: &[(f64, f64)]) {
+        let spans = spans(intervals);
+        assert_eq!(spans.len(), expected.len());
+        for ((enter, exit), (expected_enter, expected_exit)) in spans.into_iter().zip(expected) {
+            assert_float_absolute_eq!(enter, *expected_enter, 1e-9);
+            assert_float_absolute_eq!(exit, *expected_exit, 1e-9);
+        }
+    }
+
+    #[test]
+    fn sphere_intervals() {
+        let sphere = Sphere{radius: 1.0, center: Vec3::new(0., 0., 0.), material: MaterialId::default()};
+        assert_spans(&sphere.intervals(&along_x(), 0.001, f64::INFINITY), &[(4., 6.)]);
+        let inside = Ray{origin: Vec3::new(0., 0., 0.), direction: Vec3::new(1., 0., 0.), time: 0.};
+        let intervals = sphere.intervals(&inside, 0.001, f64::INFINITY);
+        assert!(intervals[0].enter.is_none());
+        assert_float_absolute_eq!(intervals[0].t_exit(), 1.0, 1e-9);
+    }
+
+    #[test]
+    fn union() {
+        let ray = along_x();
+        assert_spans(&csg(CsgOperation::Union).intervals(&ray, 0.001, f64::INFINITY), &[(4., 7.)]);
+        let hit = csg(CsgOperation::Union).hit(&ray, 4.5, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 7.0, 1e-9);
+        assert!(!hit.front_face);
+    }
+
+    #[test]
+    fn intersection() {
+        let ray = along_x();
+        assert_spans(&csg(CsgOperation::Intersection).intervals(&ray, 0.001, f64::INFINITY), &[(5., 6.)]);
+        let hit = csg(CsgOperation::Intersection).hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 5.0, 1e-9);
+        assert!(hit.front_face);
+        // the ray along y through x = -0.5 only touches the left sphere.
+        let ray = Ray{origin: Vec3::new(-0.5, 5., 0.), direction: Vec3::new(0., -1., 0.), time: 0.};
+        assert!(csg(CsgOperation::Intersection).hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn difference() {
+        let ray = along_x();
+        assert_spans(&csg(CsgOperation::Difference).intervals(&ray, 0.001, f64::INFINITY), &[(4., 5.)]);
+        // leaving the difference through the carved out sphere, the normal keeps facing the ray.
+        let hit = csg(CsgOperation::Difference).hit(&ray, 4.5, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 5.0, 1e-9);
+        assert!(!hit.front_face);
+        assert!(hit.normal.dot(&ray.direction) < 0.0);
+
+        let reversed = Csg::new(CsgOperation::Difference, csg(CsgOperation::Difference).right, csg(CsgOperation::Difference).left);
+        assert_spans(&reversed.intervals(&ray, 0.001, f64::INFINITY), &[(6., 7.)]);
+    }
+
+    #[test]
+    fn starting_inside() {
+        let ray = Ray{origin: Vec3::new(0.5, 0., 0.), direction: Vec3::new(1., 0., 0.), time: 0.};
+        let intervals = csg(CsgOperation::Intersection).intervals(&ray, 0.001, f64::INFINITY);
+        assert_eq!(intervals.len(), 1);
+        assert!(intervals[0].enter.is_none());
+        let hit = csg(CsgOperation::Intersection).hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 0.5, 1e-9);
+        assert!(!hit.front_face);
+    }
+
+    #[test]
+    fn nested() {
+        // (left - right) | right gives back the union.
+        let nested = Csg::new(
+            CsgOperation::Union,
+            Object::Csg(Box::new(csg(CsgOperation::Difference))),
+            unit_sphere_at(Vec3::new(1., 0., 0.)),
+        );
+        assert_spans(&nested.intervals(&along_x(), 0.001, f64::INFINITY), &[(4., 7.)]);
+    }
+
+    #[test]
+    fn conformance() {
+        for (operation, inside) in [
+            (CsgOperation::Union, Vec3::new(1.5, 0., 0.)),
+            (CsgOperation::Intersection, Vec3::new(0.5, 0., 0.)),
+            (CsgOperation::Difference, Vec3::new(-0.5, 0., 0.)),
+        ] {
+            let csg = csg(operation);
+            let bounds = csg.bounding_box().unwrap();
+            Conformance{hittable: &csg, bounds, inside: Some(inside), convex: false}.check();
+        }
+    }
+}
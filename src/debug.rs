@@ -0,0 +1,150 @@
+use crate::{bsdf::{sample_cosine, Frame}, ray::Ray, scene::Scene, spectrum::ColorMode, vec3::Vec3};
+
+// how far ambient occlusion looks for something in the way, in world units.
+pub const OCCLUSION_DISTANCE: f64 = 1.0;
+
+/// a false color picture of something about the scene, for finding out why it looks wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    /// the shading normal, each component taken from [-1, 1] to [0, 1].
+    Normals,
+    /// how far the camera ray got, white up close fading to black far off.
+    Depth,
+    /// how much of the hemisphere over the hit is open for `OCCLUSION_DISTANCE`, cosine weighted.
+    AmbientOcclusion,
+    /// u in red, v in green and 1 - u - v in blue, a triangle's barycentrics by default.
+    Uv,
+    /// how many times the path tracer's paths scatter, from blue for none to red for the scene's max depth.
+    Bounces,
+    /// boxes and objects tested for the camera ray, blue for none to red for four per object, log scale.
+    IntersectionTests,
+}
+
+/// from blue for 0 through green to red for 1.
+pub fn heat(t: f64) -> Vec3<f64> {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        Vec3::new(0., 2.0 * t, 1.0 - 2.0 * t)
+    } else {
+        Vec3::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.)
+    }
+}
+
+/// what `view` shows along the camera ray, as linear rgb.
+pub fn color(scene: &Scene, ray: &Ray, view: DebugView, t_min: f64, max_depth: u32) -> Vec3<f64> {
+    let black = Vec3::new(0., 0., 0.);
+    match view {
+        DebugView::Bounces => {
+            let (_, bounces) = ray.color_and_bounces(scene, ColorMode::Rgb, t_min, f64::INFINITY, max_depth);
+            return heat(bounces as f64 / max_depth.max(1) as f64);
+        }
+        DebugView::IntersectionTests => {
+            let tests = scene.intersection_tests(ray, t_min, f64::INFINITY);
+            // red for four tests per object, some objects test their own parts too.
+            let all = 4 * scene.objects().len() + 1;
+            return heat((tests as f64 + 1.0).log2() / (all as f64).log2().max(1.0));
+        }
+        _ => {}
+    }
+    let Some(hit_return) = scene.hit(ray, t_min, f64::INFINITY) else {
+        return black;
+    };
+    match view {
+        DebugView::Normals => {
+            let normal = scene.material(hit_return.material).shading_normal(&hit_return);
+            (normal + Vec3::new(1., 1., 1.)).scale(0.5)
+        }
+        DebugView::Depth => {
+            let distance = hit_return.t * ray.direction.length();
+            let shade = 1.0 / (1.0 + distance);
+            Vec3::new(shade, shade, shade)
+        }
+        DebugView::AmbientOcclusion => {
+            // scattering in a medium looks all around, not over a hemisphere.
+            if hit_return.medium.is_some() {
+                return black;
+            }
+            let frame = Frame::new(hit_return.normal, hit_return.tangent);
+            let local = sample_cosine(&Vec3::new(0., 0., 1.), (rand::random(), rand::random()));
+            let direction = frame.to_world(&local);
+            let occluder = Ray{origin: hit_return.hit_position, direction, time: ray.time};
+            let open = if scene.hit(&occluder, t_min, OCCLUSION_DISTANCE).is_some() { 0.0 } else { 1.0 };
+            Vec3::new(open, open, open)
+        }
+        DebugView::Uv => {
+            let (u, v) = hit_return.uv;
+            Vec3::new(u, v, (1.0 - u - v).max(0.0))
+        }
+        DebugView::Bounces | DebugView::IntersectionTests => black,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::{hittable::Sphere, material::{Material, MaterialId}, quad::Quad, scene::{Integrator, Object}};
+    use super::*;
+
+    // a floor with a ball on it, and a low roof over half of it.
+    fn roofed() -> Scene {
+        let mut scene = Scene::new(4, 4);
+        let floor = scene.add_material(Material::diffuse(Vec3::new(0.5, 0.5, 0.5)));
+        scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(-10., -1., -10.), u: Vec3::new(0., 0., 20.), v: Vec3::new(20., 0., 0.), material: floor})));
+        scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(0., -0.9, -10.), u: Vec3::new(0., 0., 20.), v: Vec3::new(10., 0., 0.), material: floor})));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.5, center: Vec3::new(-3., -0.5, -3.), material: MaterialId::default()}));
+        scene.update_bvh();
+        scene
+    }
+
+    fn down_at(x: f64) -> Ray {
+        Ray{origin: Vec3::new(x, 5., 0.), direction: Vec3::new(0., -1., 0.), time: 0.0}
+    }
+
+    #[test]
+    fn normals_depth_and_uvs() {
+        let scene = roofed();
+        let ball = Ray{origin: Vec3::new(-3., -0.5, -1.), direction: Vec3::new(0., 0., -1.), time: 0.0};
+        let normal = color(&scene, &ball, DebugView::Normals, 1e-6, 1);
+        assert_float_absolute_eq!(normal.x, 0.5, 1e-9);
+        assert_float_absolute_eq!(normal.z, 1.0, 1e-9);
+        assert_float_absolute_eq!(color(&scene, &ball, DebugView::Depth, 1e-6, 1).y, 1.0 / 2.5, 1e-9);
+        let sky = Ray{direction: Vec3::new(0., 0., 1.), ..ball};
+        assert_eq!(color(&scene, &sky, DebugView::Depth, 1e-6, 1), Vec3::new(0., 0., 0.));
+        let uv = color(&scene, &down_at(-5.), DebugView::Uv, 1e-6, 1);
+        assert_eq!((uv.x, uv.y), (0.5, 0.25));
+    }
+
+    #[test]
+    fn occlusion_under_the_roof() {
+        let scene = roofed();
+        let mean = |x: f64| (0..2000).map(|_| color(&scene, &down_at(x), DebugView::AmbientOcclusion, 1e-6, 1).x).sum::<f64>() / 2000.0;
+        // out in the open nothing is in the way, with the roof a tenth above only rays going off nearly
+        // flat get out.
+        assert_eq!(mean(-5.), 1.0);
+        let under = Ray{origin: Vec3::new(5., -0.95, 0.), direction: Vec3::new(0., -1., 0.), time: 0.0};
+        let covered = (0..2000).map(|_| color(&scene, &under, DebugView::AmbientOcclusion, 1e-6, 1).x).sum::<f64>() / 2000.0;
+        assert!(covered < 0.05, "{covered}");
+    }
+
+    #[test]
+    fn heatmaps() {
+        assert_eq!(heat(0.0), Vec3::new(0., 0., 1.));
+        assert_eq!(heat(0.5), Vec3::new(0., 1., 0.));
+        assert_eq!(heat(2.0), Vec3::new(1., 0., 0.));
+        let scene = roofed();
+        // straight into the sky, nothing to bounce off.
+        let up = Ray{origin: Vec3::new(0., 5., 0.), direction: Vec3::new(0., 1., 0.), time: 0.0};
+        assert_eq!(color(&scene, &up, DebugView::Bounces, 1e-6, 4), heat(0.0));
+        // down onto the floor the path bounces at least once.
+        assert!((0..100).all(|_| color(&scene, &down_at(-5.), DebugView::Bounces, 1e-6, 4) != heat(0.0)));
+        // and it is tested against more things than one missing everything.
+        assert!(color(&scene, &down_at(5.), DebugView::IntersectionTests, 1e-6, 4).x > color(&scene, &up, DebugView::IntersectionTests, 1e-6, 4).x);
+    }
+
+    #[test]
+    fn by_name() {
+        assert_eq!("ao".parse(), Ok(Integrator::Debug(DebugView::AmbientOcclusion)));
+        assert_eq!("photons".parse(), Ok(Integrator::PhotonMapping));
+        assert!("raymarching".parse::<Integrator>().is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use std::path::Path;
+
+use crate::{aabb::Aabb, hittable::{HitReturn, Hittable}, material::MaterialId, ray::Ray, triangle::intersect_triangle, vec3::Vec3};
+
+/// terrain from a grid of heights, two triangles per cell. rays walk the cells they cross in order.
+pub struct Heightfield {
+    /// number of samples along x.
+    width: usize,
+    /// number of samples along z.
+    depth: usize,
+    // world space vertex positions and normals, row major with x changing fastest.
+    vertices: Vec<Vec3<f64>>,
+    normals: Vec<Vec3<f64>>,
+    bounds: Aabb,
+    cell_size_x: f64,
+    cell_size_z: f64,
+    /// interpolate the vertex normals over each triangle instead of using the flat face normal.
+    pub smooth_normals: bool,
+    pub material: MaterialId,
+}
+
+impl Heightfield {
+    /// `heights` in 0..1 with x fastest, spanning `size` from `origin`.
+    pub fn new(width: usize, depth: usize, heights: &[f64], origin: Vec3<f64>, size: Vec3<f64>, material: MaterialId) -> Self {
+        assert!(width >= 2 && depth >= 2, "a heightfield needs at least 2x2 samples");
+        assert_eq!(heights.len(), width * depth);
+        let cell_size_x = size.x / (width - 1) as f64;
+        let cell_size_z = size.z / (depth - 1) as f64;
+
+        let vertices: Vec<Vec3<f64>> = heights.iter().enumerate().map(|(i, height)| {
+            Vec3::new(
+                origin.x + (i % width) as f64 * cell_size_x,
+                origin.y + height * size.y,
+                origin.z + (i / width) as f64 * cell_size_z,
+            )
+        }).collect();
+
+        // central differences, one sided on the border.
+        let y = |x: usize, z: usize| vertices[x + z * width].y;
+        let mut normals = Vec::with_capacity(vertices.len());
+        for z in 0..depth {
+            for x in 0..width {
+                let (x0, x1) = (x.saturating_sub(1), (x + 1).min(width - 1));
+                let (z0, z1) = (z.saturating_sub(1), (z + 1).min(depth - 1));
+                let slope_x = (y(x1, z) - y(x0, z)) / ((x1 - x0) as f64 * cell_size_x);
+                let slope_z = (y(x, z1) - y(x, z0)) / ((z1 - z0) as f64 * cell_size_z);
+                normals.push(Vec3::new(-slope_x, 1.0, -slope_z).normalize());
+            }
+        }
+
+        let (min_height, max_height) = vertices.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(v.y), max.max(v.y)));
+        let bounds = Aabb::new(
+            Vec3::new(origin.x, min_height, origin.z),
+            Vec3::new(origin.x + size.x, max_height, origin.z + size.z),
+        );
+        Heightfield{width, depth, vertices, normals, bounds, cell_size_x, cell_size_z, smooth_normals: true, material}
+    }
+
+    /// loads a grayscale heightmap at the precision it was stored with, colors become luma.
+    pub fn from_image(path: impl AsRef<Path>, origin: Vec3<f64>, size: Vec3<f64>, material: MaterialId) -> image::ImageResult<Self> {
+        let image = image::open(path)?;
+        let heights: Vec<f64> = image.to_luma32f().pixels().map(|p| p.0[0] as f64).collect();
+        Ok(Heightfield::new(image.width() as usize, image.height() as usize, &heights, origin, size, material))
+    }
+
+    pub fn bounds(&self) -> &Aabb {
+        &self.bounds
+    }
+
+    /// how many triangles the terrain is made of, primitive ids of hits are below this.
+    pub fn triangle_count(&self) -> usize {
+        2 * (self.width - 1) * (self.depth - 1)
+    }
+
+    fn vertex(&self, x: usize, z: usize) -> usize {
+        x + z * self.width
+    }
+
+    fn hit_cell(&self, ray: &Ray, x: usize, z: usize, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        let corners = [self.vertex(x, z), self.vertex(x + 1, z), self.vertex(x + 1, z + 1), self.vertex(x, z + 1)];
+        let mut closest: Option<(f64, f64, f64, usize, [usize; 3])> = None;
+        for (index, triangle) in [[corners[0], corners[2], corners[1]], [corners[0], corners[3], corners[2]]].into_iter().enumerate() {
+            let t_limit = closest.map_or(t_max, |(t, ..)| t);
+            if let Some((t, u, v)) = intersect_triangle(ray, &self.vertices[triangle[0]], &self.vertices[triangle[1]], &self.vertices[triangle[2]], t_min, t_limit) {
+                closest = Some((t, u, v, index, triangle));
+            }
+        }
+        let (t, u, v, index, triangle) = closest?;
+
+        let [p0, p1, p2] = triangle.map(|i| self.vertices[i]);
+        let face_normal = (p1 - p0).cross(&(p2 - p0)).normalize();
+        let shading_normal = if self.smooth_normals {
+            let [n0, n1, n2] = triangle.map(|i| self.normals[i]);
+            (n0.clone().scale(1.0 - u - v) + n1.clone().scale(u) + n2.clone().scale(v)).normalize()
+        } else {
+            face_normal
+        };
+        let hit_position = ray.origin + ray.direction.clone().scale(t);
+        // the surface is open, its front is the side facing up.
+        let front_face = ray.direction.dot(&face_normal) < 0.0;
+        let (normal, shading_normal) = if front_face { (face_normal, shading_normal) } else { (-face_normal, -shading_normal) };
+        // uv follows the grid, so u runs along x and the tangent is x flattened onto the surface.
+        let extent = self.bounds.max - self.bounds.min;
+        let uv = ((hit_position.x - self.bounds.min.x) / extent.x, (hit_position.z - self.bounds.min.z) / extent.z);
+        let along_x = Vec3::new(1., 0., 0.);
+        let tangent = (along_x - normal.clone().scale(normal.dot(&along_x))).normalize();
+        // across the triangle, rising as it does.
+        let dpdu = Vec3::new(1., -face_normal.x / face_normal.y, 0.).scale(extent.x);
+        let dpdv = Vec3::new(0., -face_normal.z / face_normal.y, 1.).scale(extent.z);
+        let primitive_id = 2 * (x + z * (self.width - 1)) + index;
+        Some(HitReturn{shading_normal, uv, tangent, dpdu, dpdv, primitive_id, ..HitReturn::new(hit_position, normal, t, front_face, self.material)})
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        let (t_enter, t_exit) = self.bounds.hit(ray, t_min, t_max)?;
+
+        // 2d dda over the cells under the ray, from where it enters the bounds.
+        let entry = ray.origin + ray.direction.clone().scale(t_enter);
+        let cell = |position: f64, start: f64, size: f64, count: usize| (((position - start) / size).floor().max(0.0) as usize).min(count - 2);
+        let mut x = cell(entry.x, self.bounds.min.x, self.cell_size_x, self.width);
+        let mut z = cell(entry.z, self.bounds.min.z, self.cell_size_z, self.depth);
+
+        let axis = |origin: f64, direction: f64, start: f64, size: f64, index: usize| {
+            if direction == 0.0 {
+                return (0, f64::INFINITY, f64::INFINITY);
+            }
+            let step: isize = if direction > 0.0 { 1 } else { -1 };
+            let boundary = start + (index as f64 + if direction > 0.0 { 1.0 } else { 0.0 }) * size;
+            (step, (boundary - origin) / direction, size / direction.abs())
+        };
+        let (step_x, mut t_next_x, t_delta_x) = axis(ray.origin.x, ray.direction.x, self.bounds.min.x, self.cell_size_x, x);
+        let (step_z, mut t_next_z, t_delta_z) = axis(ray.origin.z, ray.direction.z, self.bounds.min.z, self.cell_size_z, z);
+
+        let mut t_cell_enter = t_enter;
+        loop {
+            let t_cell_exit = t_next_x.min(t_next_z).min(t_exit);
+            // skip cells the ray passes entirely above.
+            let lowest_ray_y = (ray.origin.y + ray.direction.y * t_cell_enter).min(ray.origin.y + ray.direction.y * t_cell_exit);
+            let highest_terrain_y = [self.vertex(x, z), self.vertex(x + 1, z), self.vertex(x, z + 1), self.vertex(x + 1, z + 1)]
+                .iter()
+                .fold(f64::NEG_INFINITY, |max, i| max.max(self.vertices[*i].y));
+            if lowest_ray_y <= highest_terrain_y {
+                if let Some(hit_return) = self.hit_cell(ray, x, z, t_min, t_max) {
+                    return Some(hit_return);
+                }
+            }
+            if t_cell_exit >= t_exit {
+                return None;
+            }
+            t_cell_enter = t_cell_exit;
+            if t_next_x < t_next_z {
+                x = x.checked_add_signed(step_x).filter(|x| *x < self.width - 1)?;
+                t_next_x += t_delta_x;
+            } else {
+                z = z.checked_add_signed(step_z).filter(|z| *z < self.depth - 1)?;
+                t_next_z += t_delta_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::conformance::Conformance;
+    use super::*;
+
+    fn terrain(width: usize, depth: usize, height: impl Fn(f64, f64) -> f64) -> Heightfield {
+        let heights: Vec<f64> = (0..width * depth).map(|i| height((i % width) as f64 / (width - 1) as f64, (i / width) as f64 / (depth - 1) as f64)).collect();
+        Heightfield::new(width, depth, &heights, Vec3::new(-1., 0., -1.), Vec3::new(2., 1., 2.), MaterialId::default())
+    }
+
+    #[test]
+    fn flat() {
+        let flat = terrain(16, 16, |_, _| 0.5);
+        let ray = Ray{origin: Vec3::new(0.3, 5., -0.2), direction: Vec3::new(0., -1., 0.), time: 0.};
+        let hit = flat.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 4.5, 1e-9);
+        assert!((hit.normal - Vec3::new(0., 1., 0.)).length() < 1e-9);
+        assert!(hit.front_face);
+        assert_float_absolute_eq!(hit.uv.0, 0.65, 1e-9);
+        assert_float_absolute_eq!(hit.uv.1, 0.4, 1e-9);
+        assert!((hit.tangent - Vec3::new(1., 0., 0.)).length() < 1e-9);
+        assert!(hit.primitive_id < flat.triangle_count());
+
+        let from_below = Ray{origin: Vec3::new(0.3, -5., -0.2), direction: Vec3::new(0., 1., 0.), time: 0.};
+        let hit = flat.hit(&from_below, 0.001, f64::INFINITY).unwrap();
+        assert!(!hit.front_face);
+        assert!((hit.normal - Vec3::new(0., -1., 0.)).length() < 1e-9);
+    }
+
+    #[test]
+    fn grazing_ray_walks_the_grid() {
+        // a slope rising along x, hit by a ray skimming in diagonally from the low side.
+        let slope = terrain(64, 64, |x, _| x);
+        let ray = Ray{origin: Vec3::new(-3., 0.9, -3.), direction: Vec3::new(1., 0., 1.), time: 0.};
+        let hit = slope.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        // the surface is y = (x + 1) / 2, so it reaches 0.9 at x = 0.8.
+        assert_float_absolute_eq!(hit.hit_position.x, 0.8, 1e-9);
+        assert_float_absolute_eq!(hit.t, 3.8, 1e-9);
+
+        let above = Ray{origin: Vec3::new(-3., 1.1, -3.), direction: Vec3::new(1., 0., 1.), time: 0.};
+        assert!(slope.hit(&above, 0.001, f64::INFINITY).is_none());
+        let backwards = Ray{origin: Vec3::new(3., 0.9, 3.), direction: Vec3::new(-1., 0., -1.), time: 0.};
+        assert!(slope.hit(&backwards, 0.001, f64::INFINITY).is_some());
+    }
+
+    #[test]
+    fn smooth_normals() {
+        let bump = |x: f64, z: f64| 0.2 * ((x - 0.5) * std::f64::consts::PI).cos() * ((z - 0.5) * std::f64::consts::PI).cos();
+        let mut field = terrain(9, 9, bump);
+        let ray = Ray{origin: Vec3::new(0.1, 5., 0.05), direction: Vec3::new(0., -1., 0.), time: 0.};
+        let smooth = field.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        field.smooth_normals = false;
+        let flat = field.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        // smoothing only changes how the surface is shaded, the geometric normal stays the face normal.
+        assert!((smooth.normal - flat.normal).length() < 1e-12);
+        assert!((flat.shading_normal - flat.normal).length() < 1e-12);
+        let (smooth, flat) = (smooth.shading_normal, flat.shading_normal);
+        // both lean away from the top of the bump, but only the flat one is the same across the whole triangle.
+        assert!(smooth.x > 0.0 && flat.x > 0.0);
+        assert!((smooth - flat).length() > 1e-3);
+    }
+
+    #[test]
+    fn t_bounds() {
+        let flat = terrain(4, 4, |_, _| 0.5);
+        let ray = Ray{origin: Vec3::new(0.3, 5., -0.2), direction: Vec3::new(0., -1., 0.), time: 0.};
+        assert!(flat.hit(&ray, 0.001, 4.4).is_none());
+        assert!(flat.hit(&ray, 4.6, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn load_16_bit_image() {
+        let path = std::env::temp_dir().join(format!("heightfield-{}.png", std::process::id()));
+        let image = image::ImageBuffer::from_fn(4, 3, |x, _| image::Luma([x as u16 * 20000]));
+        image.save(&path).unwrap();
+        let field = Heightfield::from_image(&path, Vec3::new(0., 0., 0.), Vec3::new(3., 65535., 2.), MaterialId::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((field.width, field.depth), (4, 3));
+        // heights go through f32, which is still finer than 16 bits.
+        assert_float_absolute_eq!(field.vertices[field.vertex(3, 2)].y, 60000.0, 1e-2);
+        assert_float_absolute_eq!(field.vertices[field.vertex(1, 0)].x, 1.0, 1e-9);
+    }
+
+    #[test]
+    fn load_16_bit_color_image() {
+        // neighboring 16 bit values stay apart, even through the conversion to luma.
+        let path = std::env::temp_dir().join(format!("heightfield-rgb-{}.png", std::process::id()));
+        let image = image::ImageBuffer::from_fn(4, 3, |x, _| image::Rgb([30000 + x as u16; 3]));
+        image.save(&path).unwrap();
+        let field = Heightfield::from_image(&path, Vec3::new(0., 0., 0.), Vec3::new(3., 65535., 2.), MaterialId::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for x in 0..4 {
+            assert_float_absolute_eq!(field.vertices[field.vertex(x, 1)].y, 30000.0 + x as f64, 1e-2);
+        }
+    }
+
+    #[test]
+    fn load_8_bit_image() {
+        // 8 bit images span the same height range as 16 bit ones.
+        let path = std::env::temp_dir().join(format!("heightfield-8-{}.png", std::process::id()));
+        let image = image::ImageBuffer::from_fn(4, 3, |x, _| image::Luma([x as u8 * 85]));
+        image.save(&path).unwrap();
+        let field = Heightfield::from_image(&path, Vec3::new(0., 0., 0.), Vec3::new(3., 2., 2.), MaterialId::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((field.width, field.depth), (4, 3));
+        assert_float_absolute_eq!(field.vertices[field.vertex(0, 0)].y, 0.0, 1e-9);
+        assert_float_absolute_eq!(field.vertices[field.vertex(1, 1)].y, 2.0 / 3.0, 1e-6);
+        assert_float_absolute_eq!(field.vertices[field.vertex(3, 2)].y, 2.0, 1e-9);
+    }
+
+    #[test]
+    fn conformance() {
+        let bump = |x: f64, z: f64| 0.5 + 0.3 * (x * 7.0).sin() * (z * 5.0).cos();
+        let field = terrain(32, 32, bump);
+        Conformance{hittable: &field, bounds: *field.bounds(), inside: None, convex: false}.check();
+    }
+}
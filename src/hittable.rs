@@ -1,21 +1,179 @@
+use std::sync::Arc;
+
 use crate::vec3::Vec3;
 use crate::ray::Ray;
+use crate::aabb::Aabb;
+use crate::material::MaterialId;
+use crate::medium::Scattering;
 #[derive(Clone, Copy)]
 pub struct HitReturn {
     pub hit_position: Vec3<f64>,
+    /// the true surface normal, what offsets and visibility are decided with.
     pub normal: Vec3<f64>,
+    /// the normal lighting is computed with, e.g. interpolated from vertex normals. on the same side as `normal`.
+    pub shading_normal: Vec3<f64>,
     pub t: f64,
     pub front_face: bool,
-    pub object_color: Vec3<f64>,
+    /// surface parameterization, both in [0, 1] for the built in shapes.
+    pub uv: (f64, f64),
+    /// along increasing u where the shape has a parameterization, any perpendicular otherwise.
+    pub tangent: Vec3<f64>,
+    /// how far the hit point moves per unit u and v, `tangent` and `bitangent()` without a parameterization.
+    pub dpdu: Vec3<f64>,
+    pub dpdv: Vec3<f64>,
+    /// which part of the object was hit, e.g. the triangle of a heightfield.
+    pub primitive_id: usize,
+    /// index of the hit object in the list it was traced against, filled in by `Ray::hit` and the scene.
+    pub object_id: usize,
+    pub material: MaterialId,
+    /// set when the ray scattered inside a participating medium instead of hitting a surface.
+    pub medium: Option<Scattering>,
+}
+
+impl HitReturn {
+    /// a hit with no parameterization, shapes that have one fill in `uv` and `tangent` after.
+    pub fn new(hit_position: Vec3<f64>, normal: Vec3<f64>, t: f64, front_face: bool, material: MaterialId) -> Self {
+        let tangent = normal.orthonormal_basis().0;
+        HitReturn{
+            hit_position,
+            normal,
+            shading_normal: normal,
+            t,
+            front_face,
+            uv: (0.0, 0.0),
+            tangent,
+            dpdu: tangent,
+            dpdv: normal.cross(&tangent),
+            primitive_id: 0,
+            object_id: 0,
+            material,
+            medium: None,
+        }
+    }
+
+    pub fn bitangent(&self) -> Vec3<f64> {
+        self.normal.cross(&self.tangent)
+    }
+}
+
+/// the stretch of a ray inside a closed object, an end is `None` where it is still inside.
+#[derive(Clone, Copy)]
+pub struct Interval {
+    pub enter: Option<HitReturn>,
+    pub exit: Option<HitReturn>,
 }
+
+impl Interval {
+    pub fn t_enter(&self) -> f64 {
+        self.enter.map_or(f64::NEG_INFINITY, |hit| hit.t)
+    }
+
+    pub fn t_exit(&self) -> f64 {
+        self.exit.map_or(f64::INFINITY, |hit| hit.t)
+    }
+}
+
+// how far past a hit the next search starts when walking along a ray.
+const INTERVAL_STEP: f64 = 1e-9;
+
+/// anything a ray can hit, shapes other than the built in ones go in `Object::Custom`.
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn>;
+
+    /// every entry and exit between `t_min` and `t_max` in t order, for closed objects.
+    fn intervals(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<Interval> {
+        let mut intervals = vec![];
+        let mut current: Option<Interval> = None;
+        let mut t = t_min;
+        while let Some(hit_return) = self.hit(ray, t, t_max) {
+            match (current, hit_return.front_face) {
+                (None, true) => current = Some(Interval{enter: Some(hit_return), exit: None}),
+                (Some(interval), false) => {
+                    intervals.push(Interval{exit: Some(hit_return), ..interval});
+                    current = None;
+                }
+                // the ray started inside.
+                (None, false) if intervals.is_empty() => intervals.push(Interval{enter: None, exit: Some(hit_return)}),
+                // a grazing hit that doesn't change sides.
+                _ => {}
+            }
+            t = hit_return.t + INTERVAL_STEP * hit_return.t.abs().max(1.0);
+        }
+        intervals.extend(current);
+        intervals
+    }
+
+    /// the fraction of light that gets through between `t_min` and `t_max`, surfaces are opaque.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if self.hit(ray, t_min, t_max).is_some() { 0.0 } else { 1.0 }
+    }
+
+    /// whether light goes partly through, so shadow rays take `transmittance` as it is.
+    fn is_medium(&self) -> bool {
+        false
+    }
+
+    /// a box around everything the object can be hit at, at any time. `None` keeps it out of the bvh.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+impl<T: Hittable + ?Sized> Hittable for Box<T> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        self.as_ref().hit(ray, t_min, t_max)
+    }
+
+    fn intervals(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<Interval> {
+        self.as_ref().intervals(ray, t_min, t_max)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.as_ref().transmittance(ray, t_min, t_max)
+    }
+
+    fn is_medium(&self) -> bool {
+        self.as_ref().is_medium()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.as_ref().bounding_box()
+    }
+}
+
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        self.as_ref().hit(ray, t_min, t_max)
+    }
+
+    fn intervals(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<Interval> {
+        self.as_ref().intervals(ray, t_min, t_max)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.as_ref().transmittance(ray, t_min, t_max)
+    }
+
+    fn is_medium(&self) -> bool {
+        self.as_ref().is_medium()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.as_ref().bounding_box()
+    }
+}
+
+/// u once around the y axis from -x, counterclockwise from above. dp/du is `None` on the axis.
+fn azimuth(offset: &Vec3<f64>) -> (f64, Option<Vec3<f64>>) {
+    let u = ((-offset.z).atan2(offset.x) + std::f64::consts::PI) / (2.0 * std::f64::consts::PI);
+    let dpdu = Vec3::new(offset.z, 0.0, -offset.x).scale(2.0 * std::f64::consts::PI);
+    (u, (dpdu.length_squared() > 0.0).then_some(dpdu))
 }
 
 pub struct Sphere {
     pub radius: f64,
     pub center: Vec3<f64>,
-    pub color: Vec3<f64>,
+    pub material: MaterialId,
 }
 
 
@@ -37,13 +195,432 @@ impl Hittable for Sphere {
                     return None;
                 } 
             }
-            let hit_point = origin + ray.direction.clone().scale(root);
+            let hit_point = ray.origin + ray.direction.clone().scale(root);
             let normal = (hit_point - self.center).normalize();
-            if ray.direction.dot(&normal) > 0.0 {
-                Some(HitReturn{hit_position: hit_point, normal: -normal, front_face: false, t: root, object_color: self.color})
-            } else {
-                Some(HitReturn{hit_position: hit_point, normal: normal, front_face: true, t: root, object_color: self.color})
+            // latitude from the south pole.
+            let offset = hit_point - self.center;
+            let (u, dpdu) = azimuth(&offset);
+            let v = (-normal.y).clamp(-1.0, 1.0).acos() / std::f64::consts::PI;
+            let front_face = ray.direction.dot(&normal) <= 0.0;
+            let hit_return = HitReturn::new(hit_point, if front_face { normal } else { -normal }, root, front_face, self.material);
+            let Some(dpdu) = dpdu else {
+                return Some(HitReturn{uv: (u, v), ..hit_return});
+            };
+            let ring = dpdu.length() / (2.0 * std::f64::consts::PI);
+            let dpdv = Vec3::new(-offset.y * offset.x / ring, ring, -offset.y * offset.z / ring).scale(std::f64::consts::PI);
+            Some(HitReturn{uv: (u, v), tangent: dpdu.normalize(), dpdu, dpdv, ..hit_return})
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+}
+
+pub struct Torus {
+    pub center: Vec3<f64>,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material: MaterialId,
+}
+
+impl Hittable for Torus {
+    // the torus lies in the xz plane around `center`, its axis of revolution is y.
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        let length = ray.direction.length();
+        let direction = ray.direction.normalize();
+        let mut origin = ray.origin - self.center;
+
+        // reject rays that miss the bounding sphere, and move the origin to where the ray enters it
+        // so the quartic coefficients stay small no matter how far away the camera is.
+        let bounding_radius = self.major_radius + self.minor_radius;
+        let b = origin.dot(&direction);
+        let c = origin.dot(&origin) - bounding_radius * bounding_radius;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let shift = (-b - discriminant.sqrt()).max(0.0);
+        origin = origin + direction.clone().scale(shift);
+
+        let r2 = self.major_radius * self.major_radius;
+        let e = origin.dot(&direction);
+        let k = origin.dot(&origin) + r2 - self.minor_radius * self.minor_radius;
+        let roots = solve_quartic(
+            1.0,
+            4.0 * e,
+            4.0 * e * e + 2.0 * k - 4.0 * r2 * (direction.x * direction.x + direction.z * direction.z),
+            4.0 * e * k - 8.0 * r2 * (origin.x * direction.x + origin.z * direction.z),
+            k * k - 4.0 * r2 * (origin.x * origin.x + origin.z * origin.z),
+        );
+
+        let root = roots
+            .into_iter()
+            .map(|s| (s + shift) / length)
+            .find(|t| *t >= t_min && *t <= t_max)?;
+
+        let hit_point = ray.origin + ray.direction.clone().scale(root);
+        let local = hit_point - self.center;
+        let ring = Vec3::new(local.x, 0.0, local.z).normalize().scale(self.major_radius);
+        let normal = (local - ring).normalize();
+        // u around the axis, v around the tube starting on its outer side.
+        let (u, dpdu) = azimuth(&local);
+        let radial = Vec3::new(local.x, 0.0, local.z).length() - self.major_radius;
+        let v = (local.y.atan2(-radial) + std::f64::consts::PI) / (2.0 * std::f64::consts::PI);
+        let front_face = ray.direction.dot(&normal) <= 0.0;
+        let hit_return = HitReturn::new(hit_point, if front_face { normal } else { -normal }, root, front_face, self.material);
+        let Some(dpdu) = dpdu else {
+            return Some(HitReturn{uv: (u, v), ..hit_return});
+        };
+        let dpdv = (ring.normalize().scale(local.y) - Vec3::new(0., radial, 0.)).scale(2.0 * std::f64::consts::PI);
+        Some(HitReturn{uv: (u, v), tangent: dpdu.normalize(), dpdu, dpdv, ..hit_return})
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let reach = self.major_radius + self.minor_radius;
+        let extent = Vec3::new(reach, self.minor_radius, reach);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+}
+
+/// real roots of `a*x^2 + b*x + c`, sorted ascending.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return if b == 0.0 { vec![] } else { vec![-c / b] };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+    if discriminant == 0.0 {
+        return vec![-b / (2.0 * a)];
+    }
+    // avoids the cancellation of -b + sqrt(discriminant) when b is large.
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (x0, x1) = if q == 0.0 {
+        let x = (-c / a).sqrt();
+        (-x, x)
+    } else {
+        (q / a, c / q)
+    };
+    if x0 < x1 { vec![x0, x1] } else { vec![x1, x0] }
+}
+
+/// real roots of `a*x^3 + b*x^2 + c*x + d`, sorted ascending.
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return solve_quadratic(b, c, d);
+    }
+    // x = y - b/3a gives the depressed cubic y^3 + p*y + q.
+    let b = b / a;
+    let c = c / a;
+    let d = d / a;
+    let offset = b / 3.0;
+    let p = c - b * b / 3.0;
+    let q = 2.0 * b * b * b / 27.0 - b * c / 3.0 + d;
+
+    let mut roots = if p.abs() < POLY_EPSILON {
+        vec![(-q).cbrt()]
+    } else {
+        let discriminant = q * q / 4.0 + p * p * p / 27.0;
+        if discriminant.abs() < POLY_EPSILON {
+            let u = (-q / 2.0).cbrt();
+            vec![2.0 * u, -u]
+        } else if discriminant > 0.0 {
+            let sqrt_d = discriminant.sqrt();
+            vec![(-q / 2.0 + sqrt_d).cbrt() + (-q / 2.0 - sqrt_d).cbrt()]
+        } else {
+            // three distinct real roots, use the trigonometric form.
+            let m = 2.0 * (-p / 3.0).sqrt();
+            let phi = (3.0 * q / (p * m)).clamp(-1.0, 1.0).acos() / 3.0;
+            (0..3).map(|i| m * (phi - 2.0 * std::f64::consts::PI * i as f64 / 3.0).cos()).collect()
+        }
+    };
+    for root in roots.iter_mut() {
+        *root = polish_root(&[1.0, b, c, d], *root - offset);
+    }
+    roots.sort_by(|a, b| a.total_cmp(b));
+    roots
+}
+
+/// real roots of `a*x^4 + b*x^3 + c*x^2 + d*x + e` ascending, ferrari's method polished with newton.
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return solve_cubic(b, c, d, e);
+    }
+    let b = b / a;
+    let c = c / a;
+    let d = d / a;
+    let e = e / a;
+
+    // x = y - b/4 gives the depressed quartic y^4 + p*y^2 + q*y + r.
+    let offset = b / 4.0;
+    let b2 = b * b;
+    let p = c - 3.0 * b2 / 8.0;
+    let q = b2 * b / 8.0 - b * c / 2.0 + d;
+    let r = -3.0 * b2 * b2 / 256.0 + b2 * c / 16.0 - b * d / 4.0 + e;
+
+    let mut roots = if r.abs() < POLY_EPSILON {
+        // y * (y^3 + p*y + q) = 0
+        let mut roots = solve_cubic(1.0, 0.0, p, q);
+        roots.push(0.0);
+        roots
+    } else {
+        // any real root of the resolvent cubic splits the quartic into two quadratics,
+        // the largest one is the best conditioned.
+        let Some(&z) = solve_cubic(1.0, -p / 2.0, -r, r * p / 2.0 - q * q / 8.0).last() else {
+            return vec![];
+        };
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if u.abs() < POLY_EPSILON { 0.0 } else if u > 0.0 { u.sqrt() } else { return vec![] };
+        let v = if v.abs() < POLY_EPSILON { 0.0 } else if v > 0.0 { v.sqrt() } else { return vec![] };
+        let v = if q < 0.0 { -v } else { v };
+        let mut roots = solve_quadratic(1.0, v, z - u);
+        roots.extend(solve_quadratic(1.0, -v, z + u));
+        roots
+    };
+    for root in roots.iter_mut() {
+        *root = polish_root(&[1.0, b, c, d, e], *root - offset);
+    }
+    roots.sort_by(|a, b| a.total_cmp(b));
+    roots
+}
+
+const POLY_EPSILON: f64 = 1e-12;
+
+/// value and derivative of the polynomial with `coefficients` (highest degree first) at `x`.
+fn evaluate_polynomial(coefficients: &[f64], x: f64) -> (f64, f64) {
+    let mut value = 0.0;
+    let mut derivative = 0.0;
+    for coefficient in coefficients {
+        derivative = derivative * x + value;
+        value = value * x + coefficient;
+    }
+    (value, derivative)
+}
+
+/// newton steps, only taken when they get closer to zero so roots can't jump to a neighbour.
+fn polish_root(coefficients: &[f64], mut x: f64) -> f64 {
+    let (mut value, mut derivative) = evaluate_polynomial(coefficients, x);
+    for _ in 0..4 {
+        if value == 0.0 || derivative == 0.0 {
+            break;
+        }
+        let next = x - value / derivative;
+        let (next_value, next_derivative) = evaluate_polynomial(coefficients, next);
+        if next_value.is_nan() || next_value.abs() >= value.abs() {
+            break;
+        }
+        x = next;
+        value = next_value;
+        derivative = next_derivative;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::conformance::Conformance;
+    use super::*;
+
+    fn torus() -> Torus {
+        Torus{center: Vec3::new(0., 0., 0.), major_radius: 1.0, minor_radius: 0.25, material: MaterialId::default()}
+    }
+
+    #[test]
+    fn quartic_distinct_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let roots = solve_quartic(1., -10., 35., -50., 24.);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([1., 2., 3., 4.]) {
+            assert_float_absolute_eq!(*root, expected, 1e-10);
+        }
+    }
+
+    #[test]
+    fn quartic_double_root() {
+        // (x - 1)^2 (x - 2)(x + 3)
+        let roots = solve_quartic(1., -1., -7., 13., -6.);
+        assert!(roots.iter().any(|root| (root + 3.).abs() < 1e-10));
+        assert!(roots.iter().any(|root| (root - 1.).abs() < 1e-6));
+        assert!(roots.iter().any(|root| (root - 2.).abs() < 1e-10));
+    }
+
+    #[test]
+    fn quartic_no_real_roots() {
+        // (x^2 + 1)(x^2 + 4)
+        assert!(solve_quartic(1., 0., 5., 0., 4.).is_empty());
+    }
+
+    #[test]
+    fn torus_front_hit() {
+        let ray = Ray{origin: Vec3::new(0., 0., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = torus().hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 3.75, 1e-9);
+        assert_float_absolute_eq!(hit.normal.z, 1.0, 1e-9);
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn torus_unnormalized_direction() {
+        let ray = Ray{origin: Vec3::new(0., 0., 5.), direction: Vec3::new(0., 0., -2.), time: 0.};
+        let hit = torus().hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 1.875, 1e-9);
+    }
+
+    #[test]
+    fn torus_far_away_ray() {
+        let ray = Ray{origin: Vec3::new(0., 0., 1e6), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = torus().hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 1e6 - 1.25, 1e-6);
+    }
+
+    #[test]
+    fn torus_silhouette() {
+        // grazing the top of the tube, the entry point is R + sqrt(r^2 - y^2) along z.
+        let y = 0.2499;
+        let ray = Ray{origin: Vec3::new(0., y, 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = torus().hit(&ray, 0.001, f64::INFINITY).unwrap();
+        let expected_z = 1.0 + (0.25f64 * 0.25 - y * y).sqrt();
+        assert_float_absolute_eq!(hit.t, 5.0 - expected_z, 1e-7);
+
+        let ray = Ray{origin: Vec3::new(0., 0.2501, 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        assert!(torus().hit(&ray, 0.001, f64::INFINITY).is_none());
+
+        let ray = Ray{origin: Vec3::new(1.2501, 0., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        assert!(torus().hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn torus_inner_hole() {
+        // straight down the axis of revolution passes through the hole.
+        let ray = Ray{origin: Vec3::new(0., 5., 0.), direction: Vec3::new(0., -1., 0.), time: 0.};
+        assert!(torus().hit(&ray, 0.001, f64::INFINITY).is_none());
+
+        // a slanted ray through the hole that clears the tube on both sides.
+        let ray = Ray{origin: Vec3::new(-0.5, 5., 0.), direction: Vec3::new(0.1, -1., 0.), time: 0.};
+        assert!(torus().hit(&ray, 0.001, f64::INFINITY).is_none());
+
+        // from the center of the hole the first hit is the inner wall of the tube.
+        let ray = Ray{origin: Vec3::new(0., 0., 0.), direction: Vec3::new(1., 0., 0.), time: 0.};
+        let hit = torus().hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 0.75, 1e-9);
+        assert_float_absolute_eq!(hit.normal.x, -1.0, 1e-9);
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn torus_t_bounds() {
+        // the ray crosses the torus four times, t_min skips the first tube.
+        let ray = Ray{origin: Vec3::new(0., 0., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = torus().hit(&ray, 4.0, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 4.25, 1e-9);
+        assert!(!hit.front_face);
+        let hit = torus().hit(&ray, 4.3, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 5.75, 1e-9);
+        assert!(torus().hit(&ray, 0.001, 3.7).is_none());
+    }
+
+    #[test]
+    fn off_center_sphere() {
+        let sphere = Sphere{radius: 1.0, center: Vec3::new(3., 2., -4.), material: MaterialId::default()};
+        let ray = Ray{origin: Vec3::new(3., 2., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = sphere.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 8.0, 1e-9);
+        assert!((hit.hit_position - Vec3::new(3., 2., -3.)).length() < 1e-9);
+        assert!((hit.normal - Vec3::new(0., 0., 1.)).length() < 1e-9);
+    }
+
+    #[test]
+    fn sphere_conformance() {
+        for (center, radius) in [(Vec3::new(0., 0., 0.), 1.0), (Vec3::new(3., 2., -4.), 0.5), (Vec3::new(-20., 5., 10.), 7.0)] {
+            let sphere = Sphere{radius, center, material: MaterialId::default()};
+            let bounds = sphere.bounding_box().unwrap();
+            Conformance{hittable: &sphere, bounds, inside: Some(center), convex: true}.check();
+        }
+    }
+
+    #[test]
+    fn torus_conformance() {
+        let torus = Torus{center: Vec3::new(1., -2., 3.), ..torus()};
+        let bounds = torus.bounding_box().unwrap();
+        Conformance{hittable: &torus, bounds, inside: Some(Vec3::new(2., -2., 3.)), convex: false}.check();
+    }
+
+    // nudging the hit along dp/du or dp/dv moves its uv by the nudge in u or in v.
+    fn check_uv_derivatives(hittable: &dyn Hittable, ray: &Ray) {
+        let hit = hittable.hit(ray, 0.001, f64::INFINITY).unwrap();
+        let h = 1e-6;
+        for (dp, expected) in [(hit.dpdu, (h, 0.)), (hit.dpdv, (0., h))] {
+            let nudged = hit.hit_position + dp.clone().scale(h);
+            let toward = Ray{origin: ray.origin, direction: nudged - ray.origin, time: 0.};
+            let moved = hittable.hit(&toward, 0.001, f64::INFINITY).unwrap();
+            assert!((moved.hit_position - nudged).length() < 1e-9, "{:?} off the surface", dp);
+            assert_float_absolute_eq!(moved.uv.0 - hit.uv.0, expected.0, 1e-9);
+            assert_float_absolute_eq!(moved.uv.1 - hit.uv.1, expected.1, 1e-9);
+        }
+    }
+
+    #[test]
+    fn sphere_surface_frame() {
+        let sphere = Sphere{radius: 1.0, center: Vec3::new(0., 0., 0.), material: MaterialId(3)};
+        let ray = Ray{origin: Vec3::new(0., 0., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = sphere.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.uv.0, 0.25, 1e-9);
+        assert_float_absolute_eq!(hit.uv.1, 0.5, 1e-9);
+        assert!((hit.tangent - Vec3::new(1., 0., 0.)).length() < 1e-9);
+        assert!((hit.bitangent() - Vec3::new(0., 1., 0.)).length() < 1e-9);
+        assert_eq!(hit.material, MaterialId(3));
+
+        // the poles have no azimuth, the tangent still has to be usable.
+        let down = Ray{origin: Vec3::new(0., 5., 0.), direction: Vec3::new(0., -1., 0.), time: 0.};
+        let pole = sphere.hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(pole.uv.1, 1.0, 1e-9);
+        assert_float_absolute_eq!(pole.tangent.length(), 1.0, 1e-9);
+        assert_float_absolute_eq!(pole.tangent.dot(&pole.normal), 0.0, 1e-9);
+
+        let big = Sphere{radius: 3.0, center: Vec3::new(1., 2., 3.), material: MaterialId(3)};
+        check_uv_derivatives(&big, &Ray{origin: Vec3::new(2., 4., 10.), direction: Vec3::new(0., 0., -1.), time: 0.});
+    }
+
+    #[test]
+    fn torus_surface_frame() {
+        let ray = Ray{origin: Vec3::new(0., 0., 0.), direction: Vec3::new(1., 0., 0.), time: 0.};
+        let hit = torus().hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.uv.0, 0.5, 1e-9);
+        assert_float_absolute_eq!(hit.uv.1, 0.5, 1e-9);
+        assert_float_absolute_eq!(hit.tangent.dot(&hit.normal), 0.0, 1e-9);
+        assert_float_absolute_eq!(hit.tangent.length(), 1.0, 1e-9);
+        check_uv_derivatives(&torus(), &Ray{origin: Vec3::new(0.3, 5., 1.1), direction: Vec3::new(0., -1., 0.), time: 0.});
+    }
+
+    // a shape the renderer knows nothing about, only implementing what the trait requires.
+    struct Floor {
+        height: f64,
+    }
+
+    impl Hittable for Floor {
+        fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+            let t = (self.height - ray.origin.y) / ray.direction.y;
+            if !(t_min..=t_max).contains(&t) {
+                return None;
             }
+            let hit_position = ray.origin + ray.direction.clone().scale(t);
+            Some(HitReturn::new(hit_position, Vec3::new(0., 1., 0.), t, ray.direction.y < 0.0, MaterialId(1)))
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn custom_shapes() {
+        let ray = Ray{origin: Vec3::new(0., 5., 0.), direction: Vec3::new(0., -1., 0.), time: 0.};
+        let shapes: Vec<Box<dyn Hittable>> = vec![Box::new(Floor{height: -1.0}), Box::new(Floor{height: 1.0})];
+        let hit = ray.hit(&shapes, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 4.0, 1e-9);
+        assert_eq!((hit.object_id, hit.material), (1, MaterialId(1)));
+        assert!(shapes[1].bounding_box().is_none());
+        assert_eq!(shapes[1].transmittance(&ray, 0.001, f64::INFINITY), 0.0);
+    }
+}
@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, hittable::{HitReturn, Hittable, Interval}, mat4::Mat4, ray::Ray, scene::Object, vec3::Vec3};
+
+/// shared geometry placed in the world, behind an `Arc` so each instance only costs its matrices.
+pub struct Instance {
+    pub object: Arc<Object>,
+    placement: Placement,
+}
+
+impl Instance {
+    /// returns `None` if `transform` can't be inverted.
+    pub fn new(object: Arc<Object>, transform: Mat4<f64>) -> Option<Self> {
+        Some(Instance{object, placement: Placement::new(transform)?})
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        self.placement.hit(&self.object, ray, t_min, t_max)
+    }
+
+    fn intervals(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<Interval> {
+        self.placement.intervals(&self.object, ray, t_min, t_max)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.placement.transmittance(&self.object, ray, t_min, t_max)
+    }
+
+    fn is_medium(&self) -> bool {
+        self.object.is_medium()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.placement.bounding_box(&self.object)
+    }
+}
+
+/// the matrices of an `Instance` without the object, for anything that places geometry it holds itself.
+#[derive(Debug, Clone)]
+pub struct Placement {
+    transform: Mat4<f64>,
+    inverse: Mat4<f64>,
+    // inverse transpose, takes object space normals to world space.
+    normal_matrix: Mat4<f64>,
+}
+
+impl Placement {
+    /// returns `None` if `transform` can't be inverted.
+    pub fn new(transform: Mat4<f64>) -> Option<Self> {
+        Some(Placement::with_inverse(transform, transform.inverse()?))
+    }
+
+    /// for callers that already know the inverse, e.g. from the parts the transform was built from.
+    pub fn with_inverse(transform: Mat4<f64>, inverse: Mat4<f64>) -> Self {
+        Placement{transform, normal_matrix: inverse.transpose(), inverse}
+    }
+
+    // the direction is not renormalized so t means the same thing in both spaces.
+    fn to_local(&self, ray: &Ray) -> Ray {
+        Ray{
+            origin: self.inverse.transform_point(&ray.origin),
+            direction: self.inverse.transform_vector(&ray.direction),
+            time: ray.time,
+        }
+    }
+
+    fn to_world(&self, ray: &Ray, hit_return: HitReturn) -> HitReturn {
+        let hit_position = ray.origin + ray.direction.clone().scale(hit_return.t);
+        // the child already faced its normal against the local ray, a linear map keeps that orientation.
+        let normal = self.normal_matrix.transform_vector(&hit_return.normal).normalize();
+        let shading_normal = self.normal_matrix.transform_vector(&hit_return.shading_normal).normalize();
+        // tangents are carried along by the transform itself, shears can tip them off the normal's plane.
+        let tangent = self.transform.transform_vector(&hit_return.tangent);
+        let tangent = (tangent - normal.clone().scale(normal.dot(&tangent))).normalize();
+        let (dpdu, dpdv) = (self.transform.transform_vector(&hit_return.dpdu), self.transform.transform_vector(&hit_return.dpdv));
+        HitReturn{hit_position, normal, shading_normal, tangent, dpdu, dpdv, ..hit_return}
+    }
+
+    /// as `Hittable::hit` for `object` placed here.
+    pub fn hit(&self, object: &Object, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        let hit_return = object.hit(&self.to_local(ray), t_min, t_max)?;
+        Some(self.to_world(ray, hit_return))
+    }
+
+    /// as `Hittable::intervals` for `object` placed here.
+    pub fn intervals(&self, object: &Object, ray: &Ray, t_min: f64, t_max: f64) -> Vec<Interval> {
+        object.intervals(&self.to_local(ray), t_min, t_max)
+            .into_iter()
+            .map(|interval| Interval{
+                enter: interval.enter.map(|hit_return| self.to_world(ray, hit_return)),
+                exit: interval.exit.map(|hit_return| self.to_world(ray, hit_return)),
+            })
+            .collect()
+    }
+
+    /// as `Hittable::transmittance` for `object` placed here.
+    pub fn transmittance(&self, object: &Object, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        object.transmittance(&self.to_local(ray), t_min, t_max)
+    }
+
+    /// the box around the transformed corners of `object`'s box.
+    pub fn bounding_box(&self, object: &Object) -> Option<Aabb> {
+        let local = object.bounding_box()?;
+        let corners = (0..8).map(|i| {
+            let corner = Vec3::new(
+                if i & 1 == 0 { local.min.x } else { local.max.x },
+                if i & 2 == 0 { local.min.y } else { local.max.y },
+                if i & 4 == 0 { local.min.z } else { local.max.z },
+            );
+            let p = self.transform.transform_point(&corner);
+            Aabb::new(p, p)
+        });
+        corners.reduce(|a, b| a.surrounding(&b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::{conformance::Conformance, hittable::{Sphere, Torus}, material::MaterialId, quat::Quat};
+    use super::*;
+
+    fn unit_sphere() -> Arc<Object> {
+        Arc::new(Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(0., 0., 0.), material: MaterialId::default()}))
+    }
+
+    #[test]
+    fn translated_and_scaled() {
+        let transform = Mat4::translation(Vec3::new(5., 0., 0.)).mat_mul(&Mat4::scaling(Vec3::new(2., 2., 2.)));
+        let instance = Instance::new(unit_sphere(), transform).unwrap();
+        let ray = Ray{origin: Vec3::new(5., 0., 10.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = instance.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 8.0, 1e-9);
+        assert!((hit.hit_position - Vec3::new(5., 0., 2.)).length() < 1e-9);
+        assert!((hit.normal - Vec3::new(0., 0., 1.)).length() < 1e-9);
+
+        let miss = Ray{origin: Vec3::new(0., 0., 10.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        assert!(instance.hit(&miss, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn non_uniform_scale_normal() {
+        // an ellipsoid x^2/4 + y^2 + z^2 = 1, its normal is the gradient (x/4, y, z).
+        let instance = Instance::new(unit_sphere(), Mat4::scaling(Vec3::new(2., 1., 1.))).unwrap();
+        let ray = Ray{origin: Vec3::new(1., 0., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = instance.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        let z = 0.75f64.sqrt();
+        assert_float_absolute_eq!(hit.t, 5.0 - z, 1e-9);
+        let expected = Vec3::new(0.25, 0., z).normalize();
+        assert!((hit.normal - expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn rotated_torus() {
+        // standing the torus up on its side makes the hole face the z axis.
+        let torus = Arc::new(Object::Torus(Torus{center: Vec3::new(0., 0., 0.), major_radius: 1.0, minor_radius: 0.25, material: MaterialId::default()}));
+        let rotation = Mat4::rotation(&Quat::angle_axis(90.0f64.to_radians(), Vec3::new(1., 0., 0.)));
+        let instance = Instance::new(torus.clone(), rotation).unwrap();
+        let through_hole = Ray{origin: Vec3::new(0., 0., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        assert!(instance.hit(&through_hole, 0.001, f64::INFINITY).is_none());
+        let onto_tube = Ray{origin: Vec3::new(0., 1., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = instance.hit(&onto_tube, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 4.75, 1e-9);
+        assert!((hit.normal - Vec3::new(0., 0., 1.)).length() < 1e-9);
+        assert_eq!(Arc::strong_count(&torus), 2);
+    }
+
+    #[test]
+    fn singular_transform() {
+        assert!(Instance::new(unit_sphere(), Mat4::scaling(Vec3::new(1., 0., 1.))).is_none());
+    }
+
+    #[test]
+    fn conformance() {
+        let transform = Mat4::translation(Vec3::new(1., 2., -3.))
+            .mat_mul(&Mat4::rotation(&Quat::angle_axis(30.0f64.to_radians(), Vec3::new(1., 1., 0.).normalize())))
+            .mat_mul(&Mat4::scaling(Vec3::new(2., 1., 0.5)));
+        let ellipsoid = Instance::new(unit_sphere(), transform).unwrap();
+        let bounds = ellipsoid.bounding_box().unwrap();
+        Conformance{hittable: &ellipsoid, bounds, inside: Some(Vec3::new(1., 2., -3.)), convex: true}.check();
+
+        let torus = Arc::new(Object::Torus(Torus{center: Vec3::new(0., 0., 0.), major_radius: 1.0, minor_radius: 0.25, material: MaterialId::default()}));
+        let torus = Instance::new(torus, transform).unwrap();
+        let bounds = torus.bounding_box().unwrap();
+        let inside = transform.transform_point(&Vec3::new(1., 0., 0.));
+        Conformance{hittable: &torus, bounds, inside: Some(inside), convex: false}.check();
+    }
+}
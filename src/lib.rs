@@ -0,0 +1,38 @@
+pub mod vec3;
+pub mod ray;
+pub mod hittable;
+pub mod scene;
+pub mod fonts;
+pub mod camera;
+pub mod mat3;
+pub mod quat;
+pub mod mat4;
+pub mod vec4;
+pub mod instance;
+pub mod csg;
+pub mod sdf;
+pub mod aabb;
+pub mod heightfield;
+pub mod motion;
+pub mod medium;
+pub mod noise;
+pub mod bvh;
+pub mod material;
+pub mod texture;
+pub mod triangle;
+pub mod quad;
+pub mod bsdf;
+pub mod principled;
+pub mod alias;
+pub mod bdpt;
+pub mod debug;
+pub mod photon;
+pub mod light;
+pub mod sky;
+pub mod spectrum;
+pub mod thin_film;
+pub mod whitted;
+#[cfg(test)]
+mod conformance;
+
+pub const ASPECT_RATIO: f64 = 16.0/9.0;
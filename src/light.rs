@@ -0,0 +1,455 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+use rand::Rng;
+
+use crate::{alias::AliasTable, bsdf::sample_cosine, hittable::{HitReturn, Hittable}, ray::Ray, scene::{Object, Scene}, texture::Texture, vec3::Vec3};
+
+/// something the integrator aims shadow rays at instead of waiting for paths to run into it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    /// the sky's sun.
+    Sun,
+    /// an emissive sphere, sampled over the cone it covers.
+    Sphere{center: Vec3<f64>, radius: f64, object: usize},
+    /// emissive quads and triangles, sampled uniformly over their area.
+    Quad{corner: Vec3<f64>, u: Vec3<f64>, v: Vec3<f64>, object: usize},
+    Triangle{vertices: [Vec3<f64>; 3], object: usize},
+}
+
+/// a direction toward a light, with its radiance from there and the density it was picked with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightSample {
+    pub direction: Vec3<f64>,
+    /// how far away the light is, infinite for the sun.
+    pub distance: f64,
+    pub radiance: Vec3<f64>,
+    /// per unit solid angle with the pick chance in. 1 for delta lights, their `radiance` has it divided out.
+    pub pdf: f64,
+    /// a light only one direction reaches, which nothing but light sampling can find.
+    pub delta: bool,
+    /// which of the scene's lights it is.
+    pub light: usize,
+}
+
+/// a point on a light and a direction light leaves it in, starting a path from the light's side.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmissionSample {
+    pub light: usize,
+    pub position: Vec3<f64>,
+    /// facing `direction`.
+    pub normal: Vec3<f64>,
+    pub direction: Vec3<f64>,
+    pub radiance: Vec3<f64>,
+    /// per unit area, including the chance of picking the light.
+    pub position_pdf: f64,
+    /// per unit solid angle.
+    pub direction_pdf: f64,
+}
+
+impl Light {
+    /// the light an object is if it glows and can be sampled, with about how much it gives off per channel.
+    pub fn from_object(object: &Object, index: usize, scene: &Scene) -> Option<(Light, Vec3<f64>)> {
+        // quads and triangles glow on both sides.
+        let (light, material, center, area) = match object {
+            Object::Sphere(sphere) => (Light::Sphere{center: sphere.center, radius: sphere.radius, object: index}, sphere.material, sphere.center, 4.0 * PI * sphere.radius * sphere.radius),
+            Object::Quad(quad) => {
+                let center = quad.corner + (quad.u + quad.v).scale(0.5);
+                (Light::Quad{corner: quad.corner, u: quad.u, v: quad.v, object: index}, quad.material, center, 2.0 * quad.u.cross(&quad.v).length())
+            }
+            Object::Triangle(triangle) => {
+                let [p0, p1, p2] = triangle.vertices;
+                let center = (p0 + p1 + p2).scale(1.0 / 3.0);
+                (Light::Triangle{vertices: triangle.vertices, object: index}, triangle.material, center, (p1 - p0).cross(&(p2 - p0)).length())
+            }
+            _ => return None,
+        };
+        let emission = &scene.material(material).emission;
+        if matches!(emission, Texture::Solid(color) if *color == Vec3::new(0., 0., 0.)) {
+            return None;
+        }
+        Some((light, mean_emission(emission, &center).scale(PI * area)))
+    }
+
+    // a point on the light seen from `origin`, `None` if it can't be seen from there at all.
+    fn sample_point(&self, origin: &Vec3<f64>, rng: &mut impl Rng) -> Option<Vec3<f64>> {
+        match self {
+            Light::Sun => None,
+            Light::Sphere{center, radius, ..} => {
+                let axis = *center - *origin;
+                let distance = axis.length();
+                if distance <= *radius {
+                    return None;
+                }
+                let cos_max = (1.0 - (radius / distance).powi(2)).sqrt();
+                let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - cos_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * rng.gen::<f64>();
+                let axis = axis.normalize();
+                let (tangent, bitangent) = axis.orthonormal_basis();
+                let direction = tangent.clone().scale(sin_theta * phi.cos()) + bitangent.clone().scale(sin_theta * phi.sin()) + axis.clone().scale(cos_theta);
+                // any point along the direction will do, the sphere itself is intersected after.
+                Some(*origin + direction.clone().scale(distance))
+            }
+            Light::Quad{corner, u, v, ..} => Some(*corner + u.clone().scale(rng.gen()) + v.clone().scale(rng.gen())),
+            Light::Triangle{vertices: [p0, p1, p2], ..} => {
+                let (u1, u2) = (rng.gen::<f64>().sqrt(), rng.gen::<f64>());
+                Some(p0.clone().scale(1.0 - u1) + p1.clone().scale(u1 * (1.0 - u2)) + p2.clone().scale(u1 * u2))
+            }
+        }
+    }
+
+    /// the density `sample` would pick the direction from `origin` to `hit_return` on this light with, per unit solid angle.
+    pub fn pdf(&self, origin: &Vec3<f64>, hit_return: &HitReturn) -> f64 {
+        let offset = hit_return.hit_position - *origin;
+        let distance_squared = offset.length_squared();
+        let cos_light = hit_return.normal.dot(&offset).abs() / distance_squared.sqrt();
+        let area = match self {
+            Light::Sun => return 0.0,
+            Light::Sphere{center, radius, ..} => {
+                let distance = (*center - *origin).length();
+                if distance <= *radius {
+                    return 0.0;
+                }
+                let cos_max = (1.0 - (radius / distance).powi(2)).sqrt();
+                return 1.0 / (2.0 * PI * (1.0 - cos_max));
+            }
+            Light::Quad{u, v, ..} => u.cross(v).length(),
+            Light::Triangle{vertices: [p0, p1, p2], ..} => (*p1 - *p0).cross(&(*p2 - *p0)).length() / 2.0,
+        };
+        if cos_light <= 0.0 {
+            return 0.0;
+        }
+        distance_squared / (cos_light * area)
+    }
+
+    // the light's area, counting a side.
+    fn area(&self) -> f64 {
+        match self {
+            Light::Sun => 0.0,
+            Light::Sphere{radius, ..} => 4.0 * PI * radius * radius,
+            Light::Quad{u, v, ..} => u.cross(v).length(),
+            Light::Triangle{vertices: [p0, p1, p2], ..} => (*p1 - *p0).cross(&(*p2 - *p0)).length() / 2.0,
+        }
+    }
+
+    // the normal at a point on the light, outward for spheres and either way for the two sided ones.
+    fn normal_at(&self, position: &Vec3<f64>) -> Vec3<f64> {
+        match self {
+            Light::Sun => Vec3::new(0., 0., 0.),
+            Light::Sphere{center, ..} => (*position - *center).normalize(),
+            Light::Quad{u, v, ..} => u.cross(v).normalize(),
+            Light::Triangle{vertices: [p0, p1, p2], ..} => (*p1 - *p0).cross(&(*p2 - *p0)).normalize(),
+        }
+    }
+
+    fn two_sided(&self) -> bool {
+        matches!(self, Light::Quad{..} | Light::Triangle{..})
+    }
+
+    fn object(&self) -> Option<usize> {
+        match self {
+            Light::Sun => None,
+            Light::Sphere{object, ..} | Light::Quad{object, ..} | Light::Triangle{object, ..} => Some(*object),
+        }
+    }
+}
+
+// the mean of an emission texture, looked up over a few uvs for the textured ones.
+fn mean_emission(emission: &Texture, position: &Vec3<f64>) -> Vec3<f64> {
+    if let Texture::Solid(color) = emission {
+        return *color;
+    }
+    let n = 4;
+    let sum = (0..n * n).fold(Vec3::new(0., 0., 0.), |sum, i| sum + emission.evaluate(((i % n) as f64 / n as f64 + 0.125, (i / n) as f64 / n as f64 + 0.125), position));
+    sum.clone().scale(1.0 / (n * n) as f64)
+}
+
+/// a light shrunk to a point, or the sun to a direction, for hard shadows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Punctual {
+    /// in the middle of an emissive object, with the same `intensity` (per unit solid angle) every way.
+    Point{position: Vec3<f64>, intensity: Vec3<f64>, object: usize},
+    /// the unit `direction` toward the sun.
+    Directional{direction: Vec3<f64>, irradiance: Vec3<f64>},
+}
+
+/// every light in a scene, picked by power so bright lamps don't drown among dim triangles.
+#[derive(Debug, Default)]
+pub struct Lights {
+    lights: Vec<Light>,
+    // how much light each gives off in all, per channel.
+    powers: Vec<Vec3<f64>>,
+    table: AliasTable,
+    // the light each emissive object is, by object index.
+    by_object: HashMap<usize, usize>,
+    sun: Option<usize>,
+}
+
+impl Lights {
+    pub fn new(scene: &Scene) -> Self {
+        let (mut lights, mut powers): (Vec<Light>, Vec<Vec3<f64>>) = scene.objects().iter().enumerate().filter_map(|(i, object)| Light::from_object(object, i, scene)).unzip();
+        let sun = scene.sky().has_sun().then(|| {
+            // all the sunlight falling on the scene.
+            let irradiance = scene.sky().sample_sun().irradiance;
+            let radius = scene_radius(scene.objects());
+            lights.push(Light::Sun);
+            powers.push(irradiance.clone().scale(PI * radius * radius));
+            lights.len() - 1
+        });
+        let by_object = lights.iter().enumerate().filter_map(|(i, light)| Some((light.object()?, i))).collect();
+        let table = AliasTable::new(&powers.iter().map(|power| (power.x + power.y + power.z) / 3.0).collect::<Vec<f64>>());
+        Lights{lights, powers, table, by_object, sun}
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// the chance of `sample` going for `light`.
+    pub fn pick_probability(&self, light: usize) -> f64 {
+        self.table.pdf(light)
+    }
+
+    /// picks a light and a direction toward it from `origin`, `None` if there's nothing to see.
+    pub fn sample(&self, scene: &Scene, origin: &Vec3<f64>, time: f64, rng: &mut impl Rng) -> Option<LightSample> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let index = self.table.sample(rng.gen());
+        let light = &self.lights[index];
+        let pick = self.pick_probability(index);
+        if let Light::Sun = light {
+            let sun = scene.sky().sample_sun();
+            let pdf = scene.sky().sun_pdf(&sun.direction);
+            return Some(if pdf > 0.0 {
+                LightSample{direction: sun.direction, distance: f64::INFINITY, radiance: sun.irradiance.clone().scale(pdf), pdf: pdf * pick, delta: false, light: index}
+            } else {
+                LightSample{direction: sun.direction, distance: f64::INFINITY, radiance: sun.irradiance.clone().scale(1.0 / pick), pdf: 1.0, delta: true, light: index}
+            });
+        }
+        let direction = (light.sample_point(origin, rng)? - *origin).normalize();
+        // the emission is looked up where the light really is along the direction, with its uvs.
+        let ray = Ray{origin: *origin, direction, time};
+        let hit_return = scene.objects()[light.object()?].hit(&ray, 0.0, f64::INFINITY)?;
+        let pdf = light.pdf(origin, &hit_return) * pick;
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return None;
+        }
+        let radiance = scene.material(hit_return.material).emission.evaluate(hit_return.uv, &hit_return.hit_position);
+        Some(LightSample{direction, distance: hit_return.t, radiance, pdf, delta: false, light: index})
+    }
+
+    /// the density `sample` picks the direction to an emissive hit with, zero if it isn't a light.
+    pub fn pdf(&self, origin: &Vec3<f64>, hit_return: &HitReturn) -> f64 {
+        let Some(&index) = self.by_object.get(&hit_return.object_id) else {
+            return 0.0;
+        };
+        self.lights[index].pdf(origin, hit_return) * self.pick_probability(index)
+    }
+
+    /// the light an object is, if it is one.
+    pub fn by_object(&self, object: usize) -> Option<usize> {
+        self.by_object.get(&object).copied()
+    }
+
+    /// a light, a uniform point on it and a cosine distributed direction. `None` for the sun.
+    pub fn sample_emission(&self, scene: &Scene, time: f64, rng: &mut impl Rng) -> Option<EmissionSample> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let index = self.table.sample(rng.gen());
+        let light = &self.lights[index];
+        let position = match light {
+            Light::Sun => return None,
+            Light::Sphere{center, radius, ..} => *center + Vec3::random_unit_vector().scale(*radius),
+            // sampled from the middle of the quad or triangle, so any point of it goes.
+            _ => light.sample_point(&Vec3::new(0., 0., 0.), rng)?,
+        };
+        let mut normal = light.normal_at(&position);
+        if light.two_sided() && rng.gen::<bool>() {
+            normal = -normal;
+        }
+        // the emission is looked up with the uvs of a ray running into the light right there.
+        let ray = Ray{origin: position + normal, direction: -normal, time};
+        let hit_return = scene.objects()[light.object()?].hit(&ray, 0.0, f64::INFINITY)?;
+        let radiance = scene.material(hit_return.material).emission.evaluate(hit_return.uv, &hit_return.hit_position);
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        let local = sample_cosine(&Vec3::new(0., 0., 1.), (rng.gen(), rng.gen()));
+        let direction = tangent.clone().scale(local.x) + bitangent.clone().scale(local.y) + normal.clone().scale(local.z);
+        let direction_pdf = self.direction_pdf(index, &position, &direction);
+        if direction_pdf <= 0.0 {
+            return None;
+        }
+        Some(EmissionSample{light: index, position, normal, direction, radiance, position_pdf: self.position_pdf(index), direction_pdf})
+    }
+
+    /// the density `sample_emission` starts at a point of `light` with, per unit area, including the chance of picking it.
+    pub fn position_pdf(&self, light: usize) -> f64 {
+        let area = self.lights[light].area();
+        if area > 0.0 { self.pick_probability(light) / area } else { 0.0 }
+    }
+
+    /// the density `sample_emission` sends light from `position` on `light` off in `direction` with, per unit solid angle.
+    pub fn direction_pdf(&self, light: usize, position: &Vec3<f64>, direction: &Vec3<f64>) -> f64 {
+        let light = &self.lights[light];
+        let cos = light.normal_at(position).dot(&direction.normalize());
+        if light.two_sided() {
+            cos.abs() / (2.0 * PI)
+        } else {
+            cos.max(0.0) / PI
+        }
+    }
+
+    /// the normal of `light` at `position`, outward for spheres and either way for the two sided lights.
+    pub fn normal(&self, light: usize, position: &Vec3<f64>) -> Vec3<f64> {
+        self.lights[light].normal_at(position)
+    }
+
+    /// every light as a point or a direction, the middle of its shape or of the sun's disk.
+    pub fn punctual(&self, scene: &Scene) -> Vec<Punctual> {
+        self.lights.iter().zip(&self.powers).map(|(light, power)| match light {
+            Light::Sun => {
+                let sun = scene.sky().sun();
+                Punctual::Directional{direction: sun.direction, irradiance: sun.irradiance}
+            }
+            Light::Sphere{center, object, ..} => Punctual::Point{position: *center, intensity: power.clone().scale(1.0 / (4.0 * PI)), object: *object},
+            Light::Quad{corner, u, v, object} => Punctual::Point{position: *corner + (*u + *v).scale(0.5), intensity: power.clone().scale(1.0 / (4.0 * PI)), object: *object},
+            Light::Triangle{vertices: [p0, p1, p2], object} => Punctual::Point{position: (*p0 + *p1 + *p2).scale(1.0 / 3.0), intensity: power.clone().scale(1.0 / (4.0 * PI)), object: *object},
+        }).collect()
+    }
+
+    /// as `pdf`, for a path that left the scene in `direction`.
+    pub fn sun_pdf(&self, scene: &Scene, direction: &Vec3<f64>) -> f64 {
+        self.sun.map_or(0.0, |index| scene.sky().sun_pdf(direction) * self.pick_probability(index))
+    }
+}
+
+// half the diagonal of the box around everything bounded, what the sun has to light.
+fn scene_radius(objects: &[Object]) -> f64 {
+    objects.iter().filter_map(|object| object.bounding_box()).reduce(|a, b| a.surrounding(&b)).map_or(1.0, |bounds| (bounds.max - bounds.min).length() / 2.0)
+}
+
+/// the power heuristic weight of a sample picked with density `pdf` (veach 1997).
+pub fn power_heuristic(pdf: f64, other: f64) -> f64 {
+    if pdf.is_infinite() {
+        return 1.0;
+    }
+    let (pdf, other) = (pdf * pdf, other * other);
+    if pdf + other == 0.0 { 0.0 } else { pdf / (pdf + other) }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use crate::{bsdf::tests::sphere_grid, hittable::Sphere, material::{Material, MaterialId}, quad::Quad, sky::Sky, triangle::Triangle};
+    use super::*;
+
+    fn lit() -> Scene {
+        let mut scene = Scene::new(4, 4);
+        let lamp = scene.add_material(Material::emissive(Vec3::new(4., 4., 4.)));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.5, center: Vec3::new(0., 3., 0.), material: lamp}));
+        scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(2., 2., -1.), u: Vec3::new(1., 0., 0.), v: Vec3::new(0., 0.5, 0.5), material: lamp})));
+        scene.add_object(Object::Triangle(Box::new(Triangle::new([Vec3::new(-2., 2., 0.), Vec3::new(-1., 2., 0.), Vec3::new(-2., 3., 1.)], lamp))));
+        // not a light.
+        scene.add_object(Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(0., -1., 0.), material: MaterialId::default()}));
+        scene
+    }
+
+    #[test]
+    fn finds_the_lights() {
+        let scene = lit();
+        let lights = Lights::new(&scene);
+        assert_eq!(lights.len(), 4);
+        assert_eq!(lights.lights[3], Light::Sun);
+        assert!(!lights.by_object.contains_key(&3));
+    }
+
+    #[test]
+    fn sampling_matches_pdf() {
+        let scene = lit();
+        let lights = Lights::new(&scene);
+        let origin = Vec3::new(0.1, 0.5, 0.2);
+        let (mut area, mut delta) = (0, 0);
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..2000 {
+            let Some(sample) = lights.sample(&scene, &origin, 0.0, &mut rng) else {
+                continue;
+            };
+            if sample.delta {
+                delta += 1;
+                assert_float_relative_eq!(sample.radiance.y, PI / lights.pick_probability(3), 1e-12);
+                continue;
+            }
+            area += 1;
+            assert_eq!(sample.radiance, Vec3::new(4., 4., 4.));
+            // a path running into the light the same way finds the same density.
+            let hit_return = scene.hit(&Ray{origin, direction: sample.direction, time: 0.0}, 1e-9, f64::INFINITY).unwrap();
+            assert_float_relative_eq!(hit_return.t, sample.distance, 1e-9);
+            assert_float_relative_eq!(lights.pdf(&origin, &hit_return), sample.pdf, 1e-9);
+        }
+        // the flat sky's sun is one of the four.
+        assert!(area > 300 && delta > 300, "{area} {delta}");
+    }
+
+    #[test]
+    fn pdfs_integrate_to_one() {
+        // summed over the sphere of directions around a point, each light's density is 1.
+        let scene = lit();
+        let origin = Vec3::new(0.1, 0.5, 0.2);
+        let cell = 4.0 * PI / 1e6;
+        for (i, object) in scene.objects().iter().enumerate().take(3) {
+            let (light, _) = Light::from_object(object, i, &scene).unwrap();
+            let total: f64 = sphere_grid(1000).filter_map(|direction| {
+                let hit_return = object.hit(&Ray{origin, direction, time: 0.0}, 1e-9, f64::INFINITY)?;
+                Some(light.pdf(&origin, &hit_return) * cell)
+            }).sum();
+            assert_float_absolute_eq!(total, 1.0, 0.05);
+        }
+    }
+
+    #[test]
+    fn brighter_lights_are_picked_more() {
+        // a thousand faint triangles don't keep one bright lamp from being found.
+        let mut scene = Scene::new(4, 4);
+        scene.set_sky(Sky::Flat{color: Vec3::new(0., 0., 0.), sun_direction: Vec3::new(0., 1., 0.), sun_irradiance: 0.0});
+        let faint = scene.add_material(Material::emissive(Vec3::new(0.01, 0.01, 0.01)));
+        let bright = scene.add_material(Material::emissive(Vec3::new(100., 100., 100.)));
+        for i in 0..1000 {
+            let x = i as f64 * 0.01;
+            scene.add_object(Object::Triangle(Box::new(Triangle::new([Vec3::new(x, 0., 0.), Vec3::new(x + 0.01, 0., 0.), Vec3::new(x, 0.01, 0.)], faint))));
+        }
+        scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(0., 2., 0.), u: Vec3::new(0.1, 0., 0.), v: Vec3::new(0., 0., 0.1), material: bright})));
+        let lights = Lights::new(&scene);
+        assert_eq!(lights.len(), 1001);
+        // 1000 triangles of area 5e-5 against a quad of area 1e-2 and 10^4 times the radiance, both two sided.
+        assert_float_relative_eq!(lights.pick_probability(1000), 2.0 / 2.001, 1e-9);
+        assert_float_relative_eq!(lights.pick_probability(0), 1e-6 / 2.001, 1e-9);
+        let mut rng = StdRng::seed_from_u64(0);
+        let picked_bright = (0..1000).filter_map(|_| lights.sample(&scene, &Vec3::new(0.05, 1., 0.05), 0.0, &mut rng)).filter(|sample| sample.radiance.y == 100.0).count();
+        assert!(picked_bright > 990, "{picked_bright}");
+    }
+
+    #[test]
+    fn the_sun_lights_the_whole_scene() {
+        let scene = lit();
+        let lights = Lights::new(&scene);
+        // pi irradiance over the disk of the bounds' half diagonal, against each lamp's pi area radiance.
+        let radius = scene_radius(scene.objects());
+        let sun = PI * PI * radius * radius;
+        // a sphere of area pi, and a quad and a triangle of area sqrt(2) / 2 glowing on both sides.
+        let lamps = 4.0 * PI * (PI + 2.0 * 2.0f64.sqrt());
+        assert_float_relative_eq!(lights.pick_probability(3), sun / (sun + lamps), 1e-9);
+    }
+
+    #[test]
+    fn power_heuristic_weights_add_up() {
+        assert_float_absolute_eq!(power_heuristic(2.0, 1.0) + power_heuristic(1.0, 2.0), 1.0, 1e-12);
+        assert_eq!(power_heuristic(3.0, 0.0), 1.0);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+    }
+}
@@ -1,27 +1,162 @@
-mod vec3;
-mod ray;
-mod hittable;
-mod scene;
 mod window;
-mod fonts;
-mod camera;
-mod mat3;
-mod quat;
-mod mat4;
-mod vec4;
 
-use hittable::Sphere;
-use scene::{Scene, Object};
-use vec3::Vec3;
+use std::sync::Arc;
+
+use ray_tracing_weekend_rs::{
+    csg::{Csg, CsgOperation},
+    heightfield::Heightfield,
+    hittable::{Sphere, Torus},
+    instance::Instance,
+    mat4::Mat4,
+    material::{Material, MaterialId, NormalMap},
+    aabb::Aabb,
+    bsdf::Metal,
+    medium::{DensityField, HeightFog, HeterogeneousVolume, PhaseFunction, Volume},
+    noise::Perlin,
+    principled::Principled,
+    motion::MovingInstance,
+    quat::Quat,
+    quad::Quad,
+    sdf::{Sdf, SdfObject},
+    texture::{NoisePattern, Texture},
+    triangle::Triangle,
+    scene::{Scene, Object},
+    sky::{Daylight, Sky},
+    spectrum::Ior,
+    thin_film::ThinFilm,
+    vec3::Vec3,
+    ASPECT_RATIO,
+};
 use window::Window;
-const ASPECT_RATIO: f64 = 16.0/9.0;
 
 fn main() {
     let window =  Window{width: 1280, height: (1280. / ASPECT_RATIO) as u32, title: "Ray Tracer"};
     let mut scene = Scene::new(window.width, window.height);
-    scene.objects.push(Object::Sphere(Sphere{radius: 0.5, center: Vec3::new(0., 0., 0.), color: Vec3::new(1., 0., 0.,)}));
-    scene.objects.push(Object::Sphere(Sphere{radius: 1., center: Vec3::new(0., -1., 0.), color: Vec3::new(1., 0., 1.,)}));
-    scene.objects.push(Object::Sphere(Sphere{radius: 100., center: Vec3::new(0., -102.5, 0.), color: Vec3::new(0., 1., 0.,)}));
-    window.render_loop(scene);
+    let red = scene.add_material(Material::principled(Vec3::new(1., 0., 0.,), 0.6, Principled{clearcoat: 1.0, ..Principled::default()}));
+    let gold = scene.add_material(Material::conductor(Metal::Gold, 0.3));
+    let frosted_glass = scene.add_material(Material::dielectric(Ior::BK7, 0.2));
+    let blue = scene.add_material(Material::diffuse(Vec3::new(0., 0., 1.,)));
+    let yellow = scene.add_material(Material::diffuse(Vec3::new(1., 1., 0.,)));
+    let orange = scene.add_material(Material::diffuse(Vec3::new(1., 0.5, 0.,)));
+    let jade = scene.add_material(Material::subsurface(Vec3::new(0.3, 0.85, 0.6), Vec3::new(0.1, 0.3, 0.2), 0.3));
+    let earth = scene.add_material(Material::diffuse(Texture::Noise{
+        noise: Perlin::new(1),
+        pattern: NoisePattern::Fbm{octaves: 5},
+        frequency: 0.8,
+        low: Vec3::new(0.3, 0.25, 0.1),
+        high: Vec3::new(0.6, 0.5, 0.3),
+    }));
+    let green = scene.add_material(Material::diffuse(Texture::Checker3d{
+        size: 1.0,
+        even: Box::new(Texture::Solid(Vec3::new(0., 1., 0.))),
+        odd: Box::new(Texture::Solid(Vec3::new(0.2, 0.6, 0.2))),
+    }));
+    scene.add_object(Object::Sphere(Sphere{radius: 0.5, center: Vec3::new(0., 0., 0.), material: red}));
+    scene.add_object(Object::Sphere(Sphere{radius: 1., center: Vec3::new(0., -1., 0.), material: gold}));
+    let torus = Arc::new(Object::Torus(Torus{center: Vec3::new(0., 0., 0.), major_radius: 1.5, minor_radius: 0.2, material: blue}));
+    scene.add_object(Object::Instance(Box::new(Instance::new(torus.clone(), Mat4::identity()).unwrap())));
+    let standing = Mat4::translation(Vec3::new(2.5, 0., -1.)).mat_mul(&Mat4::rotation(&Quat::angle_axis(90.0f64.to_radians(), Vec3::new(1., 0., 0.))));
+    scene.add_object(Object::Instance(Box::new(Instance::new(torus, standing).unwrap())));
+    let bitten = Csg::new(
+        CsgOperation::Difference,
+        Object::Sphere(Sphere{radius: 0.6, center: Vec3::new(-2.5, 0., -1.), material: yellow}),
+        Object::Sphere(Sphere{radius: 0.4, center: Vec3::new(-2.1, 0.3, -0.6), material: orange}),
+    );
+    scene.add_object(Object::Csg(Box::new(bitten)));
+    let twisted_column = Sdf::Translate{
+        offset: Vec3::new(2.5, 0., -3.),
+        sdf: Box::new(Sdf::Twist{rate: 1.0, sdf: Box::new(Sdf::Cuboid{half_extents: Vec3::new(0.3, 1.2, 0.3)})}),
+    };
+    scene.add_object(Object::Sdf(Box::new(SdfObject::new(twisted_column, jade))));
+    let hills: Vec<f64> = (0..64 * 64).map(|i| {
+        let (x, z) = ((i % 64) as f64 / 8., (i / 64) as f64 / 8.);
+        0.5 + 0.25 * (x.sin() + z.cos())
+    }).collect();
+    scene.add_object(Object::Heightfield(Box::new(Heightfield::new(64, 64, &hills, Vec3::new(-8., -2.5, -14.), Vec3::new(16., 2., 8.), earth))));
+    let ball = Arc::new(Object::Sphere(Sphere{radius: 0.3, center: Vec3::new(0., 0., 0.), material: frosted_glass}));
+    scene.add_object(Object::Moving(Box::new(MovingInstance::linear(ball, Vec3::new(-1.5, 1.2, 0.), 0.0, Vec3::new(-0.5, 1.2, 0.), 1.0))));
+    scene.camera.shutter_close = 1.0;
+    scene.add_object(Object::Volume(Box::new(Volume{
+        boundary: Object::Sphere(Sphere{radius: 0.5, center: Vec3::new(1.5, 1.5, -1.), material: MaterialId::default()}),
+        density: 3.0,
+        albedo: Vec3::new(0.9, 0.9, 0.9),
+        phase: PhaseFunction::HenyeyGreenstein{g: 0.5},
+    })));
+    scene.add_object(Object::HeterogeneousVolume(Box::new(HeterogeneousVolume{
+        bounds: Aabb::new(Vec3::new(-3., 1.5, -4.), Vec3::new(1., 2.5, -2.)),
+        field: DensityField::Noise{noise: Perlin::new(0), frequency: 1.5, octaves: 4},
+        density_scale: 4.0,
+        albedo: Vec3::new(0.95, 0.95, 0.95),
+        phase: PhaseFunction::HenyeyGreenstein{g: 0.3},
+    })));
+    scene.set_sky(Sky::Daylight(Daylight::new(Vec3::new(1., 1., 1.), 2.5)));
+    scene.fog = Some(HeightFog{density: 0.05, falloff: 0.5, base_height: -2.5, albedo: Vec3::new(0.9, 0.9, 0.9), phase: PhaseFunction::Isotropic});
+    let stucco = scene.add_material(Material{
+        normal_map: Some(NormalMap::Bump{
+            height: Texture::Noise{noise: Perlin::new(2), pattern: NoisePattern::Turbulence{octaves: 4}, frequency: 6.0, low: Vec3::new(0., 0., 0.), high: Vec3::new(1., 1., 1.)},
+            strength: 0.02,
+        }),
+        ..Material::diffuse(Vec3::new(0.9, 0.85, 0.8))
+    });
+    scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(-6., -2.5, -16.), u: Vec3::new(12., 0., 0.), v: Vec3::new(0., 6., 0.), material: stucco})));
+    // swirls of thicker and thinner soap, like a bubble about to pop.
+    let bubble = scene.add_material(Material{
+        coating: Some(ThinFilm::soap(Texture::Noise{noise: Perlin::new(3), pattern: NoisePattern::Fbm{octaves: 3}, frequency: 2.0, low: Vec3::new(150., 150., 150.), high: Vec3::new(700., 700., 700.)})),
+        ..Material::dielectric(1.0, 0.0)
+    });
+    scene.add_object(Object::Sphere(Sphere{radius: 0.4, center: Vec3::new(-1., 0.2, 1.), material: bubble}));
+    // a wire fence from a single quad, the gaps between the checkers are cut out.
+    let wire = scene.add_material(Material{
+        opacity: Some(Texture::Checker{cells: (40., 8.), even: Box::new(Texture::gray(1.)), odd: Box::new(Texture::gray(0.))}),
+        ..Material::conductor(Metal::Aluminium, 0.4)
+    });
+    scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(-6., -2.5, -6.), u: Vec3::new(5., 0., 0.), v: Vec3::new(0., 1., 0.), material: wire})));
+    // a small bright lamp and a big dim one, both showing up as highlights on the gold.
+    let bulb = scene.add_material(Material::emissive(Vec3::new(60., 50., 40.)));
+    let globe = scene.add_material(Material::emissive(Vec3::new(2., 2., 2.5)));
+    scene.add_object(Object::Sphere(Sphere{radius: 0.05, center: Vec3::new(1.2, 1.5, 1.2), material: bulb}));
+    scene.add_object(Object::Sphere(Sphere{radius: 0.6, center: Vec3::new(-2., 3., 1.5), material: globe}));
+    scene.add_object(Object::Triangle(Box::new(Triangle::new([Vec3::new(3.5, -1., -2.), Vec3::new(4.5, -1., -2.5), Vec3::new(4., 0.5, -2.2)], orange))));
+    scene.add_object(Object::Sphere(Sphere{radius: 100., center: Vec3::new(0., -102.5, 0.), material: green}));
+    // `--output <file>` renders a single image into the file instead of opening the window, with
+    // `--integrator <name>` and `--samples <n>` for how.
+    let mut args = std::env::args().skip(1);
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--output", Some(path)) => output = Some(path),
+            ("--integrator", Some(name)) => match name.parse() {
+                Ok(integrator) => scene.integrator = integrator,
+                Err(err) => {
+                    println!("ERROR: {}", err);
+                    std::process::exit(1);
+                }
+            },
+            ("--samples", Some(samples)) => match samples.parse() {
+                Ok(0) => {
+                    println!("ERROR: --samples must be at least 1");
+                    std::process::exit(1);
+                }
+                Ok(samples) => scene.samples_per_pixel = samples,
+                Err(err) => {
+                    println!("ERROR: --samples {}: {}", samples, err);
+                    std::process::exit(1);
+                }
+            },
+            _ => {
+                println!("usage: ray-tracing-weekend-rs [--output <file>] [--integrator <name>] [--samples <n>]");
+                std::process::exit(1);
+            }
+        }
+    }
+    match output {
+        Some(path) => {
+            if let Err(err) = scene.render_to_file(&path) {
+                println!("ERROR: render_to_file: {}", err);
+                std::process::exit(1);
+            }
+        }
+        None => window.render_loop(scene),
+    }
 
 }
@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use std::{ops::{Mul, Sub, Add, Div, Neg}, fmt::Display};
+use std::{ops::{Mul, Sub, Add, Div}, fmt::Display};
 use num::{Zero, One};
 
 use crate::vec3::Vec3;
@@ -14,7 +14,7 @@ pub struct Mat3<T> {
 
 impl<T: Copy> Mat3<T> {
     pub fn new(data: [T; 9]) -> Self {
-        Self{data: data}
+        Self{data}
     }
     pub fn get(&self, y: usize, x: usize) -> T {
         self.data[y * 3 + x]
@@ -80,7 +80,7 @@ fn right_pad(mut s:String, width: usize, c: &str) -> String {
         s
     } else {
         for _ in 0..(width - s.len()) {
-            s = s + c;
+            s += c;
         } 
         s
     }
@@ -115,6 +115,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn mat_mul() {
         let a = Mat3::new([
             1,2,3,4,5,6,7,8,9,
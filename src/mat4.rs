@@ -3,7 +3,7 @@
 use std::{ops::{Mul, Sub, Add, Div, Neg}, fmt::Display};
 use num::{Zero, One};
 
-use crate::{quat::Quat, vec4::Vec4};
+use crate::{quat::Quat, vec3::Vec3, vec4::Vec4};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Mat4<T> {
@@ -12,14 +12,22 @@ pub struct Mat4<T> {
 
 impl<T: Copy> Mat4<T> {
     pub fn new(data: [T; 16]) -> Self {
-        Self{data: data}
+        Self{data}
     }
     pub fn get(&self, y: usize, x: usize) -> T {
         self.data[y * 4 + x]
     }
+
+    pub fn transpose(&self) -> Self {
+        Self::new(std::array::from_fn(|i| self.get(i % 4, i / 4)))
+    }
 }
 
 impl<T: Copy + Add<Output=T> + Mul<Output=T> + Neg<Output=T> + Sub<Output=T> + Div<Output=T> +  PartialEq + Zero + One> Mat4<T> {
+    pub fn identity() -> Self {
+        Self::new(std::array::from_fn(|i| if i % 5 == 0 { T::one() } else { T::zero() }))
+    }
+
     pub fn mat_mul(&self, other: &Self) -> Self {
         Self::new(
             [
@@ -188,8 +196,8 @@ impl<T: Copy + Add<Output=T> + Mul<Output=T> + Neg<Output=T> + Sub<Output=T> + D
         } else {
             det = T::one() / det;
         
-            for i in 0..16 {
-                inv[i] = inv[i] * det;                
+            for value in inv.iter_mut() {
+                *value = *value * det;
             }
         
             Some(Mat4::new(inv))
@@ -199,13 +207,59 @@ impl<T: Copy + Add<Output=T> + Mul<Output=T> + Neg<Output=T> + Sub<Output=T> + D
 
 }
 
+// affine transforms act on column vectors (x, y, z, 1), the translation lives in the last column.
+impl Mat4<f64> {
+    pub fn translation(offset: Vec3<f64>) -> Self {
+        Self::new([
+            1., 0., 0., offset.x,
+            0., 1., 0., offset.y,
+            0., 0., 1., offset.z,
+            0., 0., 0., 1.,
+        ])
+    }
+
+    pub fn scaling(factors: Vec3<f64>) -> Self {
+        Self::new([
+            factors.x, 0., 0., 0.,
+            0., factors.y, 0., 0.,
+            0., 0., factors.z, 0.,
+            0., 0., 0., 1.,
+        ])
+    }
+
+    pub fn rotation(unit_quat: &Quat<f64>) -> Self {
+        let (w, x, y, z) = (unit_quat.w, unit_quat.v.x, unit_quat.v.y, unit_quat.v.z);
+        Self::new([
+            1. - 2. * (y * y + z * z), 2. * (x * y - w * z), 2. * (x * z + w * y), 0.,
+            2. * (x * y + w * z), 1. - 2. * (x * x + z * z), 2. * (y * z - w * x), 0.,
+            2. * (x * z - w * y), 2. * (y * z + w * x), 1. - 2. * (x * x + y * y), 0.,
+            0., 0., 0., 1.,
+        ])
+    }
+
+    pub fn transform_point(&self, p: &Vec3<f64>) -> Vec3<f64> {
+        Vec3::new(
+            self.get(0, 0) * p.x + self.get(0, 1) * p.y + self.get(0, 2) * p.z + self.get(0, 3),
+            self.get(1, 0) * p.x + self.get(1, 1) * p.y + self.get(1, 2) * p.z + self.get(1, 3),
+            self.get(2, 0) * p.x + self.get(2, 1) * p.y + self.get(2, 2) * p.z + self.get(2, 3),
+        )
+    }
+
+    pub fn transform_vector(&self, v: &Vec3<f64>) -> Vec3<f64> {
+        Vec3::new(
+            self.get(0, 0) * v.x + self.get(0, 1) * v.y + self.get(0, 2) * v.z,
+            self.get(1, 0) * v.x + self.get(1, 1) * v.y + self.get(1, 2) * v.z,
+            self.get(2, 0) * v.x + self.get(2, 1) * v.y + self.get(2, 2) * v.z,
+        )
+    }
+}
 
 fn right_pad(mut s:String, width: usize, c: &str) -> String {
     if s.len() >= width {
         s
     } else {
         for _ in 0..(width - s.len()) {
-            s = s + c;
+            s += c;
         } 
         s
     }
@@ -281,4 +335,30 @@ mod tests {
 
         assert_eq!(c, Quat::new(30, 70, 30, 70));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn transpose() {
+        let a = Mat4::new([
+            1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,
+        ]);
+
+        assert_eq!(a.transpose().data, [1, 5, 9, 13, 2, 6, 10, 14, 3, 7, 11, 15, 4, 8, 12, 16]);
+    }
+
+    #[test]
+    fn affine_inverse() {
+        let axis = Vec3::new(0., 1., 0.);
+        let m = Mat4::translation(Vec3::new(1., 2., 3.))
+            .mat_mul(&Mat4::rotation(&Quat::angle_axis(90.0f64.to_radians(), axis)))
+            .mat_mul(&Mat4::scaling(Vec3::new(2., 2., 2.)));
+        let p = m.transform_point(&Vec3::new(1., 0., 0.));
+        assert!((p - Vec3::new(1., 2., 1.)).length() < 1e-12);
+
+        let back = m.inverse().unwrap().transform_point(&p);
+        assert!((back - Vec3::new(1., 0., 0.)).length() < 1e-12);
+        let identity = m.mat_mul(&m.inverse().unwrap());
+        for (value, expected) in identity.data.iter().zip(Mat4::<f64>::identity().data) {
+            assert!((value - expected).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{bsdf::{Bsdf, Ggx, Metal}, hittable::HitReturn, medium::{PhaseFunction, SubsurfaceMedium}, principled::Principled, spectrum::{ColorMode, Ior}, texture::Texture, thin_film::{Coated, ThinFilm}, vec3::Vec3};
+
+/// index into `Scene::materials`, so objects can share materials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct MaterialId(pub usize);
+
+// how far apart bump map heights are sampled, at most about a texel of a 1k image in uv and at most this
+// far along the surface in world units, whichever is shorter.
+const BUMP_UV_STEP: f64 = 1e-3;
+const BUMP_WORLD_STEP: f64 = 1e-4;
+
+/// bends the shading normal to fake detail the geometry doesn't have.
+#[derive(Clone)]
+pub enum NormalMap {
+    /// tangent space normals stored as 0..1 for -1..1, z out of the surface, like the usual bluish images.
+    TangentSpace(Texture),
+    /// the surface is pushed out by `height` times `strength`, only the slopes of that change the normal.
+    Bump{height: Texture, strength: f64},
+}
+
+/// what the surface is made of, which decides how `albedo` and `roughness` are used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Surface {
+    Diffuse,
+    /// a metal with complex index of refraction `eta + i k`, `albedo` tints its reflection.
+    Conductor{eta: Vec3<f64>, k: Vec3<f64>},
+    /// glass, water and the like, surrounded by air. `albedo` tints what passes through.
+    Dielectric{ior: Ior},
+    /// skin, wax and marble, `albedo` colored after light wanders about `radius` inside. closed objects only.
+    Subsurface{ior: f64, radius: Vec3<f64>},
+    /// the disney / gltf style uber material, `albedo` is its base color.
+    Principled(Principled),
+}
+
+/// every parameter is a texture, a constant one for plain values.
+#[derive(Clone)]
+pub struct Material {
+    pub albedo: Texture,
+    /// 0 is a perfect mirror, 1 fully rough. read by glossy materials.
+    pub roughness: Texture,
+    /// light given off by the surface itself.
+    pub emission: Texture,
+    pub normal_map: Option<NormalMap>,
+    pub surface: Surface,
+    /// an iridescent film over the whole thing, like soap or oil.
+    pub coating: Option<ThinFilm>,
+    /// alpha cutout, the surface isn't there where this is below one half, not even for shadows.
+    pub opacity: Option<Texture>,
+}
+
+impl Material {
+    pub fn diffuse(albedo: impl Into<Texture>) -> Self {
+        Material{albedo: albedo.into(), ..Material::default()}
+    }
+
+    pub fn emissive(emission: impl Into<Texture>) -> Self {
+        Material{albedo: Texture::gray(0.0), emission: emission.into(), ..Material::default()}
+    }
+
+    pub fn conductor(metal: Metal, roughness: f64) -> Self {
+        let (eta, k) = metal.ior();
+        Material{albedo: Texture::gray(1.0), roughness: Texture::gray(roughness), surface: Surface::Conductor{eta, k}, ..Material::default()}
+    }
+
+    pub fn dielectric(ior: impl Into<Ior>, roughness: f64) -> Self {
+        Material{albedo: Texture::gray(1.0), roughness: Texture::gray(roughness), surface: Surface::Dielectric{ior: ior.into()}, ..Material::default()}
+    }
+
+    pub fn subsurface(color: impl Into<Texture>, radius: Vec3<f64>, roughness: f64) -> Self {
+        Material{albedo: color.into(), roughness: Texture::gray(roughness), surface: Surface::Subsurface{ior: 1.4, radius}, ..Material::default()}
+    }
+
+    pub fn principled(base_color: impl Into<Texture>, roughness: f64, principled: Principled) -> Self {
+        Material{albedo: base_color.into(), roughness: Texture::gray(roughness), surface: Surface::Principled(principled), ..Material::default()}
+    }
+
+    /// the textures looked up at the hit, in the local frame of its shading normal, with colors as `mode` carries them.
+    pub fn bsdf(&self, hit_return: &HitReturn, mode: &ColorMode) -> Bsdf {
+        let (uv, position) = (hit_return.uv, hit_return.hit_position);
+        let albedo = mode.color(&self.albedo.evaluate(uv, &position));
+        let roughness = self.roughness.evaluate_scalar(uv, &position).clamp(0.0, 1.0);
+        let distribution = Ggx::from_roughness(roughness);
+        let base = match self.surface {
+            Surface::Diffuse => Bsdf::Lambertian{albedo},
+            Surface::Conductor{eta, k} => Bsdf::Conductor{distribution, eta: mode.measured(&eta), k: mode.measured(&k), tint: albedo},
+            // normals face the ray, so which side is the glass depends on where it came from.
+            Surface::Dielectric{ior} => {
+                let ior = mode.ior(&ior);
+                Bsdf::Dielectric{distribution, eta: if hit_return.front_face { ior } else { 1.0 / ior }, tint: albedo}
+            }
+            // the color comes from the walk inside.
+            Surface::Subsurface{ior, ..} => Bsdf::Subsurface{distribution, eta: if hit_return.front_face { ior } else { 1.0 / ior }},
+            Surface::Principled(principled) => Bsdf::Principled(principled.bsdf(albedo, roughness, hit_return.front_face)),
+        };
+        let Some(coating) = &self.coating else {
+            return base;
+        };
+        let thickness = coating.thickness.evaluate_scalar(uv, &position).max(0.0);
+        Bsdf::Coated(Box::new(Coated::new(thickness, coating.ior, coating.substrate_ior, *mode, base)))
+    }
+
+    /// the medium a path refracting in at `hit_return` walks through, `None` unless there is subsurface scattering.
+    pub fn subsurface_medium(&self, hit_return: &HitReturn, mode: &ColorMode) -> Option<SubsurfaceMedium> {
+        let Surface::Subsurface{radius, ..} = self.surface else {
+            return None;
+        };
+        let color = mode.color(&self.albedo.evaluate(hit_return.uv, &hit_return.hit_position));
+        Some(SubsurfaceMedium::new(&color, &mode.measured(&radius), PhaseFunction::Isotropic))
+    }
+
+    /// whether rays go right through the surface at the hit.
+    pub fn cut_out(&self, hit_return: &HitReturn) -> bool {
+        self.opacity.as_ref().is_some_and(|opacity| opacity.evaluate_scalar(hit_return.uv, &hit_return.hit_position) < 0.5)
+    }
+
+    /// whether light of different wavelengths leaves the surface in different directions.
+    pub fn disperses(&self) -> bool {
+        matches!(self.surface, Surface::Dielectric{ior} if ior.is_dispersive())
+    }
+
+    /// the hit's shading normal bent by the normal map, never past the geometric one.
+    pub fn shading_normal(&self, hit_return: &HitReturn) -> Vec3<f64> {
+        let normal = hit_return.shading_normal;
+        let Some(normal_map) = &self.normal_map else {
+            return normal;
+        };
+        // the tangent is perpendicular to the geometric normal, which the shading normal can differ from.
+        let tangent = hit_return.tangent - normal.clone().scale(normal.dot(&hit_return.tangent));
+        let tangent = if tangent.length_squared() > 1e-12 { tangent.normalize() } else { normal.orthonormal_basis().0 };
+        let bitangent = normal.cross(&tangent);
+        let (uv, position) = (hit_return.uv, hit_return.hit_position);
+
+        let perturbed = match normal_map {
+            NormalMap::TangentSpace(texture) => {
+                let m = texture.evaluate(uv, &position).scale(2.0).shift(-1.0);
+                tangent.clone().scale(m.x) + bitangent.clone().scale(m.y) + normal.clone().scale(m.z)
+            }
+            NormalMap::Bump{height, strength} => {
+                // the surface pushed out along its outward normal, as in pbrt's bump mapping. each step moves
+                // the uv and the point together, so uv and solid textures slope the same.
+                let outward = if hit_return.front_face { normal } else { -normal };
+                let flatten = |d: Vec3<f64>| d - outward.clone().scale(outward.dot(&d));
+                let (dpdu, dpdv) = (flatten(hit_return.dpdu), flatten(hit_return.dpdv));
+                let h0 = height.evaluate_scalar(uv, &position);
+                let displace = |dp: &Vec3<f64>, uv_step: (f64, f64), du: f64| {
+                    let h = height.evaluate_scalar((uv.0 + uv_step.0, uv.1 + uv_step.1), &(position + dp.clone().scale(du)));
+                    *dp + outward.clone().scale((h - h0) / du * strength)
+                };
+                let step = |dp: &Vec3<f64>| BUMP_UV_STEP.min(BUMP_WORLD_STEP / dp.length());
+                let (du, dv) = (step(&dpdu), step(&dpdv));
+                let bumped = displace(&dpdu, (du, 0.), du).cross(&displace(&dpdv, (0., dv), dv));
+                // parameterizations of either handedness, and back faces, see the bump from the outside.
+                let bumped = if dpdu.cross(&dpdv).dot(&outward) < 0.0 { -bumped } else { bumped };
+                if hit_return.front_face { bumped } else { -bumped }
+            }
+        };
+        if perturbed.length_squared() > 0.0 && perturbed.dot(&hit_return.normal) > 0.0 {
+            perturbed.normalize()
+        } else {
+            normal
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material{albedo: Texture::gray(0.8), roughness: Texture::gray(1.0), emission: Texture::gray(0.0), normal_map: None, surface: Surface::Diffuse, coating: None, opacity: None}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::{noise::Perlin, texture::{ImageTexture, NoisePattern, WrapMode}};
+    use std::sync::Arc;
+    use super::*;
+
+    // facing up at the origin, u along x and v along -z, a unit of each a unit long.
+    fn hit(uv: (f64, f64)) -> HitReturn {
+        let hit_return = HitReturn::new(Vec3::new(0., 0., 0.), Vec3::new(0., 1., 0.), 1.0, true, MaterialId::default());
+        HitReturn{uv, tangent: Vec3::new(1., 0., 0.), dpdu: Vec3::new(1., 0., 0.), dpdv: Vec3::new(0., 0., -1.), ..hit_return}
+    }
+
+    fn with_map(normal_map: NormalMap) -> Material {
+        Material{normal_map: Some(normal_map), ..Material::default()}
+    }
+
+    #[test]
+    fn tangent_space() {
+        // the flat color leaves the normal alone.
+        let flat = with_map(NormalMap::TangentSpace(Texture::Solid(Vec3::new(0.5, 0.5, 1.0))));
+        assert!((flat.shading_normal(&hit((0.3, 0.3))) - Vec3::new(0., 1., 0.)).length() < 1e-12);
+
+        // 45 degrees toward the tangent.
+        let leaning = with_map(NormalMap::TangentSpace(Texture::Solid(Vec3::new(1.0, 0.5, 1.0))));
+        let normal = leaning.shading_normal(&hit((0.3, 0.3)));
+        assert!((normal - Vec3::new(1., 1., 0.).normalize()).length() < 1e-12);
+
+        // pointing into the surface falls back to the unperturbed normal.
+        let inward = with_map(NormalMap::TangentSpace(Texture::Solid(Vec3::new(0.5, 0.5, 0.0))));
+        assert!((inward.shading_normal(&hit((0.3, 0.3))) - Vec3::new(0., 1., 0.)).length() < 1e-12);
+    }
+
+    #[test]
+    fn bump() {
+        // height rising along u with a slope of 1 tilts the normal back against u.
+        let ramp = ImageTexture::new(2, 1, vec![Vec3::new(0., 0., 0.), Vec3::new(0.5, 0.5, 0.5)], WrapMode::Clamp);
+        let bumpy = with_map(NormalMap::Bump{height: Texture::Image(Arc::new(ramp)), strength: 1.0});
+        let normal = bumpy.shading_normal(&hit((0.5, 0.5)));
+        assert_float_absolute_eq!(normal.x, -0.5f64.sqrt(), 1e-9);
+        assert_float_absolute_eq!(normal.y, 0.5f64.sqrt(), 1e-9);
+        assert_float_absolute_eq!(normal.z, 0.0, 1e-9);
+
+        // constant height changes nothing.
+        let flat = with_map(NormalMap::Bump{height: Texture::gray(0.7), strength: 10.0});
+        assert!((flat.shading_normal(&hit((0.5, 0.5))) - Vec3::new(0., 1., 0.)).length() < 1e-12);
+
+        // solid heights slope per world unit along the surface, wherever the uv is.
+        let height = Texture::Noise{noise: Perlin::new(1), pattern: NoisePattern::Perlin, frequency: 3.0, low: Vec3::new(0., 0., 0.), high: Vec3::new(1., 1., 1.)};
+        let h = 1e-6;
+        let slope = |along: Vec3<f64>| (height.evaluate_scalar((0., 0.), &along.clone().scale(h)) - height.evaluate_scalar((0., 0.), &along.clone().scale(-h))) / (2.0 * h);
+        let (slope_u, slope_v) = (slope(Vec3::new(1., 0., 0.)), slope(Vec3::new(0., 0., -1.)));
+        assert!(slope_u.abs() + slope_v.abs() > 0.1);
+        let expected = Vec3::new(-slope_u, 1., slope_v).normalize();
+        let solid = with_map(NormalMap::Bump{height, strength: 1.0});
+        for uv in [(0.5, 0.5), (0.1, 0.9)] {
+            assert!((solid.shading_normal(&hit(uv)) - expected).length() < 1e-3, "{:?} against {expected:?}", solid.shading_normal(&hit(uv)));
+        }
+        // and however long a unit of uv is.
+        let stretched = HitReturn{dpdu: Vec3::new(10., 0., 0.), dpdv: Vec3::new(0., 0., -0.1), ..hit((0.5, 0.5))};
+        assert!((solid.shading_normal(&stretched) - expected).length() < 1e-3);
+    }
+
+    #[test]
+    fn bump_follows_the_parameterization() {
+        let ramp = ImageTexture::new(2, 1, vec![Vec3::new(0., 0., 0.), Vec3::new(0.5, 0.5, 0.5)], WrapMode::Clamp);
+        let bumpy = with_map(NormalMap::Bump{height: Texture::Image(Arc::new(ramp)), strength: 1.0});
+        let tilted = |dpdu: Vec3<f64>, dpdv: Vec3<f64>, front_face: bool| {
+            let hit_return = HitReturn{dpdu, dpdv, ..hit((0.5, 0.5))};
+            let hit_return = if front_face { hit_return } else { HitReturn{normal: -hit_return.normal, shading_normal: -hit_return.normal, front_face, ..hit_return} };
+            bumpy.shading_normal(&hit_return)
+        };
+        let (along_x, along_z) = (Vec3::new(1., 0., 0.), Vec3::new(0., 0., -1.));
+        // twice as big, half as steep.
+        assert!((tilted(along_x.clone().scale(2.0), along_z, true) - Vec3::new(-0.5, 1., 0.).normalize()).length() < 1e-9);
+        // uv mirrored, the ramp rises the other way.
+        assert!((tilted(-along_x, along_z, true) - Vec3::new(1., 1., 0.).normalize()).length() < 1e-9);
+        assert!((tilted(along_x, -along_z, true) - Vec3::new(-1., 1., 0.).normalize()).length() < 1e-9);
+        // seen from below the same bumps are dents, the normal is the one above turned over.
+        assert!((tilted(along_x, along_z, false) - Vec3::new(1., -1., 0.).normalize()).length() < 1e-9);
+    }
+
+    #[test]
+    fn no_map() {
+        let mut hit_return = hit((0.5, 0.5));
+        hit_return.shading_normal = Vec3::new(0.6, 0.8, 0.);
+        assert_eq!(Material::default().shading_normal(&hit_return), Vec3::new(0.6, 0.8, 0.));
+    }
+
+    #[test]
+    fn cut_out() {
+        let leaf = Material{opacity: Some(Texture::Checker{cells: (2., 1.), even: Box::new(Texture::gray(0.)), odd: Box::new(Texture::gray(1.))}), ..Material::default()};
+        assert!(leaf.cut_out(&hit((0.25, 0.5))));
+        assert!(!leaf.cut_out(&hit((0.75, 0.5))));
+        assert!(!Material::default().cut_out(&hit((0.25, 0.5))));
+    }
+
+    #[test]
+    fn bsdfs() {
+        assert_eq!(Material::diffuse(Vec3::new(0.2, 0.4, 0.6)).bsdf(&hit((0., 0.)), &ColorMode::Rgb), Bsdf::Lambertian{albedo: Vec3::new(0.2, 0.4, 0.6)});
+        let Bsdf::Conductor{distribution, ..} = Material::conductor(Metal::Gold, 0.5).bsdf(&hit((0., 0.)), &ColorMode::Rgb) else {
+            panic!("gold isn't a conductor");
+        };
+        assert_eq!(distribution, Ggx{alpha_x: 0.25, alpha_y: 0.25});
+        let glass = Material::dielectric(1.5, 0.0);
+        assert!(matches!(glass.bsdf(&hit((0., 0.)), &ColorMode::Rgb), Bsdf::Dielectric{eta, ..} if eta == 1.5));
+        let leaving = HitReturn{front_face: false, ..hit((0., 0.))};
+        assert!(matches!(glass.bsdf(&leaving, &ColorMode::Rgb), Bsdf::Dielectric{eta, ..} if eta == 1.0 / 1.5));
+        assert!(Material::dielectric(Ior::BK7, 0.0).disperses() && !glass.disperses());
+        let wax = Material::subsurface(Vec3::new(0.9, 0.8, 0.6), Vec3::new(0.5, 0.3, 0.2), 0.3);
+        assert!(matches!(wax.bsdf(&hit((0., 0.)), &ColorMode::Rgb), Bsdf::Subsurface{eta, ..} if eta == 1.4));
+        assert_eq!(wax.subsurface_medium(&hit((0., 0.)), &ColorMode::Rgb).unwrap().sigma_t, Vec3::new(2., 1. / 0.3, 5.));
+        assert!(glass.subsurface_medium(&hit((0., 0.)), &ColorMode::Rgb).is_none());
+        assert!(matches!(Material::principled(Vec3::new(1., 1., 1.), 0.5, Principled::default()).bsdf(&leaving, &ColorMode::Rgb), Bsdf::Principled(_)));
+        let bubble = Material{coating: Some(ThinFilm::soap(Texture::gray(300.0))), ..Material::dielectric(1.0, 0.0)};
+        let Bsdf::Coated(coated) = bubble.bsdf(&hit((0., 0.)), &ColorMode::Rgb) else {
+            panic!("the film is missing");
+        };
+        assert_eq!(coated.thickness, 300.0);
+        assert!(matches!(coated.base, Bsdf::Dielectric{eta, ..} if eta == 1.0));
+    }
+}
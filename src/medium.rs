@@ -0,0 +1,610 @@
+use std::{io, path::Path};
+
+use rand::Rng;
+
+use crate::{aabb::Aabb, hittable::{HitReturn, Hittable}, material::{Material, MaterialId}, noise::Perlin, spectrum::ColorMode, ray::Ray, scene::Object, vec3::Vec3};
+
+/// how light traveling through a medium gets redirected when it scatters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PhaseFunction {
+    Isotropic,
+    /// `g` in -1..1, positive values scatter forward, negative ones back toward the light.
+    HenyeyGreenstein{g: f64},
+}
+
+impl PhaseFunction {
+    /// density of scattering into `direction_out` for light traveling along `direction_in`, both unit length.
+    pub fn evaluate(&self, direction_in: &Vec3<f64>, direction_out: &Vec3<f64>) -> f64 {
+        let isotropic = 1.0 / (4.0 * std::f64::consts::PI);
+        match self {
+            PhaseFunction::Isotropic => isotropic,
+            PhaseFunction::HenyeyGreenstein{g} => {
+                let cos_theta = direction_in.dot(direction_out);
+                let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+                isotropic * (1.0 - g * g) / (denominator * denominator.sqrt())
+            }
+        }
+    }
+
+    /// a new direction for light along the unit `direction_in`, distributed exactly like `evaluate`.
+    pub fn sample(&self, direction_in: &Vec3<f64>, rng: &mut impl Rng) -> Vec3<f64> {
+        let xi: f64 = rng.gen();
+        let cos_theta = match self {
+            PhaseFunction::HenyeyGreenstein{g} if g.abs() > 1e-3 => {
+                let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+                (1.0 + g * g - s * s) / (2.0 * g)
+            }
+            _ => 1.0 - 2.0 * xi,
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        let (tangent, bitangent) = direction_in.orthonormal_basis();
+        tangent.clone().scale(sin_theta * phi.cos())
+            + bitangent.clone().scale(sin_theta * phi.sin())
+            + direction_in.clone().scale(cos_theta)
+    }
+}
+
+/// what a ray scattering inside a medium sees in place of a surface material.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scattering {
+    pub albedo: Vec3<f64>,
+    pub phase: PhaseFunction,
+}
+
+impl Scattering {
+    /// the hit record for scattering at `t` along `ray`. there is no surface, the normal just faces the ray.
+    pub fn hit_at(&self, ray: &Ray, t: f64) -> HitReturn {
+        let hit_position = ray.origin + ray.direction.clone().scale(t);
+        HitReturn{medium: Some(*self), ..HitReturn::new(hit_position, -ray.direction.normalize(), t, true, MaterialId::default())}
+    }
+}
+
+/// the medium inside a subsurface scattering object, with coefficients per color channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubsurfaceMedium {
+    /// extinction per unit length.
+    pub sigma_t: Vec3<f64>,
+    /// the chance of scattering rather than being absorbed at each collision.
+    pub albedo: Vec3<f64>,
+    pub phase: PhaseFunction,
+}
+
+impl SubsurfaceMedium {
+    /// from the color after multiple scattering and the mean distance, inverting van de hulst's relation.
+    pub fn new(color: &Vec3<f64>, radius: &Vec3<f64>, phase: PhaseFunction) -> Self {
+        let single_scattering = |color: f64| {
+            let color = color.clamp(0.0, 1.0);
+            1.0 - (4.09712 + 4.20863 * color - (9.59217 + 41.6808 * color + 17.7126 * color * color).sqrt()).powi(2)
+        };
+        SubsurfaceMedium{
+            sigma_t: Vec3::new(1.0 / radius.x.max(1e-9), 1.0 / radius.y.max(1e-9), 1.0 / radius.z.max(1e-9)),
+            albedo: Vec3::new(single_scattering(color.x), single_scattering(color.y), single_scattering(color.z)),
+            phase,
+        }
+    }
+
+    /// how far a path goes before it collides, following one channel picked at random.
+    pub fn sample_distance(&self, rng: &mut impl Rng) -> f64 {
+        let sigma_t = [self.sigma_t.x, self.sigma_t.y, self.sigma_t.z][rng.gen_range(0..3)];
+        -(1.0 - rng.gen::<f64>()).ln() / sigma_t
+    }
+
+    /// the throughput factor after `distance` from `sample_distance`, averaged over the channels' chances.
+    pub fn weight(&self, distance: f64, scattered: bool) -> Vec3<f64> {
+        let transmittance = Vec3::new((-self.sigma_t.x * distance).exp(), (-self.sigma_t.y * distance).exp(), (-self.sigma_t.z * distance).exp());
+        if scattered {
+            let density = self.sigma_t * transmittance;
+            let pdf = (density.x + density.y + density.z) / 3.0;
+            if pdf <= 0.0 {
+                return Vec3::new(0., 0., 0.);
+            }
+            (density * self.albedo).scale(1.0 / pdf)
+        } else {
+            let probability = (transmittance.x + transmittance.y + transmittance.z) / 3.0;
+            if probability <= 0.0 {
+                return Vec3::new(0., 0., 0.);
+            }
+            transmittance.clone().scale(1.0 / probability)
+        }
+    }
+
+    /// one step of a walk, the throughput factor and where it scattered if before `t_surface`.
+    pub fn step(&self, ray: &Ray, t_surface: f64, rng: &mut impl Rng) -> (Vec3<f64>, Option<f64>) {
+        let speed = ray.direction.length();
+        let t = self.sample_distance(rng) / speed;
+        if t < t_surface {
+            (self.weight(t * speed, true), Some(t))
+        } else {
+            (self.weight(t_surface * speed, false), None)
+        }
+    }
+}
+
+/// a path inside a subsurface scattering object, walking through its medium.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubsurfaceWalk {
+    pub medium: SubsurfaceMedium,
+    // the object the path went in through, and objects inside it the path has gone into since, like
+    // embedded glass. each by its index and material, meshes of loose triangles share only the latter.
+    entered: (usize, MaterialId),
+    nested: Vec<(usize, MaterialId)>,
+}
+
+impl SubsurfaceWalk {
+    /// `walk` after a path goes on in `direction` from `hit_return`, only leaving where it entered ends it.
+    pub fn cross(walk: Option<Self>, hit_return: &HitReturn, direction: &Vec3<f64>, material: &Material, mode: &ColorMode) -> Option<Self> {
+        // normals face the ray, only light going through the surface goes the other way.
+        if direction.dot(&hit_return.normal) >= 0.0 {
+            return walk;
+        }
+        let surface = (hit_return.object_id, hit_return.material);
+        let Some(mut walk) = walk else {
+            return material.subsurface_medium(hit_return, mode).filter(|_| hit_return.front_face).map(|medium| SubsurfaceWalk{medium, entered: surface, nested: vec![]});
+        };
+        let same = |(object, material): &(usize, MaterialId)| *object == surface.0 || *material == surface.1;
+        if hit_return.front_face {
+            walk.nested.push(surface);
+        } else if let Some(i) = walk.nested.iter().rposition(same) {
+            walk.nested.remove(i);
+        } else if same(&walk.entered) {
+            return None;
+        }
+        Some(walk)
+    }
+
+    /// the medium the path is in, `None` while it is inside something embedded in the object.
+    pub fn medium(&self) -> Option<&SubsurfaceMedium> {
+        self.nested.is_empty().then_some(&self.medium)
+    }
+}
+
+/// samples how far light travels through a medium of `density` (extinction per unit length) before scattering.
+fn free_flight_distance(density: f64, rng: &mut impl Rng) -> f64 {
+    -(1.0 - rng.gen::<f64>()).ln() / density
+}
+
+/// a constant density medium in a closed object, hit where a freshly sampled scattering happens.
+pub struct Volume {
+    pub boundary: Object,
+    /// extinction per unit length.
+    pub density: f64,
+    /// fraction of the extinguished light that is scattered rather than absorbed, per channel.
+    pub albedo: Vec3<f64>,
+    pub phase: PhaseFunction,
+}
+
+impl Hittable for Volume {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        let length = ray.direction.length();
+        // the boundary can be concave, so it may take several trips through the medium.
+        for interval in self.boundary.intervals(ray, t_min, t_max) {
+            let enter = interval.t_enter().max(t_min);
+            let exit = interval.t_exit().min(t_max);
+            if exit <= enter {
+                continue;
+            }
+            let distance = free_flight_distance(self.density, &mut rand::thread_rng());
+            // free flights are memoryless, a fresh sample per trip is still exact.
+            if distance < (exit - enter) * length {
+                let t = enter + distance / length;
+                return Some(Scattering{albedo: self.albedo, phase: self.phase}.hit_at(ray, t));
+            }
+        }
+        None
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let length = ray.direction.length();
+        let inside: f64 = self.boundary.intervals(ray, t_min, t_max)
+            .iter()
+            .map(|interval| (interval.t_exit().min(t_max) - interval.t_enter().max(t_min)).max(0.0) * length)
+            .sum();
+        (-self.density * inside).exp()
+    }
+
+    fn is_medium(&self) -> bool {
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+/// fog filling the whole scene, densest at `base_height` and thinning out exponentially above it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeightFog {
+    /// extinction per unit length at `base_height`.
+    pub density: f64,
+    /// how quickly the density drops with height, per unit.
+    pub falloff: f64,
+    pub base_height: f64,
+    pub albedo: Vec3<f64>,
+    pub phase: PhaseFunction,
+}
+
+impl HeightFog {
+    fn density_at(&self, y: f64) -> f64 {
+        self.density * (-self.falloff * (y - self.base_height)).exp()
+    }
+
+    /// integral of the density along `distance` units of the unit `direction` from `origin`.
+    pub fn optical_depth(&self, origin: &Vec3<f64>, direction: &Vec3<f64>, distance: f64) -> f64 {
+        let rate = self.falloff * direction.y;
+        let start = self.density_at(origin.y);
+        if rate.abs() < 1e-9 {
+            return start * distance;
+        }
+        if distance.is_infinite() {
+            return if rate > 0.0 { start / rate } else { f64::INFINITY };
+        }
+        start * (1.0 - (-rate * distance).exp()) / rate
+    }
+
+    pub fn transmittance(&self, origin: &Vec3<f64>, direction: &Vec3<f64>, distance: f64) -> f64 {
+        (-self.optical_depth(origin, direction, distance)).exp()
+    }
+
+    /// where light scatters along the ray, `None` past `t_max`, inverting the optical depth in closed form.
+    pub fn sample_scatter(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut impl Rng) -> Option<f64> {
+        let length = ray.direction.length();
+        let direction = ray.direction.normalize();
+        let origin = ray.origin + ray.direction.clone().scale(t_min);
+        let target = free_flight_distance(1.0, rng);
+
+        let rate = self.falloff * direction.y;
+        let start = self.density_at(origin.y);
+        let distance = if rate.abs() < 1e-9 {
+            target / start
+        } else {
+            // start * (1 - exp(-rate * s)) / rate = target
+            let k = target * rate / start;
+            if k >= 1.0 {
+                return None;
+            }
+            -(1.0 - k).ln() / rate
+        };
+        let t = t_min + distance / length;
+        (t < t_max).then_some(t)
+    }
+}
+
+/// densities on a regular grid spanning `bounds`, sampled with trilinear interpolation.
+pub struct DensityGrid {
+    pub resolution: (usize, usize, usize),
+    /// x changes fastest, then y, then z.
+    pub values: Vec<f64>,
+    pub bounds: Aabb,
+    max: f64,
+}
+
+impl DensityGrid {
+    pub fn new(resolution: (usize, usize, usize), values: Vec<f64>, bounds: Aabb) -> Self {
+        assert_eq!(values.len(), resolution.0 * resolution.1 * resolution.2);
+        let max = values.iter().fold(0.0f64, |max, value| max.max(*value));
+        DensityGrid{resolution, values, bounds, max}
+    }
+
+    /// reads a mitsuba `.vol` grid of 32 bit floats, only the first channel is used.
+    pub fn load_mitsuba_vol(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        if bytes.len() < 48 || &bytes[0..3] != b"VOL" || bytes[3] != 3 {
+            return Err(invalid("not a version 3 mitsuba volume"));
+        }
+        let int = |offset: usize| i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let float = |offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as f64;
+        if int(4) != 1 {
+            return Err(invalid("only float32 volumes are supported"));
+        }
+        let (x, y, z, channels) = (int(8), int(12), int(16), int(20));
+        if x <= 0 || y <= 0 || z <= 0 || channels <= 0 {
+            return Err(invalid("bad volume resolution"));
+        }
+        let (x, y, z, channels) = (x as usize, y as usize, z as usize, channels as usize);
+        let count = x * y * z;
+        if bytes.len() < 48 + count * channels * 4 {
+            return Err(invalid("volume data is truncated"));
+        }
+        let bounds = Aabb::new(Vec3::new(float(24), float(28), float(32)), Vec3::new(float(36), float(40), float(44)));
+        let values = (0..count).map(|i| float(48 + i * channels * 4)).collect();
+        Ok(DensityGrid::new((x, y, z), values, bounds))
+    }
+
+    fn value(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.values[x + self.resolution.0 * (y + self.resolution.1 * z)]
+    }
+
+    pub fn density(&self, p: &Vec3<f64>) -> f64 {
+        if !self.bounds.contains(p) {
+            return 0.0;
+        }
+        // voxel centers sit at half integer grid coordinates.
+        let axis = |p: f64, min: f64, max: f64, resolution: usize| {
+            let g = ((p - min) / (max - min) * resolution as f64 - 0.5).clamp(0.0, (resolution - 1) as f64);
+            let i = (g.floor() as usize).min(resolution.saturating_sub(2));
+            (i, (i + 1).min(resolution - 1), g - i as f64)
+        };
+        let (x0, x1, fx) = axis(p.x, self.bounds.min.x, self.bounds.max.x, self.resolution.0);
+        let (y0, y1, fy) = axis(p.y, self.bounds.min.y, self.bounds.max.y, self.resolution.1);
+        let (z0, z1, fz) = axis(p.z, self.bounds.min.z, self.bounds.max.z, self.resolution.2);
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        lerp(
+            lerp(lerp(self.value(x0, y0, z0), self.value(x1, y0, z0), fx), lerp(self.value(x0, y1, z0), self.value(x1, y1, z0), fx), fy),
+            lerp(lerp(self.value(x0, y0, z1), self.value(x1, y0, z1), fx), lerp(self.value(x0, y1, z1), self.value(x1, y1, z1), fx), fy),
+            fz,
+        )
+    }
+}
+
+pub enum DensityField {
+    Grid(DensityGrid),
+    /// fbm noise remapped to 0..1, with `frequency` noise features per unit.
+    Noise{noise: Perlin, frequency: f64, octaves: u32},
+}
+
+impl DensityField {
+    pub fn density(&self, p: &Vec3<f64>) -> f64 {
+        match self {
+            DensityField::Grid(grid) => grid.density(p),
+            DensityField::Noise{noise, frequency, octaves} => (0.5 + 0.5 * noise.fbm(&p.clone().scale(*frequency), *octaves)).clamp(0.0, 1.0),
+        }
+    }
+
+    /// an upper bound of `density` anywhere.
+    pub fn max_density(&self) -> f64 {
+        match self {
+            DensityField::Grid(grid) => grid.max,
+            DensityField::Noise{..} => 1.0,
+        }
+    }
+}
+
+/// a medium of varying density, delta tracking for scattering and ratio tracking for shadow rays.
+pub struct HeterogeneousVolume {
+    pub bounds: Aabb,
+    pub field: DensityField,
+    /// extinction per unit length where the field is 1.
+    pub density_scale: f64,
+    pub albedo: Vec3<f64>,
+    pub phase: PhaseFunction,
+}
+
+impl HeterogeneousVolume {
+    /// fills the bounds of the grid.
+    pub fn from_grid(grid: DensityGrid, density_scale: f64, albedo: Vec3<f64>, phase: PhaseFunction) -> Self {
+        HeterogeneousVolume{bounds: grid.bounds, field: DensityField::Grid(grid), density_scale, albedo, phase}
+    }
+
+    fn majorant(&self) -> f64 {
+        self.density_scale * self.field.max_density()
+    }
+
+    /// walks tentative collisions at the majorant rate until `collide`, given the chance one is real, says no.
+    fn track(&self, ray: &Ray, t_min: f64, t_max: f64, mut collide: impl FnMut(f64) -> bool) -> Option<f64> {
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return None;
+        }
+        let (enter, exit) = self.bounds.hit(ray, t_min, t_max)?;
+        let length = ray.direction.length();
+        let mut t = enter;
+        loop {
+            t += free_flight_distance(majorant, &mut rand::thread_rng()) / length;
+            if t >= exit {
+                return None;
+            }
+            let p = ray.origin + ray.direction.clone().scale(t);
+            if !collide(self.density_scale * self.field.density(&p) / majorant) {
+                return Some(t);
+            }
+        }
+    }
+}
+
+impl Hittable for HeterogeneousVolume {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        let mut rng = rand::thread_rng();
+        // delta tracking, a tentative collision is real with probability density / majorant.
+        let t = self.track(ray, t_min, t_max, |real_chance| rng.gen::<f64>() >= real_chance)?;
+        Some(Scattering{albedo: self.albedo, phase: self.phase}.hit_at(ray, t))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        // ratio tracking, every tentative collision removes its chance of being real.
+        let mut transmittance = 1.0;
+        self.track(ray, t_min, t_max, |real_chance| {
+            transmittance *= 1.0 - real_chance;
+            true
+        });
+        transmittance
+    }
+
+    fn is_medium(&self) -> bool {
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use crate::hittable::Sphere;
+    use super::*;
+
+    #[test]
+    fn subsurface_medium() {
+        let color = Vec3::new(0.9, 0.5, 0.1);
+        let medium = SubsurfaceMedium::new(&color, &Vec3::new(1.0, 0.5, 0.25), PhaseFunction::Isotropic);
+        assert_eq!(medium.sigma_t, Vec3::new(1., 2., 4.));
+        // no absorption for white, all of it for black, and more scattering for brighter colors.
+        assert_float_absolute_eq!(SubsurfaceMedium::new(&Vec3::new(1., 1., 1.), &Vec3::new(1., 1., 1.), PhaseFunction::Isotropic).albedo.x, 1.0, 1e-4);
+        assert_float_absolute_eq!(SubsurfaceMedium::new(&Vec3::new(0., 0., 0.), &Vec3::new(1., 1., 1.), PhaseFunction::Isotropic).albedo.x, 0.0, 1e-4);
+        assert!(medium.albedo.x > medium.albedo.y && medium.albedo.y > medium.albedo.z);
+
+        // over a stretch of length 0.5, each channel scatters with its own probability and albedo.
+        let stretch = 0.5;
+        let n = 200_000;
+        let mut rng = StdRng::seed_from_u64(0);
+        let total = (0..n).map(|_| {
+            let distance = medium.sample_distance(&mut rng);
+            medium.weight(distance.min(stretch), distance < stretch)
+        }).fold(Vec3::new(0., 0., 0.), |sum, weight| sum + weight).scale(1.0 / n as f64);
+        for (sigma_t, albedo, estimate) in [(1.0, medium.albedo.x, total.x), (2.0, medium.albedo.y, total.y), (4.0, medium.albedo.z, total.z)] {
+            let passing = (-sigma_t * stretch).exp();
+            assert_float_absolute_eq!(estimate, albedo * (1.0 - passing) + passing, 1e-2);
+        }
+    }
+
+    #[test]
+    fn walks_end_where_they_began() {
+        let wax = Material::subsurface(Vec3::new(0.9, 0.8, 0.6), Vec3::new(0.5, 0.3, 0.2), 0.3);
+        let glass = Material::dielectric(1.5, 0.0);
+        // a path going straight down through the surfaces, hit from outside or from inside.
+        let through = |object: usize, material: usize, front_face: bool| HitReturn{object_id: object, ..HitReturn::new(Vec3::new(0., 0., 0.), Vec3::new(0., 1., 0.), 1.0, front_face, MaterialId(material))};
+        let down = Vec3::new(0., -1., 0.);
+        let mode = ColorMode::Rgb;
+
+        let walk = SubsurfaceWalk::cross(None, &through(0, 1, true), &down, &wax, &mode);
+        assert!(walk.as_ref().is_some_and(|walk| walk.medium().is_some()));
+        // reflecting off the inside changes nothing.
+        assert_eq!(SubsurfaceWalk::cross(walk.clone(), &through(0, 1, false), &-down, &wax, &mode), walk);
+        // into glass embedded in the wax and out again, the walk waits meanwhile.
+        let in_glass = SubsurfaceWalk::cross(walk.clone(), &through(1, 2, true), &down, &glass, &mode);
+        assert!(in_glass.as_ref().is_some_and(|walk| walk.medium().is_none()));
+        assert_eq!(SubsurfaceWalk::cross(in_glass, &through(1, 2, false), &down, &glass, &mode), walk);
+        // out through another triangle of the same mesh.
+        assert_eq!(SubsurfaceWalk::cross(walk.clone(), &through(5, 1, false), &down, &wax, &mode), None);
+        assert_eq!(SubsurfaceWalk::cross(walk, &through(0, 1, false), &down, &wax, &mode), None);
+        // glass starts no walk.
+        assert_eq!(SubsurfaceWalk::cross(None, &through(1, 2, true), &down, &glass, &mode), None);
+    }
+
+    #[test]
+    fn phase_functions_normalized() {
+        // integrate over the sphere of outgoing directions with a fibonacci lattice.
+        let direction_in = Vec3::new(0.3, -0.5, 0.8).normalize();
+        let n = 20000;
+        for phase in [PhaseFunction::Isotropic, PhaseFunction::HenyeyGreenstein{g: 0.7}, PhaseFunction::HenyeyGreenstein{g: -0.4}] {
+            let golden_angle = std::f64::consts::PI * (3.0 - 5.0f64.sqrt());
+            let integral: f64 = (0..n).map(|i| {
+                let z = 1.0 - 2.0 * (i as f64 + 0.5) / n as f64;
+                let r = (1.0 - z * z).sqrt();
+                let phi = golden_angle * i as f64;
+                phase.evaluate(&direction_in, &Vec3::new(r * phi.cos(), r * phi.sin(), z))
+            }).sum::<f64>() * 4.0 * std::f64::consts::PI / n as f64;
+            assert_float_absolute_eq!(integral, 1.0, 1e-3);
+        }
+    }
+
+    #[test]
+    fn henyey_greenstein_sampling_mean_cosine() {
+        // the mean cosine of henyey-greenstein is g.
+        let direction_in = Vec3::new(0., 0., 1.);
+        let mut rng = StdRng::seed_from_u64(0);
+        for g in [0.0, 0.6, -0.3] {
+            let phase = PhaseFunction::HenyeyGreenstein{g};
+            let n = 200000;
+            let mean = (0..n).map(|_| phase.sample(&direction_in, &mut rng).dot(&direction_in)).sum::<f64>() / n as f64;
+            assert_float_absolute_eq!(mean, g, 0.01);
+        }
+    }
+
+    #[test]
+    fn volume_transmittance() {
+        // the fraction of rays making it through a unit sphere of density 0.5 is exp(-0.5 * 2).
+        let volume = Volume{
+            boundary: Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(0., 0., 0.), material: MaterialId::default()}),
+            density: 0.5,
+            albedo: Vec3::new(1., 1., 1.),
+            phase: PhaseFunction::Isotropic,
+        };
+        let ray = Ray{origin: Vec3::new(0., 0., 5.), direction: Vec3::new(0., 0., -2.), time: 0.};
+        // volumes sample from the thread's rng behind `hit`, enough samples keep a share within 0.01 by
+        // four standard errors whatever it is.
+        let n = 40_000;
+        let passed = (0..n).filter(|_| volume.hit(&ray, 0.001, f64::INFINITY).is_none()).count();
+        assert_float_absolute_eq!(passed as f64 / n as f64, (-1.0f64).exp(), 0.01);
+
+        let hit = volume.hit(&Ray{origin: Vec3::new(0., 0., 0.), direction: Vec3::new(1., 0., 0.), time: 0.}, 0.001, 0.5);
+        assert!(hit.is_none_or(|hit| hit.t <= 0.5 && hit.medium.is_some()));
+    }
+
+    #[test]
+    fn height_fog_sampling_matches_transmittance() {
+        let fog = HeightFog{density: 0.3, falloff: 0.8, base_height: 0.0, albedo: Vec3::new(1., 1., 1.), phase: PhaseFunction::Isotropic};
+        let ray = Ray{origin: Vec3::new(0., -1., 0.), direction: Vec3::new(2., 1., 0.), time: 0.};
+        let direction = ray.direction.normalize();
+        let t_max = 3.0;
+        let expected = fog.transmittance(&ray.origin, &direction, t_max * ray.direction.length());
+        let n = 100000;
+        let mut rng = StdRng::seed_from_u64(0);
+        let passed = (0..n).filter(|_| fog.sample_scatter(&ray, 0.0, t_max, &mut rng).is_none()).count();
+        assert_float_absolute_eq!(passed as f64 / n as f64, expected, 0.01);
+
+        // looking up forever only goes through a finite amount of fog.
+        let up = Vec3::new(0., 1., 0.);
+        assert_float_absolute_eq!(fog.optical_depth(&Vec3::new(0., 0., 0.), &up, f64::INFINITY), 0.3 / 0.8, 1e-12);
+        assert_eq!(fog.transmittance(&Vec3::new(0., 0., 0.), &-up, f64::INFINITY), 0.0);
+    }
+    fn cloud(density_scale: f64) -> HeterogeneousVolume {
+        // density rising linearly along x inside the unit cube, resolution 2 puts the voxel centers at 0.25 and 0.75.
+        let grid = DensityGrid::new((2, 1, 1), vec![0.0, 1.0], Aabb::new(Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.)));
+        HeterogeneousVolume::from_grid(grid, density_scale, Vec3::new(1., 1., 1.), PhaseFunction::Isotropic)
+    }
+
+    #[test]
+    fn grid_interpolation() {
+        let volume = cloud(1.0);
+        let DensityField::Grid(grid) = &volume.field else { unreachable!() };
+        assert_float_absolute_eq!(grid.density(&Vec3::new(0.5, 0.5, 0.5)), 0.5, 1e-12);
+        assert_float_absolute_eq!(grid.density(&Vec3::new(0.1, 0.5, 0.5)), 0.0, 1e-12);
+        assert_float_absolute_eq!(grid.density(&Vec3::new(0.625, 0.2, 0.9)), 0.75, 1e-12);
+        assert_eq!(grid.density(&Vec3::new(1.5, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn delta_and_ratio_tracking_agree() {
+        // along x the density is 0 up to 0.25, then ramps to 1 at 0.75 and stays there,
+        // so the optical depth across is density_scale * (0.25 + 0.25).
+        let volume = cloud(2.0);
+        let ray = Ray{origin: Vec3::new(-1., 0.5, 0.5), direction: Vec3::new(1., 0., 0.), time: 0.};
+        let expected = (-2.0f64 * 0.5).exp();
+        // as for `volume_transmittance`, and four times as many for half the tolerance.
+        let n = 40_000;
+        let passed = (0..n).filter(|_| volume.hit(&ray, 0.001, f64::INFINITY).is_none()).count();
+        assert_float_absolute_eq!(passed as f64 / n as f64, expected, 0.01);
+        let ratio = (0..4 * n).map(|_| volume.transmittance(&ray, 0.001, f64::INFINITY)).sum::<f64>() / (4 * n) as f64;
+        assert_float_absolute_eq!(ratio, expected, 0.005);
+
+        let scatter = volume.hit(&ray, 0.001, f64::INFINITY);
+        assert!(scatter.is_none_or(|hit| hit.hit_position.x > 0.25 && hit.medium.is_some()));
+    }
+
+    #[test]
+    fn load_mitsuba_vol() {
+        let mut bytes = b"VOL".to_vec();
+        bytes.push(3);
+        for value in [1i32, 2, 1, 1, 1] {
+            bytes.extend(value.to_le_bytes());
+        }
+        for value in [0f32, 0., 0., 2., 1., 1., 0.25, 0.75] {
+            bytes.extend(value.to_le_bytes());
+        }
+        let path = std::env::temp_dir().join(format!("density-{}.vol", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        let grid = DensityGrid::load_mitsuba_vol(&path).unwrap();
+        std::fs::write(&path, &bytes[..50]).unwrap();
+        assert!(DensityGrid::load_mitsuba_vol(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(grid.resolution, (2, 1, 1));
+        assert_eq!(grid.bounds.max, Vec3::new(2., 1., 1.));
+        assert_float_absolute_eq!(grid.density(&Vec3::new(1., 0.5, 0.5)), 0.5, 1e-6);
+    }
+}
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, hittable::{HitReturn, Hittable, Interval}, instance::Placement, mat4::Mat4, quat::Quat, ray::Ray, scene::Object, vec3::Vec3};
+
+/// where an object is at one point in time, as translation * rotation * scale.
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translation: Vec3<f64>,
+    pub rotation: Quat<f64>,
+    pub scale: Vec3<f64>,
+}
+
+impl Keyframe {
+    /// a keyframe that only moves the object, e.g. the center of a sphere.
+    pub fn at(time: f64, translation: Vec3<f64>) -> Self {
+        Keyframe{time, translation, rotation: Quat::new(1., 0., 0., 0.), scale: Vec3::new(1., 1., 1.)}
+    }
+
+    pub fn transform(&self) -> Mat4<f64> {
+        Mat4::translation(self.translation)
+            .mat_mul(&Mat4::rotation(&self.rotation))
+            .mat_mul(&Mat4::scaling(self.scale))
+    }
+
+    /// the inverse of `transform`, undoing each part in turn. `None` if the scale is zero along an axis.
+    pub fn inverse_transform(&self) -> Option<Mat4<f64>> {
+        if self.scale.x == 0.0 || self.scale.y == 0.0 || self.scale.z == 0.0 {
+            return None;
+        }
+        Some(Mat4::scaling(Vec3::new(1.0 / self.scale.x, 1.0 / self.scale.y, 1.0 / self.scale.z))
+            .mat_mul(&Mat4::rotation(&self.rotation.conjugate()))
+            .mat_mul(&Mat4::translation(-self.translation)))
+    }
+
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        let mix = |a: Vec3<f64>, b: Vec3<f64>| a.clone().scale(1.0 - t) + b.clone().scale(t);
+        Keyframe{
+            time: self.time + (other.time - self.time) * t,
+            translation: mix(self.translation, other.translation),
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale: mix(self.scale, other.scale),
+        }
+    }
+}
+
+/// shared geometry following keyframes, standing still before the first and after the last.
+pub struct MovingInstance {
+    pub object: Arc<Object>,
+    keyframes: Vec<Keyframe>,
+}
+
+impl MovingInstance {
+    /// `keyframes` get sorted by time, at least one is needed.
+    pub fn new(object: Arc<Object>, mut keyframes: Vec<Keyframe>) -> Self {
+        assert!(!keyframes.is_empty(), "a moving instance needs at least one keyframe");
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        MovingInstance{object, keyframes}
+    }
+
+    /// moves in a straight line from `from` at `time0` to `to` at `time1`.
+    pub fn linear(object: Arc<Object>, from: Vec3<f64>, time0: f64, to: Vec3<f64>, time1: f64) -> Self {
+        MovingInstance::new(object, vec![Keyframe::at(time0, from), Keyframe::at(time1, to)])
+    }
+
+    pub fn keyframe_at(&self, time: f64) -> Keyframe {
+        let next = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 {
+            return self.keyframes[0];
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1];
+        }
+        let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        a.lerp(b, (time - a.time) / (b.time - a.time))
+    }
+
+    // built from the keyframe's parts, a general inverse for every ray would cost more than the hit.
+    fn placement_at(&self, time: f64) -> Option<Placement> {
+        let keyframe = self.keyframe_at(time);
+        Some(Placement::with_inverse(keyframe.transform(), keyframe.inverse_transform()?))
+    }
+}
+
+impl Hittable for MovingInstance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        self.placement_at(ray.time)?.hit(&self.object, ray, t_min, t_max)
+    }
+
+    fn intervals(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<Interval> {
+        self.placement_at(ray.time).map_or(vec![], |placement| placement.intervals(&self.object, ray, t_min, t_max))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.placement_at(ray.time).map_or(1.0, |placement| placement.transmittance(&self.object, ray, t_min, t_max))
+    }
+
+    fn is_medium(&self) -> bool {
+        self.object.is_medium()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = self.object.bounding_box()?;
+        let corners: Vec<Vec3<f64>> = (0..8).map(|i| Vec3::new(
+            if i & 1 == 0 { local.min.x } else { local.max.x },
+            if i & 2 == 0 { local.min.y } else { local.max.y },
+            if i & 4 == 0 { local.min.z } else { local.max.z },
+        )).collect();
+        let mut bounds = self.keyframes.iter().flat_map(|keyframe| {
+            let transform = keyframe.transform();
+            corners.iter().map(move |corner| transform.transform_point(corner))
+        }).map(|p| Aabb::new(p, p)).reduce(|a, b| a.surrounding(&b))?;
+        // between keyframes the corners move in straight lines, unless the object turns and swings them
+        // anywhere as far from its origin as they are.
+        for pair in self.keyframes.windows(2).filter(|pair| pair[0].rotation != pair[1].rotation) {
+            let reach = pair.iter().flat_map(|keyframe| corners.iter().map(|corner| (*corner * keyframe.scale).length())).fold(0.0, f64::max);
+            for keyframe in pair {
+                let reach = Vec3::new(reach, reach, reach);
+                bounds = bounds.surrounding(&Aabb::new(keyframe.translation - reach, keyframe.translation + reach));
+            }
+        }
+        Some(bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::{aabb::Aabb, bvh::Bvh, conformance::Conformance, hittable::Sphere, material::MaterialId};
+    use super::*;
+
+    fn unit_sphere() -> Arc<Object> {
+        Arc::new(Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(0., 0., 0.), material: MaterialId::default()}))
+    }
+
+    fn down_at(x: f64, time: f64) -> Ray {
+        Ray{origin: Vec3::new(x, 5., 0.), direction: Vec3::new(0., -1., 0.), time}
+    }
+
+    #[test]
+    fn linear() {
+        let moving = MovingInstance::linear(unit_sphere(), Vec3::new(0., 0., 0.), 0.0, Vec3::new(4., 0., 0.), 1.0);
+        assert!(moving.hit(&down_at(0., 0.), 0.001, f64::INFINITY).is_some());
+        assert!(moving.hit(&down_at(0., 1.), 0.001, f64::INFINITY).is_none());
+        assert!(moving.hit(&down_at(4., 1.), 0.001, f64::INFINITY).is_some());
+        let halfway = moving.hit(&down_at(2., 0.5), 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(halfway.t, 4.0, 1e-9);
+        // holds still outside the keyframes.
+        assert!(moving.hit(&down_at(4., 3.), 0.001, f64::INFINITY).is_some());
+        assert!(moving.hit(&down_at(0., -1.), 0.001, f64::INFINITY).is_some());
+    }
+
+    #[test]
+    fn keyframed() {
+        let moving = MovingInstance::new(unit_sphere(), vec![
+            Keyframe::at(2.0, Vec3::new(0., 0., 0.)),
+            Keyframe::at(0.0, Vec3::new(0., 0., 0.)),
+            Keyframe::at(1.0, Vec3::new(0., 2., 0.)),
+        ]);
+        assert_float_absolute_eq!(moving.keyframe_at(0.5).translation.y, 1.0, 1e-12);
+        assert_float_absolute_eq!(moving.keyframe_at(1.5).translation.y, 1.0, 1e-12);
+        let top = moving.hit(&down_at(0., 1.0), 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(top.t, 2.0, 1e-9);
+    }
+
+    #[test]
+    fn rotation_and_scale() {
+        let axis = Vec3::new(0., 1., 0.);
+        let start = Keyframe{time: 0.0, translation: Vec3::new(0., 0., 0.), rotation: Quat::angle_axis(0.0, axis), scale: Vec3::new(3., 1., 1.)};
+        let end = Keyframe{time: 1.0, rotation: Quat::angle_axis(std::f64::consts::PI, axis), ..start};
+        let moving = MovingInstance::new(unit_sphere(), vec![start, end]);
+        // half way through the ellipsoid is turned a quarter, its long axis along z.
+        let halfway = moving.keyframe_at(0.5).transform().transform_point(&Vec3::new(1., 0., 0.));
+        assert!((halfway - Vec3::new(0., 0., -3.)).length() < 1e-9);
+        assert!(moving.hit(&down_at(2., 0.0), 0.001, f64::INFINITY).is_some());
+        assert!(moving.hit(&down_at(2., 0.5), 0.001, f64::INFINITY).is_none());
+        // the bounds hold it all the way round, not just where the keyframes have it.
+        assert!(moving.bounding_box().unwrap().contains(&halfway));
+    }
+
+    #[test]
+    fn in_a_bvh() {
+        let axis = Vec3::new(0., 1., 0.);
+        let start = Keyframe{time: 0.0, translation: Vec3::new(0., 0., -10.), rotation: Quat::angle_axis(0.0, axis), scale: Vec3::new(3., 1., 1.)};
+        let objects: Vec<Object> = vec![
+            Object::Moving(Box::new(MovingInstance::linear(unit_sphere(), Vec3::new(0., 0., 0.), 0.0, Vec3::new(4., 0., 0.), 1.0))),
+            Object::Moving(Box::new(MovingInstance::new(unit_sphere(), vec![start, Keyframe{time: 1.0, rotation: Quat::angle_axis(std::f64::consts::PI, axis), ..start}]))),
+            Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(-10., 0., 0.), material: MaterialId::default()}),
+            Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(10., 0., 10.), material: MaterialId::default()}),
+            Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(0., 0., 20.), material: MaterialId::default()}),
+        ];
+        let bvh = Bvh::new(&objects);
+        let hit = |x: f64, z: f64, time: f64| {
+            let ray = Ray{origin: Vec3::new(x, 5., z), ..down_at(0., time)};
+            bvh.hit(&objects, &ray, 0.001, f64::INFINITY).map(|hit_return| hit_return.object_id)
+        };
+        // at both ends of the shutter.
+        assert_eq!(hit(0., 0., 0.0), Some(0));
+        assert_eq!(hit(4., 0., 1.0), Some(0));
+        assert_eq!(hit(4., 0., 0.0), None);
+        assert_eq!(hit(2.5, -10., 0.0), Some(1));
+        assert_eq!(hit(-2.5, -10., 1.0), Some(1));
+        // and the ellipsoid turned across, where neither keyframe has it.
+        assert_eq!(hit(0., -12.5, 0.5), Some(1));
+    }
+
+    #[test]
+    fn inverse_from_the_parts() {
+        let keyframe = Keyframe{time: 0.0, translation: Vec3::new(1., -2., 3.), rotation: Quat::angle_axis(0.7, Vec3::new(1., 2., 2.).normalize()), scale: Vec3::new(2., 0.5, 3.)};
+        let general = keyframe.transform().inverse().unwrap();
+        let from_parts = keyframe.inverse_transform().unwrap();
+        for (a, b) in general.data.iter().zip(from_parts.data) {
+            assert_float_absolute_eq!(*a, b, 1e-12);
+        }
+        assert!(Keyframe{scale: Vec3::new(1., 0., 1.), ..keyframe}.inverse_transform().is_none());
+    }
+
+    #[test]
+    fn conformance() {
+        // the harness fires its rays at time 0.
+        let moving = MovingInstance::linear(unit_sphere(), Vec3::new(1., 0., 0.), 0.0, Vec3::new(4., 0., 0.), 1.0);
+        let bounds = Aabb::new(Vec3::new(0., -1., -1.), Vec3::new(2., 1., 1.));
+        Conformance{hittable: &moving, bounds, inside: Some(Vec3::new(1., 0., 0.)), convex: true}.check();
+    }
+}
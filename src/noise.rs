@@ -0,0 +1,114 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::vec3::Vec3;
+
+/// ken perlin's improved noise, roughly in -1..1 with one feature per unit cube.
+#[derive(Clone)]
+pub struct Perlin {
+    // 256 shuffled values repeated twice so lookups never need to wrap.
+    permutation: Vec<u8>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut values: Vec<u8> = (0..=255).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(seed));
+        let permutation = values.iter().chain(values.iter()).copied().collect();
+        Perlin{permutation}
+    }
+
+    pub fn noise(&self, p: &Vec3<f64>) -> f64 {
+        let cell = p.floor();
+        let (x, y, z) = (p.x - cell.x as f64, p.y - cell.y as f64, p.z - cell.z as f64);
+        let (xi, yi, zi) = ((cell.x & 255) as usize, (cell.y & 255) as usize, (cell.z & 255) as usize);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let p = &self.permutation;
+        let a = p[xi] as usize + yi;
+        let aa = p[a] as usize + zi;
+        let ab = p[a + 1] as usize + zi;
+        let b = p[xi + 1] as usize + yi;
+        let ba = p[b] as usize + zi;
+        let bb = p[b + 1] as usize + zi;
+
+        lerp(w,
+            lerp(v,
+                lerp(u, gradient(p[aa], x, y, z), gradient(p[ba], x - 1., y, z)),
+                lerp(u, gradient(p[ab], x, y - 1., z), gradient(p[bb], x - 1., y - 1., z))),
+            lerp(v,
+                lerp(u, gradient(p[aa + 1], x, y, z - 1.), gradient(p[ba + 1], x - 1., y, z - 1.)),
+                lerp(u, gradient(p[ab + 1], x, y - 1., z - 1.), gradient(p[bb + 1], x - 1., y - 1., z - 1.))))
+    }
+
+    /// fractal brownian motion, `octaves` layers of noise each at twice the frequency and half the amplitude.
+    pub fn fbm(&self, p: &Vec3<f64>, octaves: u32) -> f64 {
+        self.octaves(p, octaves, |n| n)
+    }
+
+    /// like `fbm` but summing the absolute value of every layer, which gives sharp creases.
+    pub fn turbulence(&self, p: &Vec3<f64>, octaves: u32) -> f64 {
+        self.octaves(p, octaves, f64::abs)
+    }
+
+    fn octaves(&self, p: &Vec3<f64>, octaves: u32, shape: impl Fn(f64) -> f64) -> f64 {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut point = *p;
+        for _ in 0..octaves {
+            sum += amplitude * shape(self.noise(&point));
+            amplitude *= 0.5;
+            point.scale(2.0);
+        }
+        sum
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// dot product with one of 12 gradients along the cube edges, picked by the hash.
+fn gradient(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_on_lattice_points() {
+        let perlin = Perlin::new(7);
+        for p in [Vec3::new(0., 0., 0.), Vec3::new(3., -2., 5.), Vec3::new(-1., 1., 300.)] {
+            assert_eq!(perlin.noise(&p), 0.0);
+        }
+    }
+
+    #[test]
+    fn bounded_and_continuous() {
+        let perlin = Perlin::new(1);
+        let mut previous = perlin.noise(&Vec3::new(0.1, 0.2, 0.3));
+        for i in 1..5000 {
+            let p = Vec3::new(0.1 + i as f64 * 0.001, 0.2 + i as f64 * 0.0007, 0.3);
+            let value = perlin.noise(&p);
+            assert!(value.abs() <= 1.0);
+            assert!((value - previous).abs() < 0.01);
+            previous = value;
+        }
+        assert!(perlin.turbulence(&Vec3::new(0.5, 0.25, 0.125), 5) >= 0.0);
+    }
+
+    #[test]
+    fn seeded() {
+        let p = Vec3::new(1.3, 2.7, -0.4);
+        assert_eq!(Perlin::new(3).fbm(&p, 4), Perlin::new(3).fbm(&p, 4));
+        assert_ne!(Perlin::new(3).noise(&p), Perlin::new(4).noise(&p));
+    }
+}
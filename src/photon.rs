@@ -0,0 +1,276 @@
+use std::{cmp::Ordering, collections::BinaryHeap, f64::consts::PI, ops::Range};
+
+use crate::{bsdf::{Bsdf, Frame}, hittable::HitReturn, medium::SubsurfaceWalk, ray::Ray, scene::Scene, spectrum::ColorMode, vec3::Vec3};
+
+// how many photons a caustic is estimated from at most, the nearest ones.
+pub const GATHER_COUNT: usize = 64;
+
+/// light that got to a surface by way of mirrors and glass, what caustics are made of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Photon {
+    pub position: Vec3<f64>,
+    /// the unit direction the light came from.
+    pub direction: Vec3<f64>,
+    /// the geometric normal of the surface, facing where the light came from.
+    pub normal: Vec3<f64>,
+    /// the flux it carries as linear rgb, its share of what the lights give off.
+    pub power: Vec3<f64>,
+}
+
+/// photons in a balanced kd-tree, for finding the ones near a point (jensen 2001).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhotonMap {
+    // the photon in the middle of each range splits the rest of it in two, those before it and those after.
+    photons: Vec<Photon>,
+    // the axis each photon splits its range along.
+    axes: Vec<usize>,
+}
+
+fn coordinate(v: &Vec3<f64>, axis: usize) -> f64 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+// the middle of the part of the tree `range` holds, its root.
+fn middle(range: &Range<usize>) -> usize {
+    range.start + range.len() / 2
+}
+
+// a photon found near the point looked around, the farthest one first out of the heap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Near {
+    distance_squared: f64,
+    index: usize,
+}
+
+impl Eq for Near {}
+
+impl Ord for Near {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance_squared.total_cmp(&other.distance_squared)
+    }
+}
+
+impl PartialOrd for Near {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        balance(&mut photons, &mut axes);
+        PhotonMap{photons, axes}
+    }
+
+    /// photons from the lights reaching a diffuse surface through mirrors or glass, after `update_lights`.
+    pub fn caustics(scene: &Scene, count: usize, max_depth: u32, t_min: f64) -> Self {
+        let mut photons = vec![];
+        let mode = ColorMode::Rgb;
+        let mut rng = rand::thread_rng();
+        for _ in 0..count {
+            let time = scene.camera.sample_time();
+            let Some(emission) = scene.lights().sample_emission(scene, time, &mut rng) else {
+                continue;
+            };
+            let cos = emission.normal.dot(&emission.direction);
+            let mut power = mode.color(&emission.radiance).scale(cos / (emission.position_pdf * emission.direction_pdf * count as f64));
+            let mut ray = Ray{origin: emission.position, direction: emission.direction, time};
+            // the subsurface scattering object the photon is passing through.
+            let mut subsurface: Option<SubsurfaceWalk> = None;
+            let mut focused = false;
+            for _ in 0..max_depth {
+                let hit = scene.hit(&ray, t_min, f64::INFINITY);
+                let t_surface = hit.map_or(f64::INFINITY, |hit_return| hit_return.t);
+                // light scattered on the way is spread out, no caustic any more.
+                if let Some(walk) = &subsurface {
+                    if let Some(medium) = walk.medium() {
+                        let (weight, scattered) = medium.step(&ray, t_surface, &mut rng);
+                        if scattered.is_some() {
+                            break;
+                        }
+                        power = power * weight;
+                    }
+                } else if let Some(fog) = &scene.fog {
+                    if fog.sample_scatter(&ray, t_min, t_surface, &mut rng).is_some() {
+                        break;
+                    }
+                }
+                let Some(hit_return) = hit.filter(|hit_return| hit_return.medium.is_none()) else {
+                    break;
+                };
+                let material = scene.material(hit_return.material);
+                let frame = Frame::new(material.shading_normal(&hit_return), hit_return.tangent);
+                let bsdf = material.bsdf(&hit_return, &mode);
+                let incoming = -ray.direction.normalize();
+                if focused && subsurface.is_none() && !bsdf.is_specular() {
+                    photons.push(Photon{position: hit_return.hit_position, direction: incoming, normal: hit_return.normal, power});
+                }
+                let wo = frame.to_local(&incoming);
+                // only mirrors and glass keep light focused, a specular lobe of anything else does too.
+                let Some(sample) = bsdf.sample(&wo, &mut rng).filter(|sample| sample.specular) else {
+                    break;
+                };
+                let direction = frame.to_world(&sample.direction);
+                // sent across the geometric surface by a bent shading normal.
+                if direction.dot(&hit_return.normal) * sample.direction.z <= 0.0 {
+                    break;
+                }
+                power = power * sample.weight.clone().scale(bsdf.adjoint_scale(&wo, &sample.direction));
+                subsurface = SubsurfaceWalk::cross(subsurface.take(), &hit_return, &direction, material, &mode);
+                focused = true;
+                ray = Ray{origin: hit_return.hit_position, direction, time};
+            }
+        }
+        PhotonMap::new(photons)
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// the at most `count` photons within `radius` of `position`, nearest first with squared distances.
+    pub fn nearest(&self, position: &Vec3<f64>, count: usize, radius: f64) -> Vec<(f64, &Photon)> {
+        let mut heap = BinaryHeap::with_capacity(count + 1);
+        let mut max_distance_squared = radius * radius;
+        self.search(0..self.photons.len(), position, count, &mut max_distance_squared, &mut heap);
+        heap.into_sorted_vec().into_iter().map(|near| (near.distance_squared, &self.photons[near.index])).collect()
+    }
+
+    fn search(&self, range: Range<usize>, position: &Vec3<f64>, count: usize, max_distance_squared: &mut f64, heap: &mut BinaryHeap<Near>) {
+        if range.is_empty() || count == 0 {
+            return;
+        }
+        let index = middle(&range);
+        let photon = &self.photons[index];
+        let axis = self.axes[index];
+        let offset = coordinate(position, axis) - coordinate(&photon.position, axis);
+        let (near, far) = if offset < 0.0 { (range.start..index, index + 1..range.end) } else { (index + 1..range.end, range.start..index) };
+        self.search(near, position, count, max_distance_squared, heap);
+        let distance_squared = (photon.position - *position).length_squared();
+        if distance_squared <= *max_distance_squared {
+            heap.push(Near{distance_squared, index});
+            if heap.len() > count {
+                heap.pop();
+            }
+            // with enough photons found, only closer ones are worth looking for.
+            if heap.len() == count {
+                *max_distance_squared = heap.peek().map_or(*max_distance_squared, |near| near.distance_squared);
+            }
+        }
+        if offset * offset <= *max_distance_squared {
+            self.search(far, position, count, max_distance_squared, heap);
+        }
+    }
+
+    /// the radiance the `GATHER_COUNT` photons nearest `hit_return` bring toward `toward_eye` off `bsdf`.
+    pub fn radiance(&self, hit_return: &HitReturn, toward_eye: &Vec3<f64>, bsdf: &Bsdf, frame: &Frame, mode: &ColorMode, radius: f64) -> Vec3<f64> {
+        let zero = Vec3::new(0., 0., 0.);
+        let normal = hit_return.normal;
+        let wo = frame.to_local(toward_eye);
+        if normal.dot(toward_eye) * wo.z <= 0.0 {
+            return zero;
+        }
+        let mut near = self.nearest(&hit_return.hit_position, GATHER_COUNT + 1, radius);
+        // out to the farthest photon when there are enough of them, which is left out as it sits on the edge
+        // of the disc instead of in it. the whole disc otherwise.
+        let radius_squared = if near.len() > GATHER_COUNT { near.pop().map_or(0.0, |(distance_squared, _)| distance_squared) } else { radius * radius };
+        if near.is_empty() || radius_squared <= 0.0 {
+            return zero;
+        }
+        let mut flux = near.iter().fold(zero, |flux, (_, photon)| {
+            // photons on some other surface close by, around a corner say, don't light this one.
+            if photon.normal.dot(&normal).abs() < 0.9 {
+                return flux;
+            }
+            let wi = frame.to_local(&photon.direction);
+            // the geometric surface decides which side the light is on, whatever the shading normal says.
+            if normal.dot(&photon.direction) * wi.z <= 0.0 {
+                return flux;
+            }
+            flux + bsdf.evaluate(&wo, &wi).scale(1.0 / wi.z.abs()) * mode.color(&photon.power)
+        });
+        flux.scale(1.0 / (PI * radius_squared))
+    }
+}
+
+// sorts `photons` into a balanced tree, each range split at its middle along the axis it spreads out most along.
+fn balance(photons: &mut [Photon], axes: &mut [usize]) {
+    if photons.is_empty() {
+        return;
+    }
+    let (min, max) = photons.iter().fold((photons[0].position, photons[0].position), |(min, max), photon| {
+        let p = photon.position;
+        (Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)), Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)))
+    });
+    let extent = max - min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+    let index = middle(&(0..photons.len()));
+    photons.select_nth_unstable_by(index, |a, b| coordinate(&a.position, axis).total_cmp(&coordinate(&b.position, axis)));
+    axes[index] = axis;
+    let (photons_before, rest) = photons.split_at_mut(index);
+    let (axes_before, axes_rest) = axes.split_at_mut(index);
+    balance(photons_before, axes_before);
+    balance(&mut rest[1..], &mut axes_rest[1..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{hittable::Sphere, material::Material, quad::Quad, scene::Object, sky::Sky};
+    use super::*;
+
+    #[test]
+    fn finds_the_nearest_photons() {
+        let zero = Vec3::new(0., 0., 0.);
+        let photons: Vec<Photon> = (0..2000).map(|_| Photon{position: Vec3::new(rand::random(), rand::random::<f64>() * 0.1, rand::random()), direction: zero, normal: zero, power: zero}).collect();
+        let map = PhotonMap::new(photons.clone());
+        for _ in 0..50 {
+            let position = Vec3::new(rand::random(), 0.05, rand::random());
+            let mut distances: Vec<f64> = photons.iter().map(|photon| (photon.position - position).length_squared()).filter(|d| *d <= 0.01).collect();
+            distances.sort_by(f64::total_cmp);
+            distances.truncate(GATHER_COUNT);
+            let found: Vec<f64> = map.nearest(&position, GATHER_COUNT, 0.1).into_iter().map(|(d, _)| d).collect();
+            assert_eq!(found, distances);
+        }
+    }
+
+    // a glass ball above a floor, under a lamp, in the dark.
+    fn glass_ball() -> Scene {
+        let mut scene = Scene::new(4, 4);
+        scene.set_sky(Sky::Flat{color: Vec3::new(0., 0., 0.), sun_direction: Vec3::new(0., 1., 0.), sun_irradiance: 0.0});
+        let floor = scene.add_material(Material::diffuse(Vec3::new(0.5, 0.5, 0.5)));
+        let glass = scene.add_material(Material::dielectric(1.5, 0.0));
+        let lamp = scene.add_material(Material::emissive(Vec3::new(5., 5., 5.)));
+        scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(-50., 0., -50.), u: Vec3::new(0., 0., 100.), v: Vec3::new(100., 0., 0.), material: floor})));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.5, center: Vec3::new(0., 1., 0.), material: glass}));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.4, center: Vec3::new(0., 2.5, 0.), material: lamp}));
+        scene.max_depth = 4;
+        scene.update_lights();
+        scene
+    }
+
+    #[test]
+    fn caustics_agree_with_the_path_tracer() {
+        // the floor under the ball seen from the side, lit through it. paths from the camera find the lamp
+        // through the glass by chance, the photons bring the same light.
+        let scene = glass_ball();
+        let caustics = PhotonMap::caustics(&scene, 400_000, scene.max_depth, 1e-6);
+        let rays: Vec<Ray> = (0..40 * 40).map(|i| {
+            let origin = Vec3::new(2., 0.5, 0.);
+            let target = Vec3::new((i % 40) as f64 / 40.0 - 0.5, 0., (i / 40) as f64 / 40.0 - 0.5);
+            Ray{origin, direction: target - origin, time: 0.0}
+        }).collect();
+        let mean = |color: &dyn Fn(&Ray) -> f64, n: usize| rays.iter().map(|ray| (0..n).map(|_| color(ray)).sum::<f64>() / n as f64).sum::<f64>() / rays.len() as f64;
+        let reference = mean(&|ray| ray.color(&scene, ColorMode::Rgb, 1e-6, f64::INFINITY, scene.max_depth).y, 200);
+        let color = mean(&|ray| ray.color_with_caustics(&scene, ColorMode::Rgb, 1e-6, f64::INFINITY, scene.max_depth, &caustics).y, 4);
+        assert!((color / reference - 1.0).abs() < 0.05, "{color} against {reference}");
+    }
+}
@@ -0,0 +1,77 @@
+use crate::{aabb::Aabb, hittable::{HitReturn, Hittable}, material::MaterialId, ray::Ray, triangle::pad, vec3::Vec3};
+
+/// a parallelogram spanned by `u` and `v` from `corner`. open, its front is the side `u` x `v` points to.
+pub struct Quad {
+    pub corner: Vec3<f64>,
+    pub u: Vec3<f64>,
+    pub v: Vec3<f64>,
+    pub material: MaterialId,
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        let n = self.u.cross(&self.v);
+        let denominator = n.dot(&ray.direction);
+        if denominator.abs() < 1e-12 * n.length() * ray.direction.length() {
+            return None;
+        }
+        let t = n.dot(&(self.corner - ray.origin)) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let hit_position = ray.origin + ray.direction.clone().scale(t);
+        // coordinates of the hit in the u, v basis.
+        let w = n.clone().scale(1.0 / n.dot(&n));
+        let offset = hit_position - self.corner;
+        let alpha = w.dot(&offset.cross(&self.v));
+        let beta = w.dot(&self.u.cross(&offset));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let face_normal = n.normalize();
+        let front_face = denominator < 0.0;
+        let normal = if front_face { face_normal } else { -face_normal };
+        Some(HitReturn{uv: (alpha, beta), tangent: self.u.normalize(), dpdu: self.u, dpdv: self.v, ..HitReturn::new(hit_position, normal, t, front_face, self.material)})
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = [self.corner, self.corner + self.u, self.corner + self.v, self.corner + self.u + self.v];
+        corners.iter().map(|p| Aabb::new(*p, *p)).reduce(|a, b| a.surrounding(&b)).map(pad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::conformance::Conformance;
+    use super::*;
+
+    fn quad() -> Quad {
+        Quad{corner: Vec3::new(-1., 0., -1.), u: Vec3::new(2., 0., 0.), v: Vec3::new(0., 0., 4.), material: MaterialId::default()}
+    }
+
+    #[test]
+    fn uv_and_frame() {
+        // u x v points down, so a ray from above sees the back.
+        let ray = Ray{origin: Vec3::new(0.5, 3., 2.), direction: Vec3::new(0., -1., 0.), time: 0.};
+        let hit = quad().hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 3.0, 1e-12);
+        assert!(!hit.front_face);
+        assert!((hit.normal - Vec3::new(0., 1., 0.)).length() < 1e-12);
+        assert_float_absolute_eq!(hit.uv.0, 0.75, 1e-12);
+        assert_float_absolute_eq!(hit.uv.1, 0.75, 1e-12);
+        assert!((hit.tangent - Vec3::new(1., 0., 0.)).length() < 1e-12);
+
+        let beside = Ray{origin: Vec3::new(1.5, 3., 2.), direction: Vec3::new(0., -1., 0.), time: 0.};
+        assert!(quad().hit(&beside, 0.001, f64::INFINITY).is_none());
+        let parallel = Ray{origin: Vec3::new(0., 0., -5.), direction: Vec3::new(0., 0., 1.), time: 0.};
+        assert!(quad().hit(&parallel, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn conformance() {
+        let quad = Quad{corner: Vec3::new(1., 2., 3.), u: Vec3::new(1., 1., 0.), v: Vec3::new(0., 0.5, 2.), material: MaterialId::default()};
+        let bounds = quad.bounding_box().unwrap();
+        Conformance{hittable: &quad, bounds, inside: None, convex: false}.check();
+    }
+}
@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use std::{ops::{Mul, Sub, Add, Div, Neg}, fmt::Display, vec};
+use std::{ops::{Sub, Add, Neg}, fmt::Display};
 
 use rand::Rng;
 
@@ -32,7 +32,7 @@ impl Quat<f64> {
         )
     }
     pub fn length_squared(&self) -> f64 {
-        self.dot(&self)
+        self.dot(self)
     }
 
     pub fn length(&self) -> f64 {
@@ -55,6 +55,11 @@ impl Quat<f64> {
         )
     }
 
+    /// the inverse rotation, for unit quaternions.
+    pub fn conjugate(&self) -> Quat<f64> {
+        Quat::new(self.w, -self.v.x, -self.v.y, -self.v.z)
+    }
+
     pub fn floor(&self) -> Quat<i64> {
         Quat::new(
             self.w.floor() as i64,
@@ -84,6 +89,30 @@ impl Quat<f64> {
         *self
     }
 
+    /// spherical interpolation along the shorter arc between two unit quaternions.
+    pub fn slerp(&self, other: &Self, t: f64) -> Quat<f64> {
+        let mut cos_angle = self.dot(other);
+        let mut other = *other;
+        if cos_angle < 0.0 {
+            cos_angle = -cos_angle;
+            other = Quat::new(-other.w, -other.v.x, -other.v.y, -other.v.z);
+        }
+        let (a, b) = if cos_angle > 0.9995 {
+            // nearly parallel, sin(angle) would be too small to divide by.
+            (1.0 - t, t)
+        } else {
+            let angle = cos_angle.acos();
+            let sin_angle = angle.sin();
+            (((1.0 - t) * angle).sin() / sin_angle, (t * angle).sin() / sin_angle)
+        };
+        Quat::new(
+            a * self.w + b * other.w,
+            a * self.v.x + b * other.v.x,
+            a * self.v.y + b * other.v.y,
+            a * self.v.z + b * other.v.z,
+        ).normalize()
+    }
+
     pub fn angle_axis(angle_radians: f64, unit_axis: Vec3<f64>) -> Quat<f64> {
         let half_cos_angle = (angle_radians / 2.0).cos();
         let half_sin_angle = (angle_radians / 2.0).sin();
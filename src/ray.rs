@@ -1,66 +1,324 @@
 #![allow(dead_code)]
 
-use crate::{vec3::{Vec3}, scene::Object, hittable::{HitReturn, Hittable}};
+use crate::{vec3::{Vec3}, scene::Scene, hittable::{HitReturn, Hittable}, medium::{Scattering, SubsurfaceWalk}, bsdf::Frame, light::power_heuristic, photon::PhotonMap, spectrum::ColorMode};
+
+// how many times a path may scatter inside a subsurface scattering object before it is given up on.
+// counted apart from the scene's depth, light needs many steps to find its way out.
+pub const MAX_WALK_STEPS: u32 = 256;
+
+// how much of the way to a light shadow rays leave out, so they don't hit the light itself.
+pub const SHADOW_GAP: f64 = 1e-4;
 
 #[derive(Debug, Clone)]
 pub struct Ray {
     pub origin: Vec3<f64>,
     pub direction: Vec3<f64>,
+    /// when the ray was fired, moving objects are hit where they are at this time.
+    pub time: f64,
 }
 
 impl Ray {
-    pub fn hit(&self, objects: &Vec<Object>, t_min: f64, t_max: f64) -> Option<HitReturn> {
+    /// closest hit among `objects`, tagged with the index of the object that was hit.
+    pub fn hit<H: Hittable>(&self, objects: &[H], t_min: f64, t_max: f64) -> Option<HitReturn> {
         let mut ret : Option<HitReturn> = None;
         let mut closest = t_max;
-        for obj in objects {
-            if let Some(hit_return)= match obj {
-                Object::Sphere(sphere) => sphere.hit(self, t_min, closest), 
-            } {
+        for (i, obj) in objects.iter().enumerate() {
+            if let Some(hit_return) = obj.hit(self, t_min, closest) {
                 if hit_return.t <= closest {
                     closest = hit_return.t;
-                    ret = Some(hit_return);
+                    ret = Some(HitReturn{object_id: i, ..hit_return});
                 }
             }
         }
         ret
     }
 
-    pub fn color(&self, objects: &Vec<Object>, t_min: f64, t_max: f64, max_depth: u32) -> Vec3<u8> {
-        if max_depth == 0 {
-            return Vec3::new(0, 0, 0);
-        }
-        let mut total = Vec3::new(0., 0., 0.);
+    /// the radiance back along the ray as linear rgb, direct light found both ways with the power heuristic.
+    pub fn color(&self, scene: &Scene, mode: ColorMode, t_min: f64, t_max: f64, max_depth: u32) -> Vec3<f64> {
+        self.trace(scene, mode, t_min, t_max, max_depth, None).0
+    }
+
+    /// as `color`, with how many times the path scattered outside of subsurface walks.
+    pub fn color_and_bounces(&self, scene: &Scene, mode: ColorMode, t_min: f64, t_max: f64, max_depth: u32) -> (Vec3<f64>, u32) {
+        self.trace(scene, mode, t_min, t_max, max_depth, None)
+    }
+
+    /// as `color`, with area lights' caustics taken from the photons in `caustics`.
+    pub fn color_with_caustics(&self, scene: &Scene, mode: ColorMode, t_min: f64, t_max: f64, max_depth: u32, caustics: &PhotonMap) -> Vec3<f64> {
+        self.trace(scene, mode, t_min, t_max, max_depth, Some(caustics)).0
+    }
+
+    fn trace(&self, scene: &Scene, mut mode: ColorMode, t_min: f64, t_max: f64, max_depth: u32, caustics: Option<&PhotonMap>) -> (Vec3<f64>, u32) {
+        let mut radiance = Vec3::new(0., 0., 0.);
+        let mut throughput = Vec3::new(1., 1., 1.);
         let mut ray = self.clone();
-        let mut hit_count = 0;
-        let light_dir = Vec3::new(-1., -1., -1.,).normalize();
-        for _ in (0..max_depth).rev() {
-            if let Some(hit_return) = ray.hit(objects, t_min, t_max) {
-                let mut v = Vec3::<f64>::random();
-                loop {
-                    if v.length_squared() < 1.0 {
-                        break;
-                    } 
-                    v = Vec3::<f64>::random();
-                };
-                
-                ray.origin = hit_return.hit_position;
-                ray.direction = (hit_return.normal + v).normalize();
-                total = total + hit_return.object_color.clone().scale(hit_return.normal.dot(&-light_dir).max(0.0) * 0.5f64.powi(hit_count));
-                hit_count += 1;
-                
-            } else {
-                // total = Vec3::new(135./255., 206./255., 235./255.).scale(0.5f64.powi(hit_count));
+        // the subsurface scattering object the path is inside of.
+        let mut subsurface: Option<SubsurfaceWalk> = None;
+        let (mut depth, mut walk_steps) = (0, 0);
+        // the density the last direction was picked with if the lights were sampled there too, `None` when
+        // only the path itself can find the light, like through mirrors and glass.
+        let mut scatter_pdf: Option<f64> = None;
+        let mut origin = ray.origin;
+        // whether photons were gathered where the path last scattered other than specularly, and whether a
+        // light it runs into now is one they already brought, the path having gone on through mirrors and glass.
+        let (mut gathered, mut caustic) = (false, false);
+        let mut rng = rand::thread_rng();
+        loop {
+            let mut hit = scene.hit(&ray, t_min, t_max);
+            if let Some(walk) = &subsurface {
+                // the fog is outside, in here there's the object's own medium.
+                if let Some(medium) = walk.medium() {
+                    let (weight, scattered) = medium.step(&ray, hit.map_or(t_max, |hit_return| hit_return.t), &mut rng);
+                    throughput = throughput * weight;
+                    if let Some(t) = scattered {
+                        hit = Some(Scattering{albedo: Vec3::new(1., 1., 1.), phase: medium.phase}.hit_at(&ray, t));
+                    }
+                }
+            } else if let Some(fog) = &scene.fog {
+                let t_surface = hit.map_or(t_max, |hit_return| hit_return.t);
+                if let Some(t) = fog.sample_scatter(&ray, t_min, t_surface, &mut rng) {
+                    hit = Some(Scattering{albedo: fog.albedo, phase: fog.phase}.hit_at(&ray, t));
+                }
+            }
+            let Some(hit_return) = hit else {
+                let direction = ray.direction.normalize();
+                let sun = scene.sky().sun_radiance(&direction);
+                let weight = scatter_pdf.map_or(1.0, |pdf| power_heuristic(pdf, scene.lights().sun_pdf(scene, &direction)));
+                radiance = radiance + throughput * mode.color(&(scene.sky().radiance(&direction) + sun.clone().scale(weight)));
+                break;
+            };
+
+            let material = scene.material(hit_return.material);
+            if hit_return.medium.is_none() {
+                let emission = material.emission.evaluate(hit_return.uv, &hit_return.hit_position);
+                if emission != Vec3::new(0., 0., 0.) && !(caustic && scene.lights().by_object(hit_return.object_id).is_some()) {
+                    let weight = scatter_pdf.map_or(1.0, |pdf| power_heuristic(pdf, scene.lights().pdf(&origin, &hit_return)));
+                    radiance = radiance + (throughput * mode.color(&emission)).scale(weight);
+                }
+            }
+            // the last vertex is only there for what the path brings back by running into it.
+            if depth >= max_depth || walk_steps >= MAX_WALK_STEPS {
                 break;
             }
 
+            let direction = ray.direction.normalize();
+            // no light reaches into a subsurface scattering object but through its surface.
+            let samples_lights = subsurface.is_none() && !scene.lights().is_empty();
+            let light = if samples_lights { scene.lights().sample(scene, &hit_return.hit_position, ray.time, &mut rng) } else { None };
+            let mut gathered_here = false;
+            // what the light sample brings, and where the path goes next: direction, weight, density and whether it was specular.
+            let (direct, next) = match hit_return.medium {
+                // the walk's weights already account for the albedo.
+                Some(Scattering{phase, ..}) if subsurface.is_some() => {
+                    let next_direction = phase.sample(&direction, &mut rng);
+                    (Vec3::new(0., 0., 0.), Some((next_direction, Vec3::new(1., 1., 1.), phase.evaluate(&direction, &next_direction), false)))
+                }
+                Some(Scattering{albedo, phase}) => {
+                    let albedo = mode.color(&albedo);
+                    let direct = light.map_or(Vec3::new(0., 0., 0.), |light| {
+                        let pdf = phase.evaluate(&direction, &light.direction);
+                        let weight = if light.delta { 1.0 } else { power_heuristic(light.pdf, pdf) };
+                        (albedo * mode.color(&light.radiance)).scale(pdf * weight / light.pdf)
+                    });
+                    let next_direction = phase.sample(&direction, &mut rng);
+                    (direct, Some((next_direction, albedo, phase.evaluate(&direction, &next_direction), false)))
+                }
+                None => {
+                    if material.disperses() {
+                        throughput = throughput * mode.terminate_secondary();
+                    }
+                    let frame = Frame::new(material.shading_normal(&hit_return), hit_return.tangent);
+                    let bsdf = material.bsdf(&hit_return, &mode);
+                    let wo = frame.to_local(&-direction);
+                    if let Some(caustics) = caustics.filter(|_| subsurface.is_none() && !bsdf.is_specular()) {
+                        radiance = radiance + throughput * caustics.radiance(&hit_return, &-direction, &bsdf, &frame, &mode, scene.photon_radius);
+                        gathered_here = true;
+                    }
+                    let direct = light.filter(|_| !bsdf.is_specular()).map_or(Vec3::new(0., 0., 0.), |light| {
+                        let wi = frame.to_local(&light.direction);
+                        // the geometric surface decides which side light is on, whatever the shading normal says.
+                        if hit_return.normal.dot(&light.direction) * wi.z <= 0.0 {
+                            return Vec3::new(0., 0., 0.);
+                        }
+                        let weight = if light.delta { 1.0 } else { power_heuristic(light.pdf, bsdf.pdf(&wo, &wi)) };
+                        (bsdf.evaluate(&wo, &wi) * mode.color(&light.radiance)).scale(weight / light.pdf)
+                    });
+                    let next = bsdf.sample(&wo, &mut rng).map(|sample| (frame.to_world(&sample.direction), sample.weight, sample.pdf, sample.specular));
+                    let next = next.filter(|(next_direction, ..)| next_direction.dot(&hit_return.normal) * frame.to_local(next_direction).z > 0.0);
+                    if let Some((next_direction, ..)) = next {
+                        subsurface = SubsurfaceWalk::cross(subsurface.take(), &hit_return, &next_direction, material, &mode);
+                    }
+                    (direct, next)
+                }
+            };
+            // shadow rays see volumes and fog as partially transparent.
+            if let Some(light) = light.filter(|_| direct != Vec3::new(0., 0., 0.)) {
+                // stopping just short of the light itself.
+                let shadow_ray = Ray{origin: hit_return.hit_position, direction: light.direction, time: ray.time};
+                let distance = light.distance * (1.0 - SHADOW_GAP);
+                let mut visibility = scene.transmittance(&shadow_ray, t_min, distance);
+                if let Some(fog) = &scene.fog {
+                    visibility *= fog.transmittance(&hit_return.hit_position, &light.direction, distance);
+                }
+                radiance = radiance + (throughput * direct).scale(visibility);
+            }
+            // absorbed, or sent across the geometric surface by a bent shading normal.
+            let Some((next_direction, weight, pdf, specular)) = next else {
+                break;
+            };
+            throughput = throughput * weight;
+            if specular {
+                caustic = gathered;
+            } else {
+                (gathered, caustic) = (gathered_here, false);
+            }
+            scatter_pdf = (samples_lights && !specular).then_some(pdf);
+
+            origin = hit_return.hit_position;
+            ray.origin = hit_return.hit_position;
+            ray.direction = next_direction;
+            if subsurface.is_some() {
+                walk_steps += 1;
+            } else {
+                depth += 1;
+            }
         }
-        if hit_count > 0 {
-            total.scale(1. / hit_count as f64).scale(255.99).into()
+        (mode.to_rgb(&radiance), depth)
+    }
 
+
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use crate::{bsdf::Metal, hittable::Sphere, material::Material, quad::Quad, scene::Object, sky::Sky, triangle::Triangle};
+    use super::*;
+
+    // a black world with a floor through the origin and one round lamp.
+    fn lamp_over(floor: Material, center: Vec3<f64>, radius: f64, emission: f64) -> Scene {
+        let mut scene = Scene::new(4, 4);
+        scene.set_sky(Sky::Flat{color: Vec3::new(0., 0., 0.), sun_direction: Vec3::new(0., 1., 0.), sun_irradiance: 0.0});
+        let floor = scene.add_material(floor);
+        let lamp = scene.add_material(Material::emissive(Vec3::new(emission, emission, emission)));
+        scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(-50., 0., -50.), u: Vec3::new(0., 0., 100.), v: Vec3::new(100., 0., 0.), material: floor})));
+        scene.add_object(Object::Sphere(Sphere{radius, center, material: lamp}));
+        scene.update_lights();
+        scene
+    }
+
+    fn estimate(scene: &Scene, ray: &Ray, n: usize) -> f64 {
+        (0..n).map(|_| ray.color(scene, ColorMode::Rgb, 1e-6, f64::INFINITY, 1).y).sum::<f64>() / n as f64
+    }
+
+    // the light reflected at the origin toward the start of `ray`, by one strategy alone.
+    fn by_one_strategy(scene: &Scene, ray: &Ray, n: usize, sample_lights: bool) -> f64 {
+        let hit_return = scene.hit(ray, 1e-6, f64::INFINITY).unwrap();
+        let material = scene.material(hit_return.material);
+        let frame = Frame::new(material.shading_normal(&hit_return), hit_return.tangent);
+        let bsdf = material.bsdf(&hit_return, &ColorMode::Rgb);
+        let wo = frame.to_local(&-ray.direction.normalize());
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..n).map(|_| if sample_lights {
+            let light = scene.lights().sample(scene, &hit_return.hit_position, 0.0, &mut rng).unwrap();
+            bsdf.evaluate(&wo, &frame.to_local(&light.direction)).y * light.radiance.y / light.pdf
         } else {
-            return Vec3::new(135, 206, 235);
+            let Some(sample) = bsdf.sample(&wo, &mut rng) else {
+                return 0.0;
+            };
+            let bounce = Ray{origin: hit_return.hit_position, direction: frame.to_world(&sample.direction), time: 0.0};
+            scene.hit(&bounce, 1e-6, f64::INFINITY).map_or(0.0, |lamp| sample.weight.y * scene.material(lamp.material).emission.evaluate(lamp.uv, &lamp.hit_position).y)
+        }).sum::<f64>() / n as f64
+    }
+
+    #[test]
+    fn diffuse_under_lamps_of_any_size() {
+        // straight above at height 4, a lamp of angular radius a lights the floor with pi L sin^2 a.
+        let ray = Ray{origin: Vec3::new(3., 0.5, 0.), direction: Vec3::new(-3., -0.5, 0.), time: 0.0};
+        for radius in [0.05, 2.0] {
+            let sin2 = (radius / 4.0f64).powi(2);
+            let scene = lamp_over(Material::diffuse(Vec3::new(0.5, 0.5, 0.5)), Vec3::new(0., 4., 0.), radius, 1.0 / sin2);
+            let color = estimate(&scene, &ray, 20_000);
+            assert!((color - 0.5).abs() < 0.015, "radius {radius}: {color}");
+        }
+    }
+
+    #[test]
+    fn glossy_highlights_from_lamps_of_any_size() {
+        // the lamp sits where the floor mirrors the camera. a tiny one is hard to hit by following the bsdf,
+        // and a big one's light samples mostly miss the highlight, so each is checked against the strategy
+        // that works for it.
+        let ray = Ray{origin: Vec3::new(3., 1., 0.), direction: Vec3::new(-3., -1., 0.), time: 0.0};
+        for (radius, emission) in [(0.02, 20_000.0), (2.0, 1.0)] {
+            let scene = lamp_over(Material::conductor(Metal::Silver, 0.15), Vec3::new(-6., 2., 0.), radius, emission);
+            let reference = by_one_strategy(&scene, &ray, 200_000, radius < 1.0);
+            let color = estimate(&scene, &ray, 20_000);
+            assert!((color / reference - 1.0).abs() < 0.03, "radius {radius}: {color} against {reference}");
         }
     }
 
+    // a white sky all around whatever is put in.
+    fn under_white_sky() -> Scene {
+        let mut scene = Scene::new(4, 4);
+        scene.set_sky(Sky::Flat{color: Vec3::new(1., 1., 1.), sun_direction: Vec3::new(0., 1., 0.), sun_irradiance: 0.0});
+        scene.max_depth = 16;
+        scene
+    }
 
+    fn mean_color(scene: &Scene, ray: &Ray, n: usize) -> f64 {
+        (0..n).map(|_| ray.color(scene, ColorMode::Rgb, 1e-6, f64::INFINITY, scene.max_depth).y).sum::<f64>() / n as f64
+    }
+
+    #[test]
+    fn subsurface_around_glass() {
+        let ray = Ray{origin: Vec3::new(0.1, 0.2, 5.), direction: Vec3::new(0., 0., -1.), time: 0.0};
+        let mut estimates = vec![];
+        for core in [false, true] {
+            let mut scene = under_white_sky();
+            let wax = scene.add_material(Material::subsurface(Vec3::new(0.8, 0.8, 0.8), Vec3::new(0.3, 0.3, 0.3), 0.3));
+            scene.add_object(Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(0., 0., 0.), material: wax}));
+            if core {
+                // matching the outside, so it changes nothing but the medium missing from it.
+                let glass = scene.add_material(Material::dielectric(1.0, 0.0));
+                scene.add_object(Object::Sphere(Sphere{radius: 0.5, center: Vec3::new(0., 0., 0.), material: glass}));
+            }
+            scene.update_lights();
+            estimates.push(mean_color(&scene, &ray, 20_000));
+        }
+        // paths that went through the core still find their way out of the wax.
+        assert!((estimates[1] - estimates[0]).abs() < 0.05 * estimates[0], "{estimates:?}");
+    }
+
+    #[test]
+    fn subsurface_meshes() {
+        let ray = Ray{origin: Vec3::new(0.1, 0.2, 5.), direction: Vec3::new(0., 0., -1.), time: 0.0};
+        let mut estimates = vec![];
+        // the same cube from two triangles or one quad per face, every one a separate object facing out.
+        for triangles in [false, true] {
+            let mut scene = under_white_sky();
+            let wax = scene.add_material(Material::subsurface(Vec3::new(0.8, 0.8, 0.8), Vec3::new(0.3, 0.3, 0.3), 0.3));
+            let unit = |i: usize| Vec3::new((i == 0) as u8 as f64, (i == 1) as u8 as f64, (i == 2) as u8 as f64);
+            for axis in 0..3 {
+                for side in [-1.0, 1.0] {
+                    let (mut u, mut v) = (unit((axis + 1) % 3), unit((axis + 2) % 3));
+                    if side < 0.0 {
+                        (u, v) = (v, u);
+                    }
+                    let corner = |i: f64, j: f64| unit(axis).scale(side) + u.clone().scale(i) + v.clone().scale(j);
+                    if triangles {
+                        scene.add_object(Object::Triangle(Box::new(Triangle::new([corner(-1., -1.), corner(1., -1.), corner(1., 1.)], wax))));
+                        scene.add_object(Object::Triangle(Box::new(Triangle::new([corner(-1., -1.), corner(1., 1.), corner(-1., 1.)], wax))));
+                    } else {
+                        scene.add_object(Object::Quad(Box::new(Quad{corner: corner(-1., -1.), u: u.scale(2.0), v: v.scale(2.0), material: wax})));
+                    }
+                }
+            }
+            scene.update_bvh();
+            scene.update_lights();
+            estimates.push(mean_color(&scene, &ray, 20_000));
+        }
+        // paths leave through other faces than they came in by, and not all are lost in the wax.
+        assert!(estimates[0] > 0.5, "{estimates:?}");
+        assert!((estimates[1] - estimates[0]).abs() < 0.05 * estimates[0], "{estimates:?}");
+    }
 }
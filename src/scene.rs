@@ -1,14 +1,137 @@
-use std::time::Instant;
-use crate::{hittable::*, vec3::Vec3, fonts::{render_string, RasterizedCharacter, rasterize_alphabet, CHARACTER_PX}, ASPECT_RATIO, draw_string, camera::Camera, ray::{Ray, self}};
+use std::{path::Path, str::FromStr, sync::OnceLock, time::Instant};
+use crate::{aabb::Aabb, bdpt, bvh::Bvh, debug::{self, DebugView}, whitted, hittable::*, instance::Instance, light::Lights, csg::Csg, material::{Material, MaterialId}, sdf::SdfObject, heightfield::Heightfield, triangle::Triangle, quad::Quad, motion::MovingInstance, medium::{HeightFog, HeterogeneousVolume, Volume}, photon::PhotonMap, vec3::Vec3, fonts::{render_string, RasterizedCharacter, rasterize_alphabet, CHARACTER_PX}, draw_string, camera::Camera, ray::Ray, sky::Sky, spectrum::{ColorMode, Wavelengths}, texture::linear_to_srgb};
 pub enum Object {
     Sphere(Sphere),
+    Torus(Torus),
+    Triangle(Box<Triangle>),
+    Quad(Box<Quad>),
+    Instance(Box<Instance>),
+    Csg(Box<Csg>),
+    Sdf(Box<SdfObject>),
+    Heightfield(Box<Heightfield>),
+    Moving(Box<MovingInstance>),
+    Volume(Box<Volume>),
+    HeterogeneousVolume(Box<HeterogeneousVolume>),
+    /// any other shape, for crates using this one to add their own.
+    Custom(Box<dyn Hittable + Send + Sync>),
+}
+
+impl Object {
+    fn hittable(&self) -> &dyn Hittable {
+        match self {
+            Object::Sphere(sphere) => sphere,
+            Object::Torus(torus) => torus,
+            Object::Triangle(triangle) => triangle.as_ref(),
+            Object::Quad(quad) => quad.as_ref(),
+            Object::Instance(instance) => instance.as_ref(),
+            Object::Csg(csg) => csg.as_ref(),
+            Object::Sdf(sdf) => sdf.as_ref(),
+            Object::Heightfield(heightfield) => heightfield.as_ref(),
+            Object::Moving(moving) => moving.as_ref(),
+            Object::Volume(volume) => volume.as_ref(),
+            Object::HeterogeneousVolume(volume) => volume.as_ref(),
+            Object::Custom(custom) => custom.as_ref(),
+        }
+    }
+}
+
+impl Hittable for Object {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        self.hittable().hit(ray, t_min, t_max)
+    }
+
+    fn intervals(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<Interval> {
+        self.hittable().intervals(ray, t_min, t_max)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.hittable().transmittance(ray, t_min, t_max)
+    }
+
+    fn is_medium(&self) -> bool {
+        self.hittable().is_medium()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.hittable().bounding_box()
+    }
+}
+
+/// how `Scene::render` finds the light coming back along camera rays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Integrator {
+    /// paths from the camera, sampling the lights at every bounce.
+    #[default]
+    PathTracer,
+    /// paths from the camera and the lights joined, for caustics and lamps behind lampshades.
+    Bidirectional,
+    /// the path tracer with lamps' caustics from photons, spectral renders leave them to it.
+    PhotonMapping,
+    /// point lights with hard shadows, mirrors and glass, noise free and quick.
+    Whitted,
+    /// not the light at all but something else about what camera rays hit.
+    Debug(DebugView),
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(match name {
+            "path" => Integrator::PathTracer,
+            "bidirectional" => Integrator::Bidirectional,
+            "photons" => Integrator::PhotonMapping,
+            "whitted" => Integrator::Whitted,
+            "normals" => Integrator::Debug(DebugView::Normals),
+            "depth" => Integrator::Debug(DebugView::Depth),
+            "ao" => Integrator::Debug(DebugView::AmbientOcclusion),
+            "uv" => Integrator::Debug(DebugView::Uv),
+            "bounces" => Integrator::Debug(DebugView::Bounces),
+            "tests" => Integrator::Debug(DebugView::IntersectionTests),
+            _ => return Err(format!("no integrator {name:?}, there are path, bidirectional, photons, whitted, normals, depth, ao, uv, bounces and tests")),
+        })
+    }
+}
+
+// how far past a cut out surface rays carry on, in world units.
+const CUTOUT_STEP: f64 = 1e-6;
+
+// what the photons for `Integrator::PhotonMapping` were sent off with, besides the objects and materials.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PhotonSettings {
+    count: usize,
+    max_depth: u32,
+    shutter: (f64, f64),
+    fog: Option<HeightFog>,
 }
 
 pub struct Scene {
-    pub objects: Vec<Object>,
+    objects: Vec<Object>,
+    // looked up by the `MaterialId`s objects carry, the first one is the default.
+    materials: Vec<Material>,
     pub camera: Camera,
     pub window_width: u32, 
     pub window_height: u32, 
+    /// rays per pixel, each one at a different time within the camera shutter.
+    pub samples_per_pixel: u32,
+    /// how many times a path may scatter.
+    pub max_depth: u32,
+    pub fog: Option<HeightFog>,
+    sky: Sky,
+    /// trace a few wavelengths per path instead of rgb, slower and noisier in color but showing dispersion.
+    pub spectral: bool,
+    pub integrator: Integrator,
+    /// how many photons the lamps send off with `Integrator::PhotonMapping`.
+    pub photons: usize,
+    /// how far from a point the photons lighting it may be, caustics are blurred by up to this much.
+    pub photon_radius: f64,
+    // dropped whenever the objects may have changed, `update_bvh` builds it again.
+    bvh: Option<Bvh>,
+    // the photons last sent off for `Integrator::PhotonMapping` and what with. dropped whenever the objects
+    // may have changed, `update_caustics` sends them off again. materials only ever get added.
+    caustics: Option<(PhotonSettings, PhotonMap)>,
+    // dropped whenever the objects, materials or sky may have changed, `update_lights` finds them again.
+    lights: Option<Lights>,
     alphabet: [Option<RasterizedCharacter>; 128],
     frame_count: u32,
     previous_frame_duration: u128,
@@ -19,42 +142,437 @@ impl Scene {
         Scene{
             camera: Camera::new(45.0, window_width as usize, window_height as usize),
             objects: vec![],
+            materials: vec![Material::default()],
             window_height,
             window_width,
+            samples_per_pixel: 1,
+            max_depth: 1,
+            fog: None,
+            sky: Sky::default(),
+            spectral: false,
+            integrator: Integrator::default(),
+            photons: 200_000,
+            photon_radius: 0.05,
+            bvh: None,
+            caustics: None,
+            lights: None,
             alphabet: rasterize_alphabet(),
             frame_count: 0,
             previous_frame_duration: 0,
         }
     }
-    pub fn render(&mut self) -> Vec<Vec3<u8>> {
-        let mut res = Vec::with_capacity((self.window_height * self.window_width) as usize);
-        let now = Instant::now();
+    /// returns the object's index, which hits and lights refer to it by.
+    pub fn add_object(&mut self, object: Object) -> usize {
+        self.objects_mut().push(object);
+        self.objects.len() - 1
+    }
+
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    /// for changing objects in place, the bvh is dropped until the next `update_bvh`.
+    pub fn objects_mut(&mut self) -> &mut Vec<Object> {
+        self.bvh = None;
+        self.caustics = None;
+        self.lights = None;
+        &mut self.objects
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.lights = None;
+        self.materials.push(material);
+        MaterialId(self.materials.len() - 1)
+    }
+
+    /// what rays leaving the scene see, and the sun.
+    pub fn sky(&self) -> &Sky {
+        &self.sky
+    }
+
+    pub fn set_sky(&mut self, sky: Sky) {
+        self.lights = None;
+        self.sky = sky;
+    }
+
+    /// ids that don't refer to any material get the default one.
+    pub fn material(&self, id: MaterialId) -> &Material {
+        self.materials.get(id.0).unwrap_or(&self.materials[0])
+    }
+
+    /// the closest hit, skipping surfaces cut out by their material's opacity.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        let step = CUTOUT_STEP / ray.direction.length();
+        let mut t_min = t_min;
+        loop {
+            let hit_return = if let Some(bvh) = &self.bvh {
+                bvh.hit(&self.objects, ray, t_min, t_max)
+            } else {
+                ray.hit(&self.objects, t_min, t_max)
+            }?;
+            if hit_return.medium.is_some() || !self.material(hit_return.material).cut_out(&hit_return) {
+                return Some(hit_return);
+            }
+            t_min = hit_return.t + step;
+        }
+    }
 
-        for y in (0..self.window_height).rev() {
-            for x in 0..self.window_width {
+    /// how many boxes and objects finding the closest hit tests.
+    pub fn intersection_tests(&self, ray: &Ray, t_min: f64, t_max: f64) -> u64 {
+        match &self.bvh {
+            Some(bvh) => bvh.intersection_tests(&self.objects, ray, t_min, t_max),
+            None => self.objects.len() as u64,
+        }
+    }
 
-                let ray_direction = self.camera.ray_directions[(x + y * self.window_width) as usize];
-                let ray = Ray{origin: self.camera.position.clone(), direction: ray_direction};
-                let color = ray.color(&self.objects, 0.01, f64::INFINITY, 1);
+    /// how much light gets through the objects along the ray, 0 as soon as a surface is in the way.
+    pub fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let through = |object: &Object| self.object_transmittance(object, ray, t_min, t_max);
+        if let Some(bvh) = &self.bvh {
+            bvh.transmittance(&self.objects, ray, t_min, t_max, &through)
+        } else {
+            self.objects.iter().map(through).product()
+        }
+    }
 
-                res.push(
-                    Vec3::new(
-                        color.x as u8, 
-                        color.y as u8, 
-                        color.z as u8, 
-                    )
-                );
+    // surfaces block the light unless they are cut out wherever the ray crosses them.
+    fn object_transmittance(&self, object: &Object, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if object.is_medium() {
+            return object.transmittance(ray, t_min, t_max);
+        }
+        let step = CUTOUT_STEP / ray.direction.length();
+        let mut t = t_min;
+        while let Some(hit_return) = object.hit(ray, t, t_max) {
+            // a medium somewhere inside something that isn't one itself knows its own transmittance.
+            if hit_return.medium.is_some() {
+                return object.transmittance(ray, t_min, t_max);
+            }
+            if !self.material(hit_return.material).cut_out(&hit_return) {
+                return 0.0;
             }
+            t = hit_return.t + step;
         }
+        1.0
+    }
+
+    /// builds the bvh again if the objects were touched since the last one.
+    pub fn update_bvh(&mut self) {
+        if self.bvh.is_none() {
+            self.bvh = Some(Bvh::new(&self.objects));
+        }
+    }
+
+    /// sends photons off again for `Integrator::PhotonMapping` if what they depend on changed.
+    pub fn update_caustics(&mut self) {
+        let settings = PhotonSettings{count: self.photons, max_depth: self.max_depth, shutter: (self.camera.shutter_open, self.camera.shutter_close), fog: self.fog};
+        if self.caustics.as_ref().is_some_and(|(built_with, _)| *built_with == settings) {
+            return;
+        }
+        self.caustics = Some((settings, PhotonMap::caustics(self, self.photons, self.max_depth, 0.01)));
+    }
+
+    /// what gets sampled for direct light, nothing if something changed since the last `update_lights`.
+    pub fn lights(&self) -> &Lights {
+        static NONE: OnceLock<Lights> = OnceLock::new();
+        self.lights.as_ref().unwrap_or_else(|| NONE.get_or_init(Lights::default))
+    }
+
+    /// finds the emissive objects and the sun again if anything changed since the last time.
+    pub fn update_lights(&mut self) {
+        if self.lights.is_none() {
+            self.lights = Some(Lights::new(self));
+        }
+    }
+
+    /// the mean radiance through each pixel as linear rgb, top row first.
+    pub fn render_linear(&mut self) -> Vec<Vec3<f64>> {
+        self.update_bvh();
+        self.update_lights();
+        let (width, height) = (self.window_width as usize, self.window_height as usize);
+
+        // linear rgb sums, top row first. light subpaths add to any pixel.
+        let mut image = vec![Vec3::new(0., 0., 0.); width * height];
+        let mut splats = vec![Vec3::new(0., 0., 0.); width * height];
+        if self.integrator == Integrator::PhotonMapping && !self.spectral {
+            self.update_caustics();
+        }
+        let punctual = match self.integrator {
+            Integrator::Whitted => self.lights().punctual(self),
+            _ => vec![],
+        };
+        // whitted comes out the same every time, one ray per pixel is enough.
+        let samples = match self.integrator {
+            Integrator::Whitted => 1,
+            _ => self.samples_per_pixel,
+        };
+        for (pixel, color_sum) in image.iter_mut().enumerate() {
+            let (row, x) = (pixel / width, pixel % width);
+            let ray_direction = self.camera.ray_directions[x + (height - 1 - row) * width];
+            for _ in 0..samples {
+                let time = self.camera.sample_time();
+                let mode = if self.spectral { ColorMode::Spectral(Wavelengths::sample(rand::random())) } else { ColorMode::Rgb };
+                *color_sum = *color_sum + match self.integrator {
+                    Integrator::PathTracer => Ray{origin: self.camera.position, direction: ray_direction, time}.color(self, mode, 0.01, f64::INFINITY, self.max_depth),
+                    Integrator::Whitted => whitted::radiance(self, &Ray{origin: self.camera.position, direction: ray_direction, time}, 0.01, self.max_depth, &punctual),
+                    Integrator::Debug(view) => debug::color(self, &Ray{origin: self.camera.position, direction: ray_direction, time}, view, 0.01, self.max_depth),
+                    Integrator::PhotonMapping => match self.caustics.as_ref().filter(|_| !self.spectral) {
+                        Some((_, caustics)) => Ray{origin: self.camera.position, direction: ray_direction, time}.color_with_caustics(self, mode, 0.01, f64::INFINITY, self.max_depth, caustics),
+                        None => Ray{origin: self.camera.position, direction: ray_direction, time}.color(self, mode, 0.01, f64::INFINITY, self.max_depth),
+                    },
+                    Integrator::Bidirectional => {
+                        // anywhere in the pixel, light subpaths land anywhere in it too.
+                        let u = (x as f64 + rand::random::<f64>()) / width as f64 * 2.0 - 1.0;
+                        let v = (row as f64 + rand::random::<f64>()) / height as f64 * 2.0 - 1.0;
+                        let ray = Ray{origin: self.camera.position, direction: self.camera.direction_at(u, v), time};
+                        bdpt::radiance(self, &ray, mode, 0.01, self.max_depth, &mut |pixel, color| splats[pixel] = splats[pixel] + color)
+                    }
+                };
+            }
+        }
+        // averaged before clamping, so rare bright samples still count.
+        let scale = 1.0 / samples as f64;
+        image.iter().zip(&splats).map(|(color_sum, splat)| (*color_sum + *splat).scale(scale)).collect()
+    }
+
+    /// renders one frame into an image file, its format going by the extension.
+    pub fn render_to_file(&mut self, path: impl AsRef<Path>) -> image::ImageResult<()> {
+        let (width, height) = (self.window_width, self.window_height);
+        let colors = self.render_linear();
+        let image = image::RgbImage::from_fn(width, height, |x, y| {
+            let color = encode(colors[(y * width + x) as usize]);
+            image::Rgb([color.x, color.y, color.z])
+        });
+        image.save(path)
+    }
+
+    pub fn render(&mut self) -> Vec<Vec3<u8>> {
+        let now = Instant::now();
+        let mut res: Vec<Vec3<u8>> = self.render_linear().into_iter().map(encode).collect();
         let x_pos = 100;
         let y_pos = 50;
         draw_string!(&format!("{:?}ms", self.previous_frame_duration as f64 / 1000.), &self.alphabet, &mut res, self.window_width, x_pos, y_pos);
         self.frame_count += 1;
-        if self.frame_count % 10 == 0 {
+        if self.frame_count.is_multiple_of(10) {
             let new_now = Instant::now();
             self.previous_frame_duration = new_now.duration_since(now).as_micros();
         }
 
-        return res;
+        res
+    }
+}
+
+// linear rgb clamped to 0..1 and srgb encoded, as image files and the window expect.
+fn encode(color: Vec3<f64>) -> Vec3<u8> {
+    let channel = |c: f64| (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.99) as u8;
+    Vec3::new(channel(color.x), channel(color.y), channel(color.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::{mat4::Mat4, medium::PhaseFunction, quad::Quad, texture::{ImageTexture, Texture, WrapMode}};
+    use super::*;
+
+    // a fence of two bars across x, with the gap between them at 1/3 < u < 2/3, and a wall behind it.
+    fn fenced() -> Scene {
+        let mut scene = Scene::new(4, 4);
+        let bars = Texture::Checker{cells: (3., 1.), even: Box::new(Texture::gray(1.)), odd: Box::new(Texture::gray(0.))};
+        let fence = scene.add_material(Material{opacity: Some(bars), ..Material::default()});
+        scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(-1.5, -1., 0.), u: Vec3::new(3., 0., 0.), v: Vec3::new(0., 2., 0.), material: fence})));
+        scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(-1.5, -1., -1.), u: Vec3::new(3., 0., 0.), v: Vec3::new(0., 2., 0.), material: MaterialId::default()})));
+        scene
+    }
+
+    #[test]
+    fn cutouts() {
+        let mut scene = fenced();
+        for bvh in [false, true] {
+            if bvh {
+                scene.update_bvh();
+            }
+            let through_gap = Ray{origin: Vec3::new(0., 0., 1.), direction: Vec3::new(0., 0., -1.), time: 0.};
+            let at_bar = Ray{origin: Vec3::new(-1., 0., 1.), direction: Vec3::new(0., 0., -1.), time: 0.};
+            assert_eq!(scene.hit(&through_gap, 0.001, f64::INFINITY).unwrap().t, 2.0);
+            assert_eq!(scene.hit(&at_bar, 0.001, f64::INFINITY).unwrap().t, 1.0);
+            // shadow rays see the gap too, but not through the wall.
+            assert_eq!(scene.transmittance(&through_gap, 0.001, 1.5), 1.0);
+            assert_eq!(scene.transmittance(&at_bar, 0.001, 1.5), 0.0);
+            assert_eq!(scene.transmittance(&through_gap, 0.001, f64::INFINITY), 0.0);
+        }
+    }
+
+    #[test]
+    fn objects_changed_in_place() {
+        let mut scene = fenced();
+        scene.update_bvh();
+        let ray = Ray{origin: Vec3::new(-1., 0., 1.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        assert_eq!(scene.hit(&ray, 0.001, f64::INFINITY).unwrap().t, 1.0);
+        // as many objects as before, the tree built for the old ones must not be used.
+        scene.objects_mut()[0] = Object::Quad(Box::new(Quad{corner: Vec3::new(-1.5, -1., 0.5), u: Vec3::new(3., 0., 0.), v: Vec3::new(0., 2., 0.), material: MaterialId::default()}));
+        assert_eq!(scene.hit(&ray, 0.001, f64::INFINITY).unwrap().t, 0.5);
+        scene.update_bvh();
+        assert_eq!(scene.hit(&ray, 0.001, f64::INFINITY).unwrap().t, 0.5);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn photons_kept_until_the_objects_change() {
+        let mut scene = fenced();
+        let glass = scene.add_material(Material::dielectric(1.5, 0.0));
+        let lamp = scene.add_material(Material::emissive(Vec3::new(5., 5., 5.)));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.3, center: Vec3::new(0., 0., 0.5), material: glass}));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.2, center: Vec3::new(0., 0., 1.5), material: lamp}));
+        scene.photons = 2000;
+        scene.max_depth = 4;
+        scene.update_lights();
+        scene.update_caustics();
+        let (_, caustics) = scene.caustics.clone().unwrap();
+        assert!(!caustics.is_empty());
+        // looking somewhere else lands the same photons, sending them off again would scatter them differently.
+        scene.camera.update_x_position(1.0);
+        scene.update_caustics();
+        assert_eq!(scene.caustics.as_ref().unwrap().1, caustics);
+        scene.photons = 1000;
+        scene.update_caustics();
+        assert_ne!(scene.caustics.as_ref().unwrap().1, caustics);
+        scene.objects_mut().pop();
+        assert!(scene.caustics.is_none());
+    }
+
+    #[test]
+    fn lights_kept_until_something_changes() {
+        let mut scene = fenced();
+        scene.set_sky(Sky::Flat{color: Vec3::new(0., 0., 0.), sun_direction: Vec3::new(0., 1., 0.), sun_irradiance: 0.0});
+        let lamp = scene.add_material(Material::emissive(Vec3::new(5., 5., 5.)));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.2, center: Vec3::new(0., 0., 1.5), material: lamp}));
+        scene.update_lights();
+        assert_eq!(scene.lights().len(), 1);
+        // left alone while nothing changed, a stand in with no lights stays.
+        scene.lights = Some(Lights::default());
+        scene.camera.update_x_position(1.0);
+        scene.update_lights();
+        assert!(scene.lights().is_empty());
+        scene.add_material(Material::default());
+        assert!(scene.lights.is_none());
+        scene.update_lights();
+        assert_eq!(scene.lights().len(), 1);
+        // the sun is a light too.
+        scene.set_sky(Sky::default());
+        assert!(scene.lights.is_none());
+        scene.update_lights();
+        assert_eq!(scene.lights().len(), 2);
+        scene.objects_mut().pop();
+        assert!(scene.lights().is_empty());
+        scene.update_lights();
+        assert_eq!(scene.lights().len(), 1);
+    }
+
+    #[test]
+    fn media_let_light_through() {
+        // a ball of fog, on its own and moved aside by an instance, thins light through its middle by
+        // exp(-2 density).
+        let fog = Object::Volume(Box::new(Volume{
+            boundary: Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(0., 0., 0.), material: MaterialId::default()}),
+            density: 0.5,
+            albedo: Vec3::new(1., 1., 1.),
+            phase: PhaseFunction::Isotropic,
+        }));
+        let moved = Object::Instance(Box::new(Instance::new(Arc::new(fog), Mat4::translation(Vec3::new(0., 3., 0.))).unwrap()));
+        assert!(moved.is_medium());
+        let mut scene = Scene::new(4, 4);
+        scene.add_object(moved);
+        scene.update_bvh();
+        let ray = Ray{origin: Vec3::new(0., 3., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        assert!((scene.transmittance(&ray, 0.001, 10.0) - (-1.0f64).exp()).abs() < 1e-9);
+    }
+
+    // a shape from outside the crate, a square facing +z that only knows how to be hit and bounded.
+    struct Tile {
+        z: f64,
+    }
+
+    impl Hittable for Tile {
+        fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+            let t = (self.z - ray.origin.z) / ray.direction.z;
+            let hit_position = ray.origin + ray.direction.clone().scale(t);
+            if !(t_min..=t_max).contains(&t) || hit_position.x.abs() > 1.0 || hit_position.y.abs() > 1.0 {
+                return None;
+            }
+            Some(HitReturn::new(hit_position, Vec3::new(0., 0., 1.), t, ray.direction.z < 0.0, MaterialId(1)))
+        }
+
+        fn bounding_box(&self) -> Option<Aabb> {
+            Some(Aabb::new(Vec3::new(-1., -1., self.z), Vec3::new(1., 1., self.z)))
+        }
+    }
+
+    #[test]
+    fn custom_shapes() {
+        let mut scene = Scene::new(4, 4);
+        scene.add_object(Object::Sphere(Sphere{radius: 0.2, center: Vec3::new(5., 0., 0.), material: MaterialId::default()}));
+        let tile = scene.add_object(Object::Custom(Box::new(Tile{z: -1.0})));
+        let ray = Ray{origin: Vec3::new(0.5, 0.5, 1.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        for bvh in [false, true] {
+            if bvh {
+                scene.update_bvh();
+            }
+            let hit = scene.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            assert_eq!((hit.t, hit.object_id, hit.material), (2.0, tile, MaterialId(1)));
+            assert_eq!(scene.transmittance(&ray, 0.001, f64::INFINITY), 0.0);
+        }
+        // and rendered like any other, here its normal toward the camera.
+        scene.camera.position = Vec3::new(0., 0., 0.);
+        scene.camera.calculate_ray_directions();
+        scene.integrator = "normals".parse().unwrap();
+        assert!(scene.render_linear().iter().all(|color| (*color - Vec3::new(0.5, 0.5, 1.)).length() < 1e-9));
+    }
+
+    #[test]
+    fn renders_to_file() {
+        // the wall seen head on, its normal toward the camera light blue.
+        let mut scene = fenced();
+        scene.camera.position = Vec3::new(0., 0., -0.5);
+        scene.camera.calculate_ray_directions();
+        scene.integrator = "normals".parse().unwrap();
+        let path = std::env::temp_dir().join(format!("render-{}.png", std::process::id()));
+        scene.render_to_file(&path).unwrap();
+        let image = image::open(&path).unwrap().to_rgb8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.dimensions(), (4, 4));
+        assert!(image.pixels().all(|pixel| pixel.0 == [188, 188, 255]));
+    }
+
+    // a glowing picture filling the view of a 4x4 camera.
+    fn glowing_picture(srgb: [u8; 3]) -> Scene {
+        let path = std::env::temp_dir().join(format!("glowing-{}-{:?}.png", std::process::id(), srgb));
+        image::RgbImage::from_fn(2, 2, |_, _| image::Rgb(srgb)).save(&path).unwrap();
+        let picture = ImageTexture::load_srgb(&path, WrapMode::Clamp).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut scene = Scene::new(4, 4);
+        let glow = scene.add_material(Material::emissive(Texture::Image(Arc::new(picture))));
+        scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(-5., -5., -1.), u: Vec3::new(10., 0., 0.), v: Vec3::new(0., 10., 0.), material: glow})));
+        scene.camera.position = Vec3::new(0., 0., 0.);
+        scene.camera.calculate_ray_directions();
+        scene
+    }
+
+    #[test]
+    fn textures_come_out_as_they_went_in() {
+        let mut scene = glowing_picture([12, 128, 230]);
+        let path = std::env::temp_dir().join(format!("glowing-render-{}.png", std::process::id()));
+        scene.render_to_file(&path).unwrap();
+        let image = image::open(&path).unwrap().to_rgb8();
+        std::fs::remove_file(&path).unwrap();
+        assert!(image.pixels().all(|pixel| pixel.0 == [12, 128, 230]));
+    }
+
+    #[test]
+    fn spectral_renders_come_out_in_srgb() {
+        // the wavelengths are random, so only on average, and smits' spectra are off for saturated colors.
+        let mut scene = glowing_picture([200, 170, 140]);
+        scene.spectral = true;
+        scene.samples_per_pixel = 500;
+        let colors: Vec<Vec3<u8>> = scene.render_linear().into_iter().map(encode).collect();
+        let mean = colors.iter().fold(Vec3::new(0., 0., 0.), |sum, color| sum + Vec3::new(color.x as f64, color.y as f64, color.z as f64)).scale(1.0 / colors.len() as f64);
+        assert!((mean - Vec3::new(200., 170., 140.)).length() < 8.0, "{mean:?}");
+    }
+}
@@ -0,0 +1,367 @@
+use crate::{aabb::Aabb, hittable::{HitReturn, Hittable}, material::MaterialId, ray::Ray, vec3::Vec3};
+
+/// a shape as a signed distance function, negative inside, nesting as trees.
+pub enum Sdf {
+    Sphere{radius: f64},
+    Cuboid{half_extents: Vec3<f64>},
+    /// lies in the xz plane, like `hittable::Torus`.
+    Torus{major_radius: f64, minor_radius: f64},
+    Translate{offset: Vec3<f64>, sdf: Box<Sdf>},
+    Union(Box<Sdf>, Box<Sdf>),
+    Intersection(Box<Sdf>, Box<Sdf>),
+    /// the first shape with the second carved out of it.
+    Difference(Box<Sdf>, Box<Sdf>),
+    /// union that blends the two shapes together over a distance of roughly `k`.
+    SmoothUnion{k: f64, a: Box<Sdf>, b: Box<Sdf>},
+    /// infinite copies of the shape every `period` units, a zero component disables that axis.
+    Repeat{period: Vec3<f64>, sdf: Box<Sdf>},
+    /// rotates the shape around the y axis by `rate` radians per unit of height.
+    Twist{rate: f64, sdf: Box<Sdf>},
+}
+
+impl Sdf {
+    pub fn distance(&self, p: &Vec3<f64>) -> f64 {
+        match self {
+            Sdf::Sphere{radius} => p.length() - radius,
+            Sdf::Cuboid{half_extents} => {
+                let q = Vec3::new(p.x.abs() - half_extents.x, p.y.abs() - half_extents.y, p.z.abs() - half_extents.z);
+                let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+                let inside = q.x.max(q.y).max(q.z).min(0.0);
+                outside + inside
+            }
+            Sdf::Torus{major_radius, minor_radius} => {
+                let ring = (p.x * p.x + p.z * p.z).sqrt() - major_radius;
+                (ring * ring + p.y * p.y).sqrt() - minor_radius
+            }
+            Sdf::Translate{offset, sdf} => sdf.distance(&(*p - *offset)),
+            Sdf::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Sdf::Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            Sdf::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+            Sdf::SmoothUnion{k, a, b} => smooth_min(a.distance(p), b.distance(p), *k),
+            Sdf::Repeat{period, sdf} => {
+                let wrap = |x: f64, period: f64| if period == 0.0 { x } else { x - period * (x / period).round() };
+                sdf.distance(&Vec3::new(wrap(p.x, period.x), wrap(p.y, period.y), wrap(p.z, period.z)))
+            }
+            Sdf::Twist{rate, sdf} => {
+                let (sin, cos) = (rate * p.y).sin_cos();
+                sdf.distance(&Vec3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z))
+            }
+        }
+    }
+
+    /// an upper bound on how fast the distance changes in the box, unbounded twists get a unit radius.
+    pub fn lipschitz(&self) -> f64 {
+        match self {
+            Sdf::Sphere{..} | Sdf::Cuboid{..} | Sdf::Torus{..} => 1.0,
+            Sdf::Translate{sdf, ..} | Sdf::Repeat{sdf, ..} => sdf.lipschitz(),
+            Sdf::Union(a, b) | Sdf::Intersection(a, b) | Sdf::Difference(a, b) | Sdf::SmoothUnion{a, b, ..} => a.lipschitz().max(b.lipschitz()),
+            // a point r from the axis moves sideways by rate * r per unit of height, r is at most the distance
+            // to the box's corners.
+            Sdf::Twist{rate, sdf} => {
+                let radius = self.bounds().map_or(1.0, |bounds| bounds.max.x.hypot(bounds.max.z));
+                sdf.lipschitz() * (1.0 + (rate * radius).powi(2)).sqrt()
+            }
+        }
+    }
+
+    /// a box the surface stays within, `None` for shapes repeated forever.
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Sdf::Sphere{radius} => Some(Aabb::new(Vec3::new(-radius, -radius, -radius), Vec3::new(*radius, *radius, *radius))),
+            Sdf::Cuboid{half_extents} => Some(Aabb::new(-*half_extents, *half_extents)),
+            Sdf::Torus{major_radius, minor_radius} => {
+                let outer = major_radius + minor_radius;
+                Some(Aabb::new(Vec3::new(-outer, -minor_radius, -outer), Vec3::new(outer, *minor_radius, outer)))
+            }
+            Sdf::Translate{offset, sdf} => sdf.bounds().map(|bounds| Aabb::new(bounds.min + *offset, bounds.max + *offset)),
+            Sdf::Union(a, b) => Some(a.bounds()?.surrounding(&b.bounds()?)),
+            Sdf::Intersection(a, b) => match (a.bounds(), b.bounds()) {
+                (Some(a), Some(b)) => {
+                    let min = Vec3::new(a.min.x.max(b.min.x), a.min.y.max(b.min.y), a.min.z.max(b.min.z));
+                    let max = Vec3::new(a.max.x.min(b.max.x), a.max.y.min(b.max.y), a.max.z.min(b.max.z));
+                    // shapes that don't overlap leave nothing, a box around a point is as good as any.
+                    Some(Aabb::new(min, Vec3::new(max.x.max(min.x), max.y.max(min.y), max.z.max(min.z))))
+                }
+                (a, b) => a.or(b),
+            },
+            Sdf::Difference(a, _) => a.bounds(),
+            // the blend pulls the distance down by at most k / 4.
+            Sdf::SmoothUnion{k, a, b} => {
+                let bounds = a.bounds()?.surrounding(&b.bounds()?);
+                let pad = Vec3::new(k / 4.0, k / 4.0, k / 4.0);
+                Some(Aabb::new(bounds.min - pad, bounds.max + pad))
+            }
+            Sdf::Repeat{period, sdf} => {
+                if *period != Vec3::new(0., 0., 0.) {
+                    return None;
+                }
+                sdf.bounds()
+            }
+            // turning around the y axis sweeps the box out to its farthest corner from it.
+            Sdf::Twist{sdf, ..} => {
+                let bounds = sdf.bounds()?;
+                let x = bounds.min.x.abs().max(bounds.max.x.abs());
+                let z = bounds.min.z.abs().max(bounds.max.z.abs());
+                let radius = (x * x + z * z).sqrt();
+                Some(Aabb::new(Vec3::new(-radius, bounds.min.y, -radius), Vec3::new(radius, bounds.max.y, radius)))
+            }
+        }
+    }
+
+    /// the direction the distance grows fastest in, estimated from four samples around `p`.
+    pub fn gradient(&self, p: &Vec3<f64>, h: f64) -> Vec3<f64> {
+        let offsets = [
+            Vec3::new(1., -1., -1.),
+            Vec3::new(-1., -1., 1.),
+            Vec3::new(-1., 1., -1.),
+            Vec3::new(1., 1., 1.),
+        ];
+        offsets.iter().fold(Vec3::new(0., 0., 0.), |sum, k| {
+            sum + k.clone().scale(self.distance(&(*p + k.clone().scale(h))))
+        })
+    }
+}
+
+/// polynomial smooth minimum, equal to `a.min(b)` once they are more than `k` apart.
+pub fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+/// an sdf placed in the scene, found by sphere tracing along the ray.
+pub struct SdfObject {
+    pub sdf: Sdf,
+    pub material: MaterialId,
+    /// a ray counts as hitting once it is this close to the surface.
+    pub epsilon: f64,
+    pub max_steps: u32,
+    /// how far to march along rays past shapes without bounds, repeated ones never end otherwise.
+    pub max_distance: f64,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Sdf, material: MaterialId) -> Self {
+        SdfObject{sdf, material, epsilon: 1e-5, max_steps: 512, max_distance: 100.0}
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        // march in world units along a unit direction, then convert back to the ray's t.
+        let length = ray.direction.length();
+        let direction = ray.direction.normalize();
+        let step_scale = 1.0 / self.sdf.lipschitz();
+        let (t_start, t_end) = match self.bounding_box() {
+            Some(bounds) => bounds.hit(ray, t_min, t_max)?,
+            None => (t_min, t_max.min(self.max_distance / length)),
+        };
+        let end = t_end * length;
+
+        let mut s = t_start * length;
+        let start = ray.origin + direction.clone().scale(s);
+        let start_distance = self.sdf.distance(&start);
+        // a ray starting that close to the surface is leaving it, off the side it heads into, and doesn't hit
+        // it again until it got away.
+        let mut leaving = t_start == t_min && start_distance.abs() < self.epsilon;
+        // which side the ray starts on decides whether we look for a zero from above or below.
+        let start_sign = if leaving { self.sdf.gradient(&start, self.epsilon).dot(&direction).signum() } else { start_distance.signum() };
+        for _ in 0..self.max_steps {
+            if s > end {
+                return None;
+            }
+            let p = ray.origin + direction.clone().scale(s);
+            let distance = self.sdf.distance(&p) * start_sign;
+            if leaving {
+                leaving = distance < self.epsilon;
+                if leaving {
+                    s += self.epsilon;
+                    continue;
+                }
+            }
+            if distance < self.epsilon {
+                // s started out as t_min * length, dividing it back can round below t_min.
+                let t = (s / length).max(t_min);
+                let normal = self.sdf.gradient(&p, self.epsilon).normalize();
+                let front_face = ray.direction.dot(&normal) <= 0.0;
+                return Some(HitReturn::new(p, if front_face { normal } else { -normal }, t, front_face, self.material));
+            }
+            s += distance * step_scale;
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // hits count from within epsilon of the surface.
+        let pad = Vec3::new(self.epsilon, self.epsilon, self.epsilon).scale(2.0);
+        self.sdf.bounds().map(|bounds| Aabb::new(bounds.min - pad, bounds.max + pad))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::{aabb::Aabb, conformance::Conformance, hittable::Sphere, scene::Object};
+    use super::*;
+
+    fn toward_origin() -> Ray {
+        Ray{origin: Vec3::new(0., 0., 5.), direction: Vec3::new(0., 0., -1.), time: 0.}
+    }
+
+    #[test]
+    fn sphere_matches_analytic() {
+        let sdf = SdfObject::new(Sdf::Sphere{radius: 1.0}, MaterialId::default());
+        let ray = Ray{origin: Vec3::new(0.3, 0.2, 5.), direction: Vec3::new(0., 0., -2.), time: 0.};
+        let analytic = Sphere{radius: 1.0, center: Vec3::new(0., 0., 0.), material: MaterialId::default()}.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        let traced = sdf.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(traced.t, analytic.t, 1e-4);
+        assert!((traced.normal - analytic.normal).length() < 1e-3);
+        assert!(traced.front_face);
+    }
+
+    #[test]
+    fn from_inside() {
+        let sdf = SdfObject::new(Sdf::Cuboid{half_extents: Vec3::new(1., 1., 1.)}, MaterialId::default());
+        let ray = Ray{origin: Vec3::new(0., 0., 0.), direction: Vec3::new(1., 0., 0.), time: 0.};
+        let hit = sdf.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 1.0, 1e-4);
+        assert!(!hit.front_face);
+        assert!((hit.normal - Vec3::new(-1., 0., 0.)).length() < 1e-3);
+    }
+
+    #[test]
+    fn t_bounds() {
+        let sdf = SdfObject::new(Sdf::Sphere{radius: 1.0}, MaterialId::default());
+        assert!(sdf.hit(&toward_origin(), 0.001, 3.9).is_none());
+        assert!(sdf.hit(&Ray{origin: Vec3::new(0., 2., 5.), direction: Vec3::new(0., 0., -1.), time: 0.}, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn far_away() {
+        // marched through its box wherever that is, not given up on after a fixed distance from the ray's origin.
+        let sdf = SdfObject::new(Sdf::Translate{offset: Vec3::new(0., 0., -500.), sdf: Box::new(Sdf::Sphere{radius: 1.0})}, MaterialId::default());
+        assert_float_absolute_eq!(sdf.hit(&toward_origin(), 0.001, f64::INFINITY).unwrap().t, 504.0, 1e-4);
+        assert!(sdf.hit(&toward_origin(), 0.001, 400.0).is_none());
+    }
+
+    #[test]
+    fn leaving_the_surface() {
+        // rays scattered off the surface start within epsilon of it, just outside or just inside.
+        let sdf = SdfObject::new(Sdf::Cuboid{half_extents: Vec3::new(1., 1., 1.)}, MaterialId::default());
+        for x in [1.0 + 2e-6, 1.0 - 2e-6] {
+            let outward = Ray{origin: Vec3::new(x, 0., 0.), direction: Vec3::new(1., 0., 0.), time: 0.};
+            assert!(sdf.hit(&outward, 1e-9, f64::INFINITY).is_none());
+            let inward = Ray{origin: Vec3::new(x, 0., 0.), direction: Vec3::new(-1., 0., 0.), time: 0.};
+            let hit = sdf.hit(&inward, 1e-9, f64::INFINITY).unwrap();
+            assert_float_absolute_eq!(hit.t, 2.0, 1e-4);
+            assert!(!hit.front_face);
+        }
+    }
+
+    #[test]
+    fn bounds() {
+        let torus = Sdf::Torus{major_radius: 1.0, minor_radius: 0.25};
+        assert_eq!(torus.bounds(), Some(Aabb::new(Vec3::new(-1.25, -0.25, -1.25), Vec3::new(1.25, 0.25, 1.25))));
+        let column = Sdf::Twist{rate: 1.0, sdf: Box::new(Sdf::Cuboid{half_extents: Vec3::new(0.3, 1.2, 0.4)})};
+        assert_eq!(column.bounds(), Some(Aabb::new(Vec3::new(-0.5, -1.2, -0.5), Vec3::new(0.5, 1.2, 0.5))));
+        let rows = Sdf::Repeat{period: Vec3::new(4., 0., 0.), sdf: Box::new(Sdf::Sphere{radius: 1.0})};
+        assert!(rows.bounds().is_none());
+        // every point the surface passes through is in the box.
+        let blob = Sdf::SmoothUnion{k: 0.5, a: Box::new(Sdf::Sphere{radius: 1.0}), b: Box::new(Sdf::Translate{offset: Vec3::new(1.5, 0., 0.), sdf: Box::new(Sdf::Sphere{radius: 0.5})})};
+        let bounds = blob.bounds().unwrap();
+        for _ in 0..10_000 {
+            let p = Vec3::random().scale(6.0).shift(-3.0);
+            if blob.distance(&p) <= 0.0 {
+                assert!(bounds.contains(&p), "{p:?} outside {bounds:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_union_fills_the_gap() {
+        // two spheres 2.2 apart leave a gap at the origin that only the blend covers.
+        let spheres = |smooth: bool| {
+            let a = Box::new(Sdf::Translate{offset: Vec3::new(-1.1, 0., 0.), sdf: Box::new(Sdf::Sphere{radius: 1.0})});
+            let b = Box::new(Sdf::Translate{offset: Vec3::new(1.1, 0., 0.), sdf: Box::new(Sdf::Sphere{radius: 1.0})});
+            if smooth { Sdf::SmoothUnion{k: 0.5, a, b} } else { Sdf::Union(a, b) }
+        };
+        let ray = Ray{origin: Vec3::new(0., 5., 0.), direction: Vec3::new(0., -1., 0.), time: 0.};
+        assert!(SdfObject::new(spheres(false), MaterialId::default()).hit(&ray, 0.001, f64::INFINITY).is_none());
+        assert!(SdfObject::new(spheres(true), MaterialId::default()).hit(&ray, 0.001, f64::INFINITY).is_some());
+    }
+
+    #[test]
+    fn intersection_and_difference() {
+        // a lens where two spheres overlap.
+        let lens = SdfObject::new(Sdf::Intersection(
+            Box::new(Sdf::Sphere{radius: 1.0}),
+            Box::new(Sdf::Translate{offset: Vec3::new(1., 0., 0.), sdf: Box::new(Sdf::Sphere{radius: 1.0})}),
+        ), MaterialId::default());
+        let through = |x: f64| Ray{origin: Vec3::new(x, 0., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        assert_float_absolute_eq!(lens.hit(&through(0.5), 0.001, f64::INFINITY).unwrap().t, 5.0 - 0.75f64.sqrt(), 1e-4);
+        assert!(lens.hit(&through(-0.5), 0.001, f64::INFINITY).is_none());
+        // a box with a dimple in its front face.
+        let dimpled = SdfObject::new(Sdf::Difference(
+            Box::new(Sdf::Cuboid{half_extents: Vec3::new(1., 1., 1.)}),
+            Box::new(Sdf::Translate{offset: Vec3::new(0., 0., 1.), sdf: Box::new(Sdf::Sphere{radius: 0.5})}),
+        ), MaterialId::default());
+        assert_float_absolute_eq!(dimpled.hit(&through(0.0), 0.001, f64::INFINITY).unwrap().t, 4.5, 1e-4);
+        assert_float_absolute_eq!(dimpled.hit(&through(0.8), 0.001, f64::INFINITY).unwrap().t, 4.0, 1e-4);
+    }
+
+    #[test]
+    fn repetition() {
+        let sdf = SdfObject::new(Sdf::Repeat{period: Vec3::new(4., 0., 0.), sdf: Box::new(Sdf::Sphere{radius: 1.0})}, MaterialId::default());
+        let ray = Ray{origin: Vec3::new(8., 0., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        assert_float_absolute_eq!(sdf.hit(&ray, 0.001, f64::INFINITY).unwrap().t, 4.0, 1e-4);
+        let between = Ray{origin: Vec3::new(6., 0., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        assert!(sdf.hit(&between, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn twist() {
+        // a square column twisted by 45 degrees at y = 1 shows its corner to the ray there.
+        let column = || Box::new(Sdf::Cuboid{half_extents: Vec3::new(0.5, 10., 0.5)});
+        let twisted = SdfObject::new(Sdf::Twist{rate: std::f64::consts::FRAC_PI_4, sdf: column()}, MaterialId::default());
+        let straight = SdfObject::new(Sdf::Twist{rate: 0.0, sdf: column()}, MaterialId::default());
+        let ray = Ray{origin: Vec3::new(0., 1., 5.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        assert_float_absolute_eq!(straight.hit(&ray, 0.001, f64::INFINITY).unwrap().t, 4.5, 1e-4);
+        assert_float_absolute_eq!(twisted.hit(&ray, 0.001, f64::INFINITY).unwrap().t, 5.0 - 0.5f64.sqrt(), 1e-4);
+    }
+
+    #[test]
+    fn wide_twist() {
+        // far from the axis a twisted slab turns fast enough to be stepped over with a bound for a unit radius.
+        let slab = Sdf::Twist{rate: 1.0, sdf: Box::new(Sdf::Cuboid{half_extents: Vec3::new(3., 1., 0.1)})};
+        let slab = SdfObject::new(slab, MaterialId::default());
+        let ray = Ray{origin: Vec3::new(-2.7, 5., -0.5), direction: Vec3::new(0., -1., 0.), time: 0.};
+        let first_inside = (0..10_000).map(|i| i as f64 * 1e-3).find(|t| slab.sdf.distance(&(ray.origin + ray.direction.clone().scale(*t))) < 0.0).unwrap();
+        assert_float_absolute_eq!(slab.hit(&ray, 0.001, f64::INFINITY).unwrap().t, first_inside, 2e-3);
+    }
+
+    #[test]
+    fn composes_with_analytic_objects() {
+        let objects = vec![
+            Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(0., 0., 0.), material: MaterialId(0)}),
+            Object::Sdf(Box::new(SdfObject::new(Sdf::Translate{offset: Vec3::new(0., 0., 2.), sdf: Box::new(Sdf::Sphere{radius: 0.5})}, MaterialId(1)))),
+        ];
+        let hit = toward_origin().hit(&objects, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 2.5, 1e-4);
+        assert_eq!(hit.material, MaterialId(1));
+        assert_eq!(hit.object_id, 1);
+    }
+
+    #[test]
+    fn conformance() {
+        let sphere = SdfObject::new(Sdf::Sphere{radius: 1.0}, MaterialId::default());
+        let bounds = Aabb::new(Vec3::new(-1., -1., -1.), Vec3::new(1., 1., 1.));
+        Conformance{hittable: &sphere, bounds, inside: Some(Vec3::new(0., 0., 0.)), convex: true}.check();
+
+        let column = Sdf::Twist{rate: 1.0, sdf: Box::new(Sdf::Cuboid{half_extents: Vec3::new(0.3, 1.2, 0.3)})};
+        let column = SdfObject::new(column, MaterialId::default());
+        let bounds = Aabb::new(Vec3::new(-0.5, -1.2, -0.5), Vec3::new(0.5, 1.2, 0.5));
+        Conformance{hittable: &column, bounds, inside: Some(Vec3::new(0., 0., 0.)), convex: false}.check();
+    }
+}
@@ -0,0 +1,244 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::{spectrum::{xyz_to_linear_srgb, RGB_WAVELENGTHS}, vec3::Vec3};
+
+// scene radiance per cd/m², chosen so 100 klux of sunlight makes a white diffuse surface facing it white.
+const LUMINANCE_SCALE: f64 = PI / 100_000.0;
+
+// sunlight before it enters the atmosphere, in lux.
+const SOLAR_ILLUMINANCE: f64 = 128_000.0;
+
+// half the angle the sun disk covers as seen from earth, in radians.
+const SUN_ANGULAR_RADIUS: f64 = 4.65e-3;
+
+/// what rays leaving the scene see, and the sun that lights it. y is up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sky {
+    /// one color all around, lit by a sun so far and small it's a single direction.
+    Flat{color: Vec3<f64>, sun_direction: Vec3<f64>, sun_irradiance: f64},
+    Daylight(Daylight),
+}
+
+/// a direction toward the sun and the light arriving from it, already divided by the density it was picked with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunSample {
+    pub direction: Vec3<f64>,
+    pub irradiance: Vec3<f64>,
+}
+
+impl Default for Sky {
+    /// the old flat light blue, with a sun chosen so a white diffuse surface facing it reflects exactly white.
+    fn default() -> Self {
+        Sky::Flat{color: Vec3::new(135./255., 206./255., 235./255.), sun_direction: Vec3::new(1., 1., 1.).normalize(), sun_irradiance: PI}
+    }
+}
+
+impl Sky {
+    /// the light coming from `direction` (unit length), leaving out the sun disk.
+    pub fn radiance(&self, direction: &Vec3<f64>) -> Vec3<f64> {
+        match self {
+            Sky::Flat{color, ..} => *color,
+            Sky::Daylight(daylight) => daylight.radiance(direction),
+        }
+    }
+
+    /// the light of the sun disk alone coming from `direction`, zero for a sun that is just a direction.
+    pub fn sun_radiance(&self, direction: &Vec3<f64>) -> Vec3<f64> {
+        match self {
+            Sky::Flat{..} => Vec3::new(0., 0., 0.),
+            Sky::Daylight(daylight) => daylight.sun_radiance(direction),
+        }
+    }
+
+    /// the density `sample_sun` picks `direction` with, per unit solid angle. zero for a sun that is just a direction.
+    pub fn sun_pdf(&self, direction: &Vec3<f64>) -> f64 {
+        match self {
+            Sky::Flat{..} => 0.0,
+            Sky::Daylight(daylight) if direction.dot(&daylight.sun_direction) < SUN_ANGULAR_RADIUS.cos() => 0.0,
+            Sky::Daylight(_) => 1.0 / sun_solid_angle(),
+        }
+    }
+
+    /// whether there is a sun giving off any light at all.
+    pub fn has_sun(&self) -> bool {
+        match self {
+            Sky::Flat{sun_irradiance, ..} => *sun_irradiance > 0.0,
+            Sky::Daylight(daylight) => daylight.sun_irradiance != Vec3::new(0., 0., 0.),
+        }
+    }
+
+    /// the direction toward the middle of the sun, with all the light of its disk.
+    pub fn sun(&self) -> SunSample {
+        match self {
+            Sky::Flat{..} => self.sample_sun(),
+            Sky::Daylight(daylight) => SunSample{direction: daylight.sun_direction, irradiance: daylight.sun_irradiance},
+        }
+    }
+
+    /// picks a direction toward the sun, uniformly over its disk.
+    pub fn sample_sun(&self) -> SunSample {
+        match self {
+            Sky::Flat{sun_direction, sun_irradiance, ..} => SunSample{direction: *sun_direction, irradiance: Vec3::new(*sun_irradiance, *sun_irradiance, *sun_irradiance)},
+            Sky::Daylight(daylight) => daylight.sample_sun(),
+        }
+    }
+}
+
+/// preetham, shirley and smits' clear sky (1999), `turbidity` from 2 for clear to about 10 for murky.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Daylight {
+    sun_direction: Vec3<f64>,
+    turbidity: f64,
+    // the perez function's a to e for luminance and the two chromaticities.
+    perez: [[f64; 5]; 3],
+    // luminance and chromaticities at the zenith, divided by the perez function there.
+    zenith: [f64; 3],
+    sun_irradiance: Vec3<f64>,
+}
+
+impl Daylight {
+    pub fn new(sun_direction: Vec3<f64>, turbidity: f64) -> Self {
+        let sun_direction = sun_direction.normalize();
+        let t = turbidity;
+        // the model is only fitted for a sun above the horizon.
+        let theta_sun = sun_direction.y.clamp(0.0, 1.0).acos();
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        // in kcd/m².
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let cubic = |c: [f64; 4]| c[0] * theta_sun.powi(3) + c[1] * theta_sun.powi(2) + c[2] * theta_sun + c[3];
+        let x = t * t * cubic([0.00166, -0.00375, 0.00209, 0.0]) + t * cubic([-0.02903, 0.06377, -0.03202, 0.00394]) + cubic([0.11693, -0.21196, 0.06052, 0.25886]);
+        let y = t * t * cubic([0.00275, -0.00610, 0.00317, 0.0]) + t * cubic([-0.04214, 0.08970, -0.04153, 0.00516]) + cubic([0.15346, -0.26756, 0.06670, 0.26688]);
+        let zenith = [luminance * 1000.0, x, y];
+        let zenith = [0, 1, 2].map(|i| zenith[i] / perez_function(&perez[i], 1.0, theta_sun));
+        Daylight{sun_direction, turbidity, perez, zenith, sun_irradiance: sun_irradiance(&sun_direction, turbidity)}
+    }
+
+    pub fn sun_direction(&self) -> Vec3<f64> {
+        self.sun_direction
+    }
+
+    fn radiance(&self, direction: &Vec3<f64>) -> Vec3<f64> {
+        // below the horizon continues the horizon.
+        let cos_theta = direction.y.max(1e-3);
+        let gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y] = [0, 1, 2].map(|i| self.zenith[i] * perez_function(&self.perez[i], cos_theta, gamma));
+        let xyz = Vec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = xyz_to_linear_srgb(&xyz).scale(LUMINANCE_SCALE);
+        Vec3::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+    }
+
+    fn sun_radiance(&self, direction: &Vec3<f64>) -> Vec3<f64> {
+        if direction.dot(&self.sun_direction) < SUN_ANGULAR_RADIUS.cos() {
+            return Vec3::new(0., 0., 0.);
+        }
+        self.sun_irradiance.clone().scale(1.0 / sun_solid_angle())
+    }
+
+    fn sample_sun(&self) -> SunSample {
+        let mut rng = rand::thread_rng();
+        let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - SUN_ANGULAR_RADIUS.cos());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        let (tangent, bitangent) = self.sun_direction.orthonormal_basis();
+        let direction = tangent.clone().scale(sin_theta * phi.cos()) + bitangent.clone().scale(sin_theta * phi.sin()) + self.sun_direction.clone().scale(cos_theta);
+        // the radiance over the uniform density, 1 / solid angle.
+        SunSample{direction, irradiance: self.sun_irradiance}
+    }
+}
+
+fn sun_solid_angle() -> f64 {
+    2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos())
+}
+
+// how the sky brightens toward the horizon and around the sun, relative to the zenith.
+fn perez_function(coefficients: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+// sunlight on a surface facing the sun after scattering by air molecules and haze on the way down,
+// from preetham et al.'s appendix without the gas and water absorption that hardly matter in the visible.
+fn sun_irradiance(sun_direction: &Vec3<f64>, turbidity: f64) -> Vec3<f64> {
+    if sun_direction.y <= 0.0 {
+        return Vec3::new(0., 0., 0.);
+    }
+    let theta_degrees = sun_direction.y.acos().to_degrees();
+    // how much air the light goes through relative to straight down, with kasten's fit near the horizon.
+    let air_mass = 1.0 / (sun_direction.y + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = RGB_WAVELENGTHS.map(|wavelength| {
+        let micrometers = wavelength / 1000.0;
+        let rayleigh = -0.008735 * micrometers.powf(-4.08) * air_mass;
+        let aerosol = -beta * micrometers.powf(-1.3) * air_mass;
+        (rayleigh + aerosol).exp()
+    });
+    Vec3::new(transmittance[0], transmittance[1], transmittance[2]).scale(SOLAR_ILLUMINANCE * LUMINANCE_SCALE)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use super::*;
+
+    fn elevated(elevation: f64, azimuth: f64) -> Vec3<f64> {
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        Vec3::new(elevation.cos() * azimuth.cos(), elevation.sin(), elevation.cos() * azimuth.sin())
+    }
+
+    fn blueness(color: &Vec3<f64>) -> f64 {
+        color.z / color.x
+    }
+
+    #[test]
+    fn flat() {
+        let sky = Sky::default();
+        assert_eq!(sky.radiance(&Vec3::new(0., 1., 0.)), Vec3::new(135./255., 206./255., 235./255.));
+        assert_eq!(sky.sample_sun(), SunSample{direction: Vec3::new(1., 1., 1.).normalize(), irradiance: Vec3::new(PI, PI, PI)});
+        assert_eq!(sky.sun_radiance(&Vec3::new(1., 1., 1.).normalize()), Vec3::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn daylight() {
+        let clear = Daylight::new(elevated(40.0, 0.0), 2.5);
+        // a blue sky, brightest around the sun and bluest overhead.
+        let zenith = clear.radiance(&Vec3::new(0., 1., 0.));
+        assert!(blueness(&zenith) > 1.5, "{zenith:?}");
+        assert!(clear.radiance(&elevated(40.0, 20.0)).y > clear.radiance(&elevated(40.0, 180.0)).y);
+        assert!(blueness(&zenith) > blueness(&clear.radiance(&elevated(5.0, 180.0))));
+        // a zenith luminance of a few kcd/m², a few tenths in scene units.
+        assert!(zenith.y > 0.05 && zenith.y < 0.5, "{zenith:?}");
+        // haze washes the blue out.
+        let hazy = Daylight::new(elevated(40.0, 0.0), 8.0);
+        assert!(blueness(&hazy.radiance(&Vec3::new(0., 1., 0.))) < blueness(&zenith));
+    }
+
+    #[test]
+    fn sun() {
+        let noon = Daylight::new(Vec3::new(0., 1., 0.), 2.0);
+        // straight overhead on a clear day the sun gives about 100 klux.
+        assert_float_absolute_eq!(noon.sun_irradiance.y, PI, 0.5);
+        let sunset = Daylight::new(elevated(2.0, 0.0), 2.0);
+        assert!(blueness(&sunset.sun_irradiance) < 0.5 * blueness(&noon.sun_irradiance));
+        assert_eq!(Daylight::new(elevated(-5.0, 0.0), 2.0).sample_sun().irradiance, Vec3::new(0., 0., 0.));
+
+        let sky = Sky::Daylight(sunset);
+        for _ in 0..1000 {
+            let sample = sky.sample_sun();
+            assert!(sample.direction.dot(&sunset.sun_direction()) >= SUN_ANGULAR_RADIUS.cos() - 1e-12);
+            assert_float_relative_eq!(sample.direction.length(), 1.0, 1e-12);
+            // what the disk gives off over where it is picked.
+            let radiance = sky.sun_radiance(&sample.direction);
+            assert_float_relative_eq!(radiance.y / sky.sun_pdf(&sample.direction), sample.irradiance.y, 1e-9);
+        }
+        assert_eq!(sky.sun_radiance(&elevated(10.0, 0.0)), Vec3::new(0., 0., 0.));
+        assert_eq!(sky.sun_pdf(&elevated(10.0, 0.0)), 0.0);
+        assert!(sky.has_sun() && !Sky::Daylight(Daylight::new(elevated(-5.0, 0.0), 2.0)).has_sun());
+    }
+}
@@ -0,0 +1,323 @@
+use std::sync::OnceLock;
+
+use crate::{mat3::Mat3, vec3::Vec3};
+
+/// the visible range wavelengths get sampled from, in nm.
+pub const MIN_WAVELENGTH: f64 = 360.0;
+pub const MAX_WAVELENGTH: f64 = 830.0;
+
+// where conductor and other measured rgb values are taken to be measured, see `ColorMode::measured`.
+pub const RGB_WAVELENGTHS: [f64; 3] = [650.0, 550.0, 450.0];
+
+// smits' reflectance spectra for turning rgb into a spectrum, 10 bins from 380 to 720nm.
+const SMITS_FIRST: f64 = 380.0;
+const SMITS_LAST: f64 = 720.0;
+const SMITS_WHITE: [f64; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+fn smits(table: &[f64; 10], wavelength: f64) -> f64 {
+    let x = ((wavelength - SMITS_FIRST) / (SMITS_LAST - SMITS_FIRST) * 9.0).clamp(0.0, 9.0);
+    let i = (x as usize).min(8);
+    table[i] + (table[i + 1] - table[i]) * (x - i as f64)
+}
+
+/// the value at `wavelength` of a smooth spectrum with the color `rgb`. white is 1 everywhere.
+pub fn rgb_to_spectrum(rgb: &Vec3<f64>, wavelength: f64) -> f64 {
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let at = |table| smits(table, wavelength);
+    if r <= g && r <= b {
+        r * at(&SMITS_WHITE) + if g <= b {
+            (g - r) * at(&SMITS_CYAN) + (b - g) * at(&SMITS_BLUE)
+        } else {
+            (b - r) * at(&SMITS_CYAN) + (g - b) * at(&SMITS_GREEN)
+        }
+    } else if g <= r && g <= b {
+        g * at(&SMITS_WHITE) + if r <= b {
+            (r - g) * at(&SMITS_MAGENTA) + (b - r) * at(&SMITS_BLUE)
+        } else {
+            (b - g) * at(&SMITS_MAGENTA) + (r - b) * at(&SMITS_RED)
+        }
+    } else {
+        b * at(&SMITS_WHITE) + if r <= g {
+            (r - b) * at(&SMITS_YELLOW) + (g - r) * at(&SMITS_GREEN)
+        } else {
+            (g - b) * at(&SMITS_YELLOW) + (r - g) * at(&SMITS_RED)
+        }
+    }
+}
+
+fn lobe(wavelength: f64, mean: f64, below: f64, above: f64) -> f64 {
+    let t = (wavelength - mean) / if wavelength < mean { below } else { above };
+    (-0.5 * t * t).exp()
+}
+
+/// the cie 1931 color matching functions, fitted with a few gaussians (wyman, sloan and shirley 2013).
+pub fn xyz_matching(wavelength: f64) -> Vec3<f64> {
+    Vec3::new(
+        1.056 * lobe(wavelength, 599.8, 37.9, 31.0) + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7) - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2),
+        0.821 * lobe(wavelength, 568.8, 46.9, 40.5) + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1),
+        1.217 * lobe(wavelength, 437.0, 11.8, 36.0) + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8),
+    )
+}
+
+pub fn xyz_to_linear_srgb(xyz: &Vec3<f64>) -> Vec3<f64> {
+    Mat3::new([
+        3.2404542, -1.5371385, -0.4985314,
+        -0.9692660, 1.8760108, 0.0415560,
+        0.0556434, -0.2040259, 1.0572252,
+    ]).vec_mul(xyz)
+}
+
+// linear srgb of a spectrum that is 1 everywhere, what has to come out as white.
+fn white() -> Vec3<f64> {
+    static WHITE: OnceLock<Vec3<f64>> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let xyz = (MIN_WAVELENGTH as usize..MAX_WAVELENGTH as usize).map(|wavelength| xyz_matching(wavelength as f64 + 0.5)).fold(Vec3::new(0., 0., 0.), |sum, xyz| sum + xyz);
+        xyz_to_linear_srgb(&xyz)
+    })
+}
+
+/// the wavelengths in nm a path carries in spectral mode in place of rgb, `x` is the hero one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wavelengths {
+    pub lambda: Vec3<f64>,
+    secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    /// the hero at `u` through the visible range and the other two spread evenly from there.
+    pub fn sample(u: f64) -> Self {
+        let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+        let at = |offset: f64| MIN_WAVELENGTH + ((u + offset) / 3.0).fract() * range;
+        Wavelengths{lambda: Vec3::new(at(0.0), at(1.0), at(2.0)), secondary_terminated: false}
+    }
+}
+
+/// how colors are carried along a path, as linear rgb or as values at a few sampled wavelengths.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    Rgb,
+    Spectral(Wavelengths),
+}
+
+impl ColorMode {
+    /// what the path carries for a color from a texture, a medium or a light.
+    pub fn color(&self, rgb: &Vec3<f64>) -> Vec3<f64> {
+        match self {
+            ColorMode::Rgb => *rgb,
+            ColorMode::Spectral(wavelengths) => Vec3::new(
+                rgb_to_spectrum(rgb, wavelengths.lambda.x),
+                rgb_to_spectrum(rgb, wavelengths.lambda.y),
+                rgb_to_spectrum(rgb, wavelengths.lambda.z),
+            ),
+        }
+    }
+
+    /// what the path carries for a reflectance by wavelength, rgb mode integrates it in 10nm steps.
+    pub fn reflectance(&self, reflectance: impl Fn(f64) -> f64) -> Vec3<f64> {
+        match self {
+            ColorMode::Spectral(wavelengths) => Vec3::new(reflectance(wavelengths.lambda.x), reflectance(wavelengths.lambda.y), reflectance(wavelengths.lambda.z)),
+            ColorMode::Rgb => {
+                let step = 10.0;
+                let xyz = (0..((MAX_WAVELENGTH - MIN_WAVELENGTH) / step) as usize)
+                    .map(|i| MIN_WAVELENGTH + (i as f64 + 0.5) * step)
+                    .fold(Vec3::new(0., 0., 0.), |sum, wavelength| sum + xyz_matching(wavelength).scale(reflectance(wavelength) * step));
+                xyz_to_linear_srgb(&xyz) / white()
+            }
+        }
+    }
+
+    /// what the path carries for a quantity given at 650, 550 and 450nm, linear in between.
+    pub fn measured(&self, rgb: &Vec3<f64>) -> Vec3<f64> {
+        let ColorMode::Spectral(wavelengths) = self else {
+            return *rgb;
+        };
+        let at = |wavelength: f64| {
+            let [red, green, blue] = RGB_WAVELENGTHS;
+            if wavelength >= green {
+                rgb.y + (rgb.x - rgb.y) * ((wavelength - green) / (red - green)).min(1.0)
+            } else {
+                rgb.y + (rgb.z - rgb.y) * ((green - wavelength) / (green - blue)).min(1.0)
+            }
+        };
+        Vec3::new(at(wavelengths.lambda.x), at(wavelengths.lambda.y), at(wavelengths.lambda.z))
+    }
+
+    /// the index of refraction the path refracts with, the hero wavelength's in spectral mode.
+    pub fn ior(&self, ior: &Ior) -> f64 {
+        match self {
+            ColorMode::Rgb => ior.at(Ior::REFERENCE_WAVELENGTH),
+            ColorMode::Spectral(wavelengths) => ior.at(wavelengths.lambda.x),
+        }
+    }
+
+    /// keeps only the hero wavelength from here on, returns what the throughput has to be scaled by.
+    pub fn terminate_secondary(&mut self) -> Vec3<f64> {
+        match self {
+            ColorMode::Rgb => Vec3::new(1., 1., 1.),
+            ColorMode::Spectral(wavelengths) if wavelengths.secondary_terminated => Vec3::new(1., 0., 0.),
+            ColorMode::Spectral(wavelengths) => {
+                wavelengths.secondary_terminated = true;
+                // the hero alone now has to account for all three.
+                Vec3::new(3., 0., 0.)
+            }
+        }
+    }
+
+    /// the radiance a path brought back, as linear srgb. the transfer function is applied on output.
+    pub fn to_rgb(self, radiance: &Vec3<f64>) -> Vec3<f64> {
+        let ColorMode::Spectral(wavelengths) = self else {
+            return *radiance;
+        };
+        // monte carlo over the visible range, each wavelength with density 1 / range.
+        let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+        let xyz = xyz_matching(wavelengths.lambda.x).scale(radiance.x)
+            + xyz_matching(wavelengths.lambda.y).scale(radiance.y)
+            + xyz_matching(wavelengths.lambda.z).scale(radiance.z);
+        xyz_to_linear_srgb(&xyz.clone().scale(range / 3.0)) / white()
+    }
+}
+
+/// an index of refraction, possibly varying with wavelength.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    /// `a + b / wavelength^2` with the wavelength in micrometers.
+    Cauchy{a: f64, b: f64},
+    /// `n^2 = 1 + sum b_i wavelength^2 / (wavelength^2 - c_i)` with the wavelength in micrometers.
+    Sellmeier{b: [f64; 3], c: [f64; 3]},
+}
+
+impl Ior {
+    /// the sodium d line, where indices of refraction usually get quoted. rgb mode uses this one.
+    pub const REFERENCE_WAVELENGTH: f64 = 589.3;
+
+    /// schott's bk7 crown glass.
+    pub const BK7: Ior = Ior::Sellmeier{b: [1.03961212, 0.231792344, 1.01046945], c: [0.00600069867, 0.0200179144, 103.560653]};
+    /// a dense flint glass, schott's sf11, with strong dispersion.
+    pub const SF11: Ior = Ior::Sellmeier{b: [1.73759695, 0.313747346, 1.89878101], c: [0.013188707, 0.0623068142, 155.23629]};
+    pub const DIAMOND: Ior = Ior::Sellmeier{b: [4.3356, 0.3306, 0.0], c: [0.011236, 0.030625, 0.0]};
+
+    /// at `wavelength` in nm.
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let micrometers = wavelength / 1000.0;
+        let squared = micrometers * micrometers;
+        match self {
+            Ior::Constant(ior) => *ior,
+            Ior::Cauchy{a, b} => a + b / squared,
+            Ior::Sellmeier{b, c} => (1.0 + (0..3).map(|i| b[i] * squared / (squared - c[i])).sum::<f64>()).sqrt(),
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+impl From<f64> for Ior {
+    fn from(ior: f64) -> Self {
+        Ior::Constant(ior)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::{ray::Ray, scene::Scene};
+    use super::*;
+
+    // the average over many sets of wavelengths of what a path with constant `rgb` would bring back.
+    fn round_trip(rgb: Vec3<f64>) -> Vec3<f64> {
+        let n = 3000;
+        (0..n).map(|i| {
+            let mode = ColorMode::Spectral(Wavelengths::sample((i as f64 + 0.5) / n as f64));
+            mode.to_rgb(&mode.color(&rgb))
+        }).fold(Vec3::new(0., 0., 0.), |sum, rgb| sum + rgb).scale(1.0 / n as f64)
+    }
+
+    #[test]
+    fn wavelengths_cover_the_range() {
+        let wavelengths = Wavelengths::sample(0.9).lambda;
+        for lambda in [wavelengths.x, wavelengths.y, wavelengths.z] {
+            assert!((MIN_WAVELENGTH..MAX_WAVELENGTH).contains(&lambda));
+        }
+        let mut sorted = [wavelengths.x, wavelengths.y, wavelengths.z];
+        sorted.sort_by(f64::total_cmp);
+        assert_float_absolute_eq!(sorted[1] - sorted[0], (MAX_WAVELENGTH - MIN_WAVELENGTH) / 3.0, 1e-9);
+    }
+
+    #[test]
+    fn colors_survive_the_round_trip() {
+        let white = round_trip(Vec3::new(1., 1., 1.));
+        assert!((white - Vec3::new(1., 1., 1.)).length() < 1e-2, "white came back as {white:?}");
+        // smits' spectra aren't exact for the primaries, but the hue has to be right.
+        for (rgb, tolerance) in [(Vec3::new(0.8, 0.4, 0.2), 0.1), (Vec3::new(1., 0., 0.), 0.25), (Vec3::new(0., 0., 1.), 0.25), (Vec3::new(0.2, 0.6, 0.3), 0.15)] {
+            let back = round_trip(rgb);
+            assert!((back - rgb).length() < tolerance, "{rgb:?} came back as {back:?}");
+        }
+        assert_eq!(ColorMode::Rgb.to_rgb(&ColorMode::Rgb.color(&Vec3::new(0.1, 0.2, 0.3))), Vec3::new(0.1, 0.2, 0.3));
+    }
+
+    #[test]
+    fn spectral_paths_average_out_before_clamping() {
+        // single sets of wavelengths bring the sky back out of gamut, only their average is the sky.
+        let scene = Scene::new(4, 4);
+        let up = Ray{origin: Vec3::new(0., 0., 0.), direction: Vec3::new(0., 1., 0.), time: 0.0};
+        let n = 3000;
+        let samples: Vec<Vec3<f64>> = (0..n).map(|i| {
+            let mode = ColorMode::Spectral(Wavelengths::sample((i as f64 + 0.5) / n as f64));
+            up.color(&scene, mode, 0.01, f64::INFINITY, 1)
+        }).collect();
+        assert!(samples.iter().any(|rgb| rgb.x < 0.0 || rgb.y < 0.0 || rgb.z < 0.0 || rgb.x > 1.0 || rgb.y > 1.0 || rgb.z > 1.0));
+        let mean = samples.iter().fold(Vec3::new(0., 0., 0.), |sum, rgb| sum + *rgb).scale(1.0 / n as f64);
+        let sky = round_trip(Vec3::new(135./255., 206./255., 235./255.));
+        assert!((mean - sky).length() < 1e-9, "the sky came back as {mean:?}");
+    }
+
+    #[test]
+    fn reflectance_spectra() {
+        let flat = ColorMode::Rgb.reflectance(|_| 0.5);
+        assert!((flat - Vec3::new(0.5, 0.5, 0.5)).length() < 1e-3);
+        // only long wavelengths reflected comes out red.
+        let red = ColorMode::Rgb.reflectance(|wavelength| if wavelength > 600.0 { 1.0 } else { 0.0 });
+        assert!(red.x > 0.5 && red.y < 0.1 && red.z < 0.1, "{red:?}");
+        let mode = ColorMode::Spectral(Wavelengths::sample(0.5));
+        assert_eq!(mode.reflectance(|wavelength| wavelength), Wavelengths::sample(0.5).lambda);
+    }
+
+    #[test]
+    fn measured_values() {
+        let mode = ColorMode::Spectral(Wavelengths{lambda: Vec3::new(650., 500., 700.), secondary_terminated: false});
+        let values = mode.measured(&Vec3::new(1., 2., 4.));
+        assert_float_absolute_eq!(values.x, 1.0, 1e-12);
+        assert_float_absolute_eq!(values.y, 3.0, 1e-12);
+        assert_float_absolute_eq!(values.z, 1.0, 1e-12);
+    }
+
+    #[test]
+    fn dispersion() {
+        // catalog values at the d line.
+        assert_float_absolute_eq!(Ior::BK7.at(587.6), 1.5168, 1e-4);
+        assert_float_absolute_eq!(Ior::DIAMOND.at(589.3), 2.417, 2e-3);
+        // blue bends more than red.
+        for ior in [Ior::BK7, Ior::SF11, Ior::DIAMOND, Ior::Cauchy{a: 1.5, b: 0.004}] {
+            assert!(ior.at(450.0) > ior.at(650.0));
+            assert!(ior.is_dispersive());
+        }
+        assert!(!Ior::from(1.33).is_dispersive());
+        assert_eq!(ColorMode::Rgb.ior(&Ior::from(1.33)), 1.33);
+    }
+
+    #[test]
+    fn terminating_secondary_wavelengths() {
+        let mut mode = ColorMode::Spectral(Wavelengths::sample(0.2));
+        assert_eq!(mode.terminate_secondary(), Vec3::new(3., 0., 0.));
+        assert_eq!(mode.terminate_secondary(), Vec3::new(1., 0., 0.));
+        assert_eq!(ColorMode::Rgb.terminate_secondary(), Vec3::new(1., 1., 1.));
+    }
+}
@@ -0,0 +1,277 @@
+use std::{path::Path, sync::Arc};
+
+use crate::{noise::Perlin, vec3::Vec3};
+
+/// what happens to texture coordinates outside 0..1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    /// repeat, flipping every other tile so the edges line up.
+    Mirror,
+    /// stretch the border pixels.
+    Clamp,
+}
+
+impl WrapMode {
+    // a pixel index along an axis `size` pixels long.
+    fn apply(&self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size { i } else { 2 * size - 1 - i }
+            }
+            WrapMode::Clamp => i.clamp(0, size - 1),
+        };
+        i as usize
+    }
+}
+
+/// an image sampled with bilinear filtering. pixels hold linear values in 0..1, see `load` and `load_srgb`.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    // row major, the first row is the top of the image.
+    pixels: Vec<Vec3<f64>>,
+    pub wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3<f64>>, wrap: WrapMode) -> Self {
+        assert!(width >= 1 && height >= 1, "an image texture needs at least one pixel");
+        assert_eq!(pixels.len(), width * height);
+        ImageTexture{width, height, pixels, wrap}
+    }
+
+    /// png or jpeg with values as stored, for data maps. colors want `load_srgb`.
+    pub fn load(path: impl AsRef<Path>, wrap: WrapMode) -> image::ImageResult<Self> {
+        Ok(ImageTexture::load_from(image::open(path)?, wrap))
+    }
+
+    /// as `load`, decoding 8 and 16 bit srgb to linear for albedo and emission.
+    pub fn load_srgb(path: impl AsRef<Path>, wrap: WrapMode) -> image::ImageResult<Self> {
+        let image = image::open(path)?;
+        let encoded = !matches!(image, image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_));
+        let mut texture = ImageTexture::load_from(image, wrap);
+        if encoded {
+            for pixel in &mut texture.pixels {
+                *pixel = Vec3::new(srgb_to_linear(pixel.x), srgb_to_linear(pixel.y), srgb_to_linear(pixel.z));
+            }
+        }
+        Ok(texture)
+    }
+
+    fn load_from(image: image::DynamicImage, wrap: WrapMode) -> Self {
+        let image = image.to_rgb32f();
+        let pixels = image.pixels().map(|p| Vec3::new(p.0[0] as f64, p.0[1] as f64, p.0[2] as f64)).collect();
+        ImageTexture::new(image.width() as usize, image.height() as usize, pixels, wrap)
+    }
+
+    /// just the alpha channel of a png or the like, in every channel. for `Material::opacity`.
+    pub fn load_alpha(path: impl AsRef<Path>, wrap: WrapMode) -> image::ImageResult<Self> {
+        let image = image::open(path)?.to_rgba32f();
+        let pixels = image.pixels().map(|p| Vec3::new(p.0[3] as f64, p.0[3] as f64, p.0[3] as f64)).collect();
+        Ok(ImageTexture::new(image.width() as usize, image.height() as usize, pixels, wrap))
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> Vec3<f64> {
+        self.pixels[self.wrap.apply(x, self.width) + self.wrap.apply(y, self.height) * self.width]
+    }
+
+    /// v = 0 is the bottom of the image. pixel centers sit at half integer coordinates.
+    pub fn sample(&self, u: f64, v: f64) -> Vec3<f64> {
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = self.pixel(x0, y0).scale(1.0 - fx) + self.pixel(x0 + 1, y0).scale(fx);
+        let bottom = self.pixel(x0, y0 + 1).scale(1.0 - fx) + self.pixel(x0 + 1, y0 + 1).scale(fx);
+        top.clone().scale(1.0 - fy) + bottom.clone().scale(fy)
+    }
+}
+
+/// the srgb transfer function undone, 0..1 to 0..1.
+pub fn srgb_to_linear(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// the srgb transfer function, 0..1 to 0..1.
+pub fn linear_to_srgb(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoisePattern {
+    Perlin,
+    Fbm{octaves: u32},
+    Turbulence{octaves: u32},
+}
+
+/// a color, or a scalar in every channel, looked up by a hit's uv and world position.
+#[derive(Clone)]
+pub enum Texture {
+    Solid(Vec3<f64>),
+    Image(Arc<ImageTexture>),
+    /// squares alternating between two textures, `cells` of them along u and along v.
+    Checker{cells: (f64, f64), even: Box<Texture>, odd: Box<Texture>},
+    /// cubes of side `size` alternating through space, so it doesn't depend on the uv layout.
+    Checker3d{size: f64, even: Box<Texture>, odd: Box<Texture>},
+    /// blends from `low` to `high` with the noise at the hit position times `frequency`.
+    Noise{noise: Perlin, pattern: NoisePattern, frequency: f64, low: Vec3<f64>, high: Vec3<f64>},
+}
+
+impl Texture {
+    pub fn gray(value: f64) -> Self {
+        Texture::Solid(Vec3::new(value, value, value))
+    }
+
+    pub fn evaluate(&self, uv: (f64, f64), position: &Vec3<f64>) -> Vec3<f64> {
+        match self {
+            Texture::Solid(color) => *color,
+            Texture::Image(image) => image.sample(uv.0, uv.1),
+            Texture::Checker{cells, even, odd} => {
+                let parity = (uv.0 * cells.0).floor() as i64 + (uv.1 * cells.1).floor() as i64;
+                if parity.rem_euclid(2) == 0 { even.evaluate(uv, position) } else { odd.evaluate(uv, position) }
+            }
+            Texture::Checker3d{size, even, odd} => {
+                let cell = position.clone().scale(1.0 / size).floor();
+                if (cell.x + cell.y + cell.z).rem_euclid(2) == 0 { even.evaluate(uv, position) } else { odd.evaluate(uv, position) }
+            }
+            Texture::Noise{noise, pattern, frequency, low, high} => {
+                let p = position.clone().scale(*frequency);
+                let value = match pattern {
+                    NoisePattern::Perlin => 0.5 + 0.5 * noise.noise(&p),
+                    NoisePattern::Fbm{octaves} => 0.5 + 0.5 * noise.fbm(&p, *octaves),
+                    NoisePattern::Turbulence{octaves} => noise.turbulence(&p, *octaves),
+                }.clamp(0.0, 1.0);
+                low.clone().scale(1.0 - value) + high.clone().scale(value)
+            }
+        }
+    }
+
+    /// for scalar parameters like roughness, the mean of the channels.
+    pub fn evaluate_scalar(&self, uv: (f64, f64), position: &Vec3<f64>) -> f64 {
+        let value = self.evaluate(uv, position);
+        (value.x + value.y + value.z) / 3.0
+    }
+}
+
+impl From<Vec3<f64>> for Texture {
+    fn from(color: Vec3<f64>) -> Self {
+        Texture::Solid(color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use super::*;
+
+    // 2x2, black and white on the top row, red and green on the bottom one.
+    fn tiny(wrap: WrapMode) -> ImageTexture {
+        ImageTexture::new(2, 2, vec![
+            Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.),
+            Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.),
+        ], wrap)
+    }
+
+    #[test]
+    fn bilinear() {
+        let image = tiny(WrapMode::Clamp);
+        // pixel centers come back exactly.
+        assert_eq!(image.sample(0.25, 0.75), Vec3::new(0., 0., 0.));
+        assert_eq!(image.sample(0.75, 0.25), Vec3::new(0., 1., 0.));
+        // the middle is the average of all four.
+        let middle = image.sample(0.5, 0.5);
+        assert_float_absolute_eq!(middle.x, 0.5, 1e-12);
+        assert_float_absolute_eq!(middle.y, 0.5, 1e-12);
+        assert_float_absolute_eq!(middle.z, 0.25, 1e-12);
+        // clamped past the corner.
+        assert_eq!(image.sample(-3.0, 5.0), Vec3::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn wrap_modes() {
+        assert_eq!(tiny(WrapMode::Repeat).sample(1.25, 0.75), Vec3::new(0., 0., 0.));
+        assert_eq!(tiny(WrapMode::Repeat).sample(-0.25, 0.75), Vec3::new(1., 1., 1.));
+        assert_eq!(tiny(WrapMode::Mirror).sample(1.25, 0.75), Vec3::new(1., 1., 1.));
+        assert_eq!(tiny(WrapMode::Clamp).sample(1.25, 0.75), Vec3::new(1., 1., 1.));
+        assert_eq!((0..6).map(|i| WrapMode::Mirror.apply(i, 3)).collect::<Vec<_>>(), vec![0, 1, 2, 2, 1, 0]);
+        assert_eq!(WrapMode::Repeat.apply(-1, 3), 2);
+    }
+
+    #[test]
+    fn load_png_and_jpeg() {
+        for extension in ["png", "jpg"] {
+            let path = std::env::temp_dir().join(format!("texture-{}.{extension}", std::process::id()));
+            image::RgbImage::from_fn(8, 8, |_, _| image::Rgb([255, 0, 0])).save(&path).unwrap();
+            let texture = ImageTexture::load(&path, WrapMode::Repeat).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!((texture.width, texture.height), (8, 8));
+            let color = texture.sample(0.5, 0.5);
+            // jpeg is lossy.
+            assert!((color - Vec3::new(1., 0., 0.)).length() < 0.02);
+        }
+    }
+
+    #[test]
+    fn load_srgb() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert_float_absolute_eq!(srgb_to_linear(1.0), 1.0, 1e-12);
+        for encoded in [0.0, 0.02, 0.5, 0.9, 1.0] {
+            assert_float_absolute_eq!(linear_to_srgb(srgb_to_linear(encoded)), encoded, 1e-12);
+        }
+        let path = std::env::temp_dir().join(format!("srgb-{}.png", std::process::id()));
+        // srgb 188 is about half as bright as white.
+        image::RgbImage::from_fn(2, 2, |_, _| image::Rgb([188, 188, 188])).save(&path).unwrap();
+        let color = ImageTexture::load_srgb(&path, WrapMode::Clamp).unwrap().sample(0.5, 0.5);
+        let data = ImageTexture::load(&path, WrapMode::Clamp).unwrap().sample(0.5, 0.5);
+        std::fs::remove_file(&path).unwrap();
+        assert_float_absolute_eq!(color.x, 0.5, 3e-3);
+        assert_float_absolute_eq!(data.x, 188.0 / 255.0, 1e-6);
+    }
+
+    #[test]
+    fn load_alpha() {
+        let path = std::env::temp_dir().join(format!("alpha-{}.png", std::process::id()));
+        image::RgbaImage::from_fn(2, 1, |x, _| image::Rgba([255, 255, 255, if x == 0 { 0 } else { 255 }])).save(&path).unwrap();
+        let texture = ImageTexture::load_alpha(&path, WrapMode::Clamp).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(texture.sample(0.25, 0.5), Vec3::new(0., 0., 0.));
+        assert_eq!(texture.sample(0.75, 0.5), Vec3::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn checkers() {
+        let checker = Texture::Checker{cells: (4., 4.), even: Box::new(Texture::gray(0.)), odd: Box::new(Texture::gray(1.))};
+        let origin = Vec3::new(0., 0., 0.);
+        assert_eq!(checker.evaluate((0.1, 0.1), &origin).x, 0.);
+        assert_eq!(checker.evaluate((0.3, 0.1), &origin).x, 1.);
+        assert_eq!(checker.evaluate((0.3, 0.3), &origin).x, 0.);
+
+        let solid = Texture::Checker3d{size: 0.5, even: Box::new(Texture::gray(0.)), odd: Box::new(Texture::gray(1.))};
+        assert_eq!(solid.evaluate((0., 0.), &Vec3::new(0.1, 0.1, 0.1)).x, 0.);
+        assert_eq!(solid.evaluate((0., 0.), &Vec3::new(0.6, 0.1, 0.1)).x, 1.);
+        assert_eq!(solid.evaluate((0., 0.), &Vec3::new(-0.1, 0.1, 0.1)).x, 1.);
+    }
+
+    #[test]
+    fn noise_stays_between_low_and_high() {
+        for pattern in [NoisePattern::Perlin, NoisePattern::Fbm{octaves: 5}, NoisePattern::Turbulence{octaves: 5}] {
+            let texture = Texture::Noise{noise: Perlin::new(3), pattern, frequency: 4.0, low: Vec3::new(0.2, 0.2, 0.2), high: Vec3::new(0.8, 0.8, 0.8)};
+            let values: Vec<f64> = (0..500).map(|i| texture.evaluate_scalar((0., 0.), &Vec3::new(i as f64 * 0.037, i as f64 * 0.011, 0.5))).collect();
+            assert!(values.iter().all(|v| (0.2 - 1e-12..=0.8 + 1e-12).contains(v)));
+            assert!(values.iter().any(|v| (v - values[0]).abs() > 0.05));
+        }
+    }
+}
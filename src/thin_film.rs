@@ -0,0 +1,305 @@
+use std::{cell::OnceCell, f64::consts::PI};
+
+use num::Complex;
+use rand::Rng;
+
+use crate::{bsdf::{microfacet_transmission, refract, Bsdf, BsdfSample, Ggx}, spectrum::ColorMode, texture::Texture, vec3::Vec3};
+
+/// a transparent coating a few hundred nm thick like soap or oil, colored by interference.
+#[derive(Clone)]
+pub struct ThinFilm {
+    /// in nm, as the mean of the texture's channels.
+    pub thickness: Texture,
+    pub ior: f64,
+    /// the index of refraction of what is under the film, 1.0 for a soap bubble.
+    pub substrate_ior: f64,
+}
+
+impl ThinFilm {
+    /// soapy water with air on both sides, for bubbles.
+    pub fn soap(thickness: impl Into<Texture>) -> Self {
+        ThinFilm{thickness: thickness.into(), ior: 1.33, substrate_ior: 1.0}
+    }
+
+    /// oil floating on water, for puddles.
+    pub fn oil(thickness: impl Into<Texture>) -> Self {
+        ThinFilm{thickness: thickness.into(), ior: 1.47, substrate_ior: 1.33}
+    }
+}
+
+/// airy's reflectance at `wavelength` of a film `thickness` thick from air at `cos_i`, both in nm.
+pub fn reflectance(wavelength: f64, cos_i: f64, thickness: f64, ior: f64, substrate_ior: f64) -> f64 {
+    let cos_i = cos_i.abs().min(1.0);
+    let sin2 = 1.0 - cos_i * cos_i;
+    // complex so total internal reflection at the substrate works out too.
+    let cos_in = |n: f64| (Complex::new(1.0 - sin2 / (n * n), 0.0)).sqrt();
+    let (cos_1, cos_2, cos_3) = (Complex::new(cos_i, 0.0), cos_in(ior), cos_in(substrate_ior));
+    let (n_1, n_2, n_3) = (1.0, ior, substrate_ior);
+    // the phase the light going down and back up through the film picks up.
+    let phase = cos_2 * (4.0 * PI * n_2 * thickness / wavelength);
+    let delay = (Complex::<f64>::i() * phase).exp();
+    let airy = |r_12: Complex<f64>, r_23: Complex<f64>| ((r_12 + r_23 * delay) / (Complex::new(1.0, 0.0) + r_12 * r_23 * delay)).norm_sqr();
+    let s = airy((cos_1 * n_1 - cos_2 * n_2) / (cos_1 * n_1 + cos_2 * n_2), (cos_2 * n_2 - cos_3 * n_3) / (cos_2 * n_2 + cos_3 * n_3));
+    let p = airy((cos_1 * n_2 - cos_2 * n_1) / (cos_1 * n_2 + cos_2 * n_1), (cos_2 * n_3 - cos_3 * n_2) / (cos_2 * n_3 + cos_3 * n_2));
+    ((s + p) / 2.0).clamp(0.0, 1.0)
+}
+
+/// a film over the bsdf it coats, a dielectric base only refracts since the film has its interface in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coated {
+    pub thickness: f64,
+    pub ior: f64,
+    pub substrate_ior: f64,
+    pub mode: ColorMode,
+    pub base: Bsdf,
+    // `film_average`, worked out the first time the base scatters anything back up.
+    average: OnceCell<Vec3<f64>>,
+}
+
+impl Coated {
+    pub fn new(thickness: f64, ior: f64, substrate_ior: f64, mode: ColorMode, base: Bsdf) -> Self {
+        Coated{thickness, ior, substrate_ior, mode, base, average: OnceCell::new()}
+    }
+
+    fn film(&self, cos_i: f64) -> Vec3<f64> {
+        let film = self.mode.reflectance(|wavelength| reflectance(wavelength, cos_i, self.thickness, self.ior, self.substrate_ior));
+        // integrating the rgb approximation can overshoot a little.
+        Vec3::new(film.x.clamp(0.0, 1.0), film.y.clamp(0.0, 1.0), film.z.clamp(0.0, 1.0))
+    }
+
+    // only light arriving from above meets the film.
+    fn film_above(&self, w: &Vec3<f64>) -> Vec3<f64> {
+        if w.z > 0.0 { self.film(w.z) } else { Vec3::new(0., 0., 0.) }
+    }
+
+    // the film's reflectance averaged over the hemisphere, cosine weighted, from midpoints in cos^2 where
+    // the weighting is even.
+    fn film_average(&self) -> Vec3<f64> {
+        *self.average.get_or_init(|| {
+            let n = 16;
+            (0..n).fold(Vec3::new(0., 0., 0.), |sum, i| sum + self.film(((i as f64 + 0.5) / n as f64).sqrt())).scale(1.0 / n as f64)
+        })
+    }
+
+    // the share of the base's light crossing the film both ways, with what the film reflects back down
+    fn through(&self, wo: &Vec3<f64>, wi: &Vec3<f64>, specular: bool) -> Vec3<f64> {
+        let one = Vec3::new(1., 1., 1.);
+        let crossings = (one - self.film_above(wo)) * (one - self.film_above(wi));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return crossings;
+        }
+        crossings / (one - if specular { self.film(wi.z) } else { self.film_average() })
+    }
+
+    // the base's interface when the film stands in for it, a dielectric's seen from above.
+    fn interface(&self, wo: &Vec3<f64>) -> Option<(&Ggx, f64, Vec3<f64>)> {
+        match &self.base {
+            Bsdf::Dielectric{distribution, eta, tint} if wo.z > 0.0 => Some((distribution, *eta, *tint)),
+            _ => None,
+        }
+    }
+
+    // where a smooth such base sends all of what the film lets through, and its weight.
+    fn refracted(&self, wo: &Vec3<f64>) -> Option<(Vec3<f64>, Vec3<f64>)> {
+        let (_, eta, tint) = self.interface(wo)?;
+        refract(wo, &Vec3::new(0., 0., 1.), eta).map(|(direction, etap)| (direction, tint.clone().scale(1.0 / (etap * etap))))
+    }
+
+    // a rough one refracts through its microfacets without reflecting off any.
+    fn transmitted(&self, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Option<(Vec3<f64>, f64)> {
+        let (distribution, eta, tint) = self.interface(wo)?;
+        if distribution.is_smooth() {
+            return None;
+        }
+        microfacet_transmission(distribution, eta, wo, wi).map(|(f, pdf)| (tint.clone().scale(f), pdf))
+    }
+
+    /// as `Bsdf::evaluate`, the base seen through the film.
+    pub fn evaluate(&self, wo: &Vec3<f64>, wi: &Vec3<f64>) -> Vec3<f64> {
+        let f = if self.interface(wo).is_some() {
+            self.transmitted(wo, wi).map_or(Vec3::new(0., 0., 0.), |(f, _)| f)
+        } else {
+            self.base.evaluate(wo, wi)
+        };
+        if f == Vec3::new(0., 0., 0.) {
+            return f;
+        }
+        self.through(wo, wi, false) * f
+    }
+
+    /// as `Bsdf::pdf`.
+    pub fn pdf(&self, wo: &Vec3<f64>, wi: &Vec3<f64>) -> f64 {
+        let pdf = if self.interface(wo).is_some() { self.transmitted(wo, wi).map_or(0.0, |(_, pdf)| pdf) } else { self.base.pdf(wo, wi) };
+        (1.0 - mean(&self.film_above(wo))) * pdf
+    }
+
+    /// as `Bsdf::sample`, the film's mirror reflection picked as often as it reflects on average.
+    pub fn sample(&self, wo: &Vec3<f64>, rng: &mut impl Rng) -> Option<BsdfSample> {
+        let film = self.film_above(wo);
+        let reflected = mean(&film);
+        if rng.gen::<f64>() < reflected {
+            let direction = Vec3::new(-wo.x, -wo.y, wo.z);
+            return Some(BsdfSample{direction, weight: film.clone().scale(1.0 / reflected), pdf: 1.0, specular: true});
+        }
+        if let Some((distribution, eta, _)) = self.interface(wo) {
+            let through = (Vec3::new(1., 1., 1.) - film).scale(1.0 / (1.0 - reflected));
+            if distribution.is_smooth() {
+                let (direction, weight) = self.refracted(wo)?;
+                return Some(BsdfSample{direction, weight: through * weight, pdf: 1.0, specular: true});
+            }
+            let wm = distribution.sample_visible(wo, (rng.gen(), rng.gen()));
+            let direction = refract(wo, &wm, eta)?.0;
+            let (f, pdf) = self.transmitted(wo, &direction)?;
+            if pdf <= 0.0 {
+                return None;
+            }
+            return Some(BsdfSample{direction, weight: through * f.clone().scale(1.0 / pdf), pdf: pdf * (1.0 - reflected), specular: false});
+        }
+        let sample = self.base.sample(wo, rng)?;
+        let weight = (self.through(wo, &sample.direction, sample.specular) * sample.weight).scale(1.0 / (1.0 - reflected));
+        Some(BsdfSample{weight, pdf: sample.pdf * (1.0 - reflected), ..sample})
+    }
+
+    /// as `Bsdf::specular_directions`, the film's mirror and whatever of the base's it lets through.
+    pub fn specular_directions(&self, wo: &Vec3<f64>) -> Vec<(Vec3<f64>, Vec3<f64>)> {
+        let film = self.film_above(wo);
+        let mut directions = vec![(Vec3::new(-wo.x, -wo.y, wo.z), film)];
+        if self.interface(wo).is_some() {
+            let through = Vec3::new(1., 1., 1.) - film;
+            directions.extend(self.refracted(wo).map(|(direction, weight)| (direction, through * weight)));
+            return directions;
+        }
+        directions.extend(self.base.specular_directions(wo).into_iter().map(|(direction, weight)| (direction, self.through(wo, &direction, true) * weight)));
+        directions
+    }
+}
+
+fn mean(v: &Vec3<f64>) -> f64 {
+    (v.x + v.y + v.z) / 3.0
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use crate::{bsdf::{fresnel_dielectric, tests::sphere_grid, Metal}, spectrum::Wavelengths};
+    use super::*;
+
+    #[test]
+    fn airy() {
+        // no film, or one matching what is under it, is just the interface's fresnel reflection.
+        for cos_i in [1.0, 0.7, 0.2] {
+            assert_float_absolute_eq!(reflectance(550.0, cos_i, 0.0, 1.33, 1.5), fresnel_dielectric(cos_i, 1.5), 1e-9);
+            assert_float_absolute_eq!(reflectance(550.0, cos_i, 321.0, 1.5, 1.5), fresnel_dielectric(cos_i, 1.5), 1e-9);
+        }
+        // a quarter wave coating with the geometric mean index cancels the reflection, like on camera lenses.
+        let ior = 1.5f64.sqrt();
+        assert_float_absolute_eq!(reflectance(550.0, 1.0, 550.0 / (4.0 * ior), ior, 1.5), 0.0, 1e-9);
+        // and a half wave one doesn't change a thing.
+        assert_float_absolute_eq!(reflectance(550.0, 1.0, 550.0 / (2.0 * ior), ior, 1.5), 0.04, 1e-9);
+    }
+
+    #[test]
+    fn soap_bubbles_are_colorful() {
+        let bubble = |thickness: f64| Coated::new(thickness, 1.33, 1.0, ColorMode::Rgb, Bsdf::Lambertian{albedo: Vec3::new(0., 0., 0.)}).film(1.0);
+        let colors: Vec<Vec3<f64>> = [250.0, 350.0, 450.0, 550.0].into_iter().map(bubble).collect();
+        for color in &colors {
+            assert!(color.x.max(color.y).max(color.z) - color.x.min(color.y).min(color.z) > 0.02, "{color:?} is gray");
+        }
+        // too thin to interfere constructively anywhere, the film goes dark.
+        assert!(mean(&bubble(5.0)) < 0.01);
+    }
+
+    #[test]
+    fn coated_sampling() {
+        let coated = Coated::new(400.0, 1.4, 1.5, ColorMode::Rgb, Bsdf::Lambertian{albedo: Vec3::new(1., 1., 1.)});
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let n = 20_000;
+        let mut rng = StdRng::seed_from_u64(0);
+        let samples: Vec<BsdfSample> = (0..n).filter_map(|_| coated.sample(&wo, &mut rng)).collect();
+        // everything is reflected one way or the other, in the film's colors or the base's.
+        let total = samples.iter().fold(Vec3::new(0., 0., 0.), |sum, sample| sum + sample.weight).scale(1.0 / n as f64);
+        assert!((total - Vec3::new(1., 1., 1.)).length() < 2e-2, "{total:?}");
+        let mirrored = samples.iter().filter(|sample| sample.specular).count() as f64 / n as f64;
+        assert_float_absolute_eq!(mirrored, mean(&coated.film(0.8)), 1e-2);
+        let wi = Vec3::new(0., 0.6, 0.8);
+        assert_float_absolute_eq!(coated.pdf(&wo, &wi), (1.0 - mirrored) * 0.8 / PI, 1e-2);
+    }
+
+    #[test]
+    fn coating_is_reciprocal() {
+        let (eta, k) = Metal::Gold.ior();
+        let bases = [
+            Bsdf::Lambertian{albedo: Vec3::new(0.8, 0.5, 0.2)},
+            Bsdf::Conductor{distribution: Ggx::from_roughness(0.5), eta, k, tint: Vec3::new(1., 1., 1.)},
+        ];
+        let directions: Vec<Vec3<f64>> = sphere_grid(6).filter(|w| w.z > 0.0).collect();
+        for base in bases {
+            let coated = Coated::new(400.0, 1.4, 1.5, ColorMode::Spectral(Wavelengths::sample(0.3)), base);
+            for wo in &directions {
+                for wi in &directions {
+                    // evaluate has the cosine of wi in.
+                    let there = coated.evaluate(wo, wi).scale(1.0 / wi.z);
+                    let back = coated.evaluate(wi, wo).scale(1.0 / wo.z);
+                    assert!((there - back).length() < 1e-9 * (1.0 + there.length()), "{there:?} {back:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn white_under_the_film() {
+        // what the film doesn't reflect at first comes out of a white base in the end.
+        let coated = Coated::new(400.0, 1.4, 1.5, ColorMode::Spectral(Wavelengths::sample(0.3)), Bsdf::Lambertian{albedo: Vec3::new(1., 1., 1.)});
+        let n = 400;
+        for wo in [Vec3::new(0., 0., 1.), Vec3::new(0.6, 0.0, 0.8), Vec3::new(0.95, 0.0, 0.3)] {
+            let wo = wo.normalize();
+            // lambertian doesn't care about the azimuth, so midpoints in cos^2 integrate it.
+            let diffuse = (0..n).fold(Vec3::new(0., 0., 0.), |sum, i| {
+                let z = ((i as f64 + 0.5) / n as f64).sqrt();
+                sum + coated.evaluate(&wo, &Vec3::new((1.0 - z * z).sqrt(), 0., z)).scale(PI / z)
+            }).scale(1.0 / n as f64);
+            let total = coated.film(wo.z) + diffuse;
+            assert!((total - Vec3::new(1., 1., 1.)).length() < 1e-2, "{total:?}");
+        }
+    }
+
+    #[test]
+    fn oil_over_water() {
+        // the film's reflectance has the oil to water interface in, the water doesn't reflect again under it.
+        let water = Bsdf::Dielectric{distribution: Ggx::from_roughness(0.0), eta: 1.33, tint: Vec3::new(1., 1., 1.)};
+        let oil = ThinFilm::oil(Texture::gray(350.0));
+        let puddle = Coated::new(350.0, oil.ior, oil.substrate_ior, ColorMode::Spectral(Wavelengths::sample(0.3)), water);
+        for cos_i in [1.0, 0.6, 0.2] {
+            let wo = Vec3::new((1.0f64 - cos_i * cos_i).sqrt(), 0., cos_i);
+            let directions = puddle.specular_directions(&wo);
+            assert_eq!(directions.len(), 2);
+            let (reflected, refracted) = (directions[0].1, directions[1].1);
+            assert_eq!(reflected, puddle.film(cos_i));
+            // refraction squeezes the radiance by eta^2, but what gets in and what is reflected add up.
+            let total = reflected + refracted.clone().scale(1.33 * 1.33);
+            assert!((total - Vec3::new(1., 1., 1.)).length() < 1e-9, "{total:?}");
+        }
+    }
+
+    #[test]
+    fn rough_water_under_the_film() {
+        // a white furnace: only what the microfacets shadow is lost, none of it is reflected twice.
+        let energy = |sample: BsdfSample| sample.weight.clone().scale(if sample.direction.z < 0.0 { 1.33 * 1.33 } else { 1.0 });
+        let mut rng = StdRng::seed_from_u64(0);
+        let n = 10_000;
+        for roughness in [0.2, 0.6] {
+            let water = Bsdf::Dielectric{distribution: Ggx::from_roughness(roughness), eta: 1.33, tint: Vec3::new(1., 1., 1.)};
+            let puddle = Coated::new(350.0, 1.47, 1.33, ColorMode::Rgb, water.clone());
+            for cos_i in [1.0f64, 0.6, 0.2] {
+                let wo = Vec3::new((1.0 - cos_i * cos_i).sqrt(), 0., cos_i);
+                let kept = (0..n).filter_map(|_| puddle.sample(&wo, &mut rng)).map(energy).fold(Vec3::new(0., 0., 0.), |sum, e| sum + e).scale(1.0 / n as f64);
+                // and at least as much as the bare water keeps, which loses some of its reflection too.
+                let bare = (0..n).filter_map(|_| water.sample(&wo, &mut rng)).map(|sample| energy(sample).x).sum::<f64>() / n as f64;
+                for channel in [kept.x, kept.y, kept.z] {
+                    assert!(channel <= 1.0 + 1e-2 && channel >= bare - 1e-2, "roughness {roughness} at {cos_i} keeps {kept:?}, bare {bare}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{aabb::Aabb, hittable::{HitReturn, Hittable}, material::MaterialId, ray::Ray, vec3::Vec3};
+
+// lets rays through shared edges hit at least one of the triangles despite rounding.
+const BARYCENTRIC_EPSILON: f64 = 1e-9;
+
+/// möller-trumbore, returns t and the barycentric weights of `p1` and `p2`.
+pub fn intersect_triangle(ray: &Ray, p0: &Vec3<f64>, p1: &Vec3<f64>, p2: &Vec3<f64>, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let edge1 = *p1 - *p0;
+    let edge2 = *p2 - *p0;
+    let p = ray.direction.cross(&edge2);
+    let determinant = edge1.dot(&p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+    let s = ray.origin - *p0;
+    let u = s.dot(&p) * inverse_determinant;
+    if !(-BARYCENTRIC_EPSILON..=1.0 + BARYCENTRIC_EPSILON).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&edge1);
+    let v = ray.direction.dot(&q) * inverse_determinant;
+    if v < -BARYCENTRIC_EPSILON || u + v > 1.0 + BARYCENTRIC_EPSILON {
+        return None;
+    }
+    let t = edge2.dot(&q) * inverse_determinant;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some((t, u, v))
+}
+
+/// how far a point on a triangle moves per unit u and v, `None` for degenerate uvs.
+pub fn uv_derivatives(vertices: &[Vec3<f64>; 3], uvs: &[(f64, f64); 3]) -> Option<(Vec3<f64>, Vec3<f64>)> {
+    let (edge1, edge2) = (vertices[1] - vertices[0], vertices[2] - vertices[0]);
+    let (du1, dv1) = (uvs[1].0 - uvs[0].0, uvs[1].1 - uvs[0].1);
+    let (du2, dv2) = (uvs[2].0 - uvs[0].0, uvs[2].1 - uvs[0].1);
+    let determinant = du1 * dv2 - du2 * dv1;
+    if determinant.abs() <= 1e-12 {
+        return None;
+    }
+    let dpdu = (edge1.clone().scale(dv2) - edge2.clone().scale(dv1)).scale(1.0 / determinant);
+    let dpdv = (edge2.clone().scale(du1) - edge1.clone().scale(du2)).scale(1.0 / determinant);
+    Some((dpdu, dpdv))
+}
+
+/// the direction of increasing u flattened onto `normal`'s plane, the first edge for degenerate uvs.
+pub fn uv_tangent(vertices: &[Vec3<f64>; 3], uvs: &[(f64, f64); 3], normal: &Vec3<f64>) -> Vec3<f64> {
+    let tangent = uv_derivatives(vertices, uvs).map_or(vertices[1] - vertices[0], |(dpdu, _)| dpdu);
+    let tangent = tangent - normal.clone().scale(normal.dot(&tangent));
+    if tangent.length_squared() > 0.0 { tangent.normalize() } else { normal.orthonormal_basis().0 }
+}
+
+/// a single triangle, hit from either side, its front where the vertices wind counterclockwise.
+pub struct Triangle {
+    pub vertices: [Vec3<f64>; 3],
+    /// per vertex normals to interpolate for shading, the face normal is used if `None`.
+    pub normals: Option<[Vec3<f64>; 3]>,
+    pub uvs: [(f64, f64); 3],
+    pub material: MaterialId,
+}
+
+impl Triangle {
+    /// flat shaded, with the uvs of the corners of a unit square's lower half.
+    pub fn new(vertices: [Vec3<f64>; 3], material: MaterialId) -> Self {
+        Triangle{vertices, normals: None, uvs: [(0., 0.), (1., 0.), (0., 1.)], material}
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitReturn> {
+        let [p0, p1, p2] = &self.vertices;
+        let (t, b1, b2) = intersect_triangle(ray, p0, p1, p2, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+        let face_normal = (*p1 - *p0).cross(&(*p2 - *p0)).normalize();
+        let front_face = ray.direction.dot(&face_normal) < 0.0;
+        let normal = if front_face { face_normal } else { -face_normal };
+        let shading_normal = match &self.normals {
+            Some([n0, n1, n2]) => {
+                let interpolated = (n0.clone().scale(b0) + n1.clone().scale(b1) + n2.clone().scale(b2)).normalize();
+                let interpolated = if front_face { interpolated } else { -interpolated };
+                // vertex normals bent past the face would light the wrong side.
+                if interpolated.dot(&normal) > 0.0 { interpolated } else { normal }
+            }
+            None => normal,
+        };
+        let uv = (
+            b0 * self.uvs[0].0 + b1 * self.uvs[1].0 + b2 * self.uvs[2].0,
+            b0 * self.uvs[0].1 + b1 * self.uvs[1].1 + b2 * self.uvs[2].1,
+        );
+        let tangent = uv_tangent(&self.vertices, &self.uvs, &normal);
+        let hit_position = ray.origin + ray.direction.clone().scale(t);
+        let hit_return = HitReturn{shading_normal, uv, tangent, ..HitReturn::new(hit_position, normal, t, front_face, self.material)};
+        let (dpdu, dpdv) = uv_derivatives(&self.vertices, &self.uvs).unwrap_or((hit_return.dpdu, hit_return.dpdv));
+        Some(HitReturn{dpdu, dpdv, ..hit_return})
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [p0, p1, p2] = self.vertices;
+        Some(pad(Aabb::new(p0, p0).surrounding(&Aabb::new(p1, p1)).surrounding(&Aabb::new(p2, p2))))
+    }
+}
+
+// flat shapes lying in an axis plane would get a box with no thickness.
+pub fn pad(bounds: Aabb) -> Aabb {
+    let padding = Vec3::new(1e-6, 1e-6, 1e-6);
+    Aabb::new(bounds.min - padding, bounds.max + padding)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::conformance::Conformance;
+    use super::*;
+
+    fn triangle() -> Triangle {
+        Triangle::new([Vec3::new(0., 0., 0.), Vec3::new(2., 0., 0.), Vec3::new(0., 2., 0.)], MaterialId::default())
+    }
+
+    #[test]
+    fn front_and_back() {
+        let front = Ray{origin: Vec3::new(0.5, 0.5, 3.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = triangle().hit(&front, 0.001, f64::INFINITY).unwrap();
+        assert_float_absolute_eq!(hit.t, 3.0, 1e-12);
+        assert!(hit.front_face);
+        assert!((hit.normal - Vec3::new(0., 0., 1.)).length() < 1e-12);
+        assert_float_absolute_eq!(hit.uv.0, 0.25, 1e-12);
+        assert_float_absolute_eq!(hit.uv.1, 0.25, 1e-12);
+        assert!((hit.tangent - Vec3::new(1., 0., 0.)).length() < 1e-12);
+
+        let back = Ray{origin: Vec3::new(0.5, 0.5, -3.), direction: Vec3::new(0., 0., 1.), time: 0.};
+        let hit = triangle().hit(&back, 0.001, f64::INFINITY).unwrap();
+        assert!(!hit.front_face);
+        assert!((hit.normal - Vec3::new(0., 0., -1.)).length() < 1e-12);
+
+        let outside = Ray{origin: Vec3::new(1.5, 1.5, 3.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        assert!(triangle().hit(&outside, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn tangent_follows_uvs() {
+        // u running down the y edge instead.
+        let triangle = Triangle{uvs: [(0., 0.), (0., 1.), (1., 0.)], ..triangle()};
+        let ray = Ray{origin: Vec3::new(0.5, 0.5, 3.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = triangle.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.tangent - Vec3::new(0., 1., 0.)).length() < 1e-12);
+        assert!((hit.bitangent() - Vec3::new(-1., 0., 0.)).length() < 1e-12);
+        assert!((hit.dpdu - Vec3::new(0., 2., 0.)).length() < 1e-12);
+        assert!((hit.dpdv - Vec3::new(2., 0., 0.)).length() < 1e-12);
+    }
+
+    #[test]
+    fn interpolated_normals() {
+        let tilted = Vec3::new(1., 0., 1.).normalize();
+        let up = Vec3::new(0., 0., 1.);
+        let triangle = Triangle{normals: Some([up, tilted, up]), ..triangle()};
+        let ray = Ray{origin: Vec3::new(1.0, 0.0, 3.), direction: Vec3::new(0., 0., -1.), time: 0.};
+        let hit = triangle.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.normal - up).length() < 1e-12);
+        assert!(hit.shading_normal.x > 0.1);
+
+        let back = Ray{origin: Vec3::new(1.0, 0.0, -3.), direction: Vec3::new(0., 0., 1.), time: 0.};
+        let hit = triangle.hit(&back, 0.001, f64::INFINITY).unwrap();
+        assert!(hit.shading_normal.dot(&hit.normal) > 0.0);
+    }
+
+    #[test]
+    fn conformance() {
+        let triangle = Triangle::new([Vec3::new(1., -1., 0.), Vec3::new(-1., 2., 1.), Vec3::new(0., 1., -2.)], MaterialId::default());
+        let bounds = triangle.bounding_box().unwrap();
+        Conformance{hittable: &triangle, bounds, inside: None, convex: false}.check();
+    }
+}
@@ -33,7 +33,7 @@ impl Vec3<f64> {
     }
 
     pub fn length_squared(&self) -> f64 {
-        self.dot(&self)
+        self.dot(self)
     }
 
     pub fn length(&self) -> f64 {
@@ -77,6 +77,31 @@ impl Vec3<f64> {
         Vec3::new(rng.gen(), rng.gen(), rng.gen())
     }
 
+    pub fn random_in_unit_sphere() -> Vec3<f64> {
+        loop {
+            let v = Vec3::<f64>::random().scale(2.0).shift(-1.0);
+            if v.length_squared() < 1.0 {
+                return v;
+            }
+        }
+    }
+
+    pub fn random_unit_vector() -> Vec3<f64> {
+        Vec3::random_in_unit_sphere().normalize()
+    }
+
+    /// two unit vectors that together with `self` (assumed unit length) form a right handed orthonormal basis.
+    pub fn orthonormal_basis(&self) -> (Vec3<f64>, Vec3<f64>) {
+        // duff et al. 2017, branchless and stable for every direction.
+        let sign = 1.0f64.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vec3::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
     pub fn shift(&mut self, scalar: f64) -> Vec3<f64> {
         self.x += scalar;
         self.y += scalar;
@@ -241,6 +266,20 @@ mod tests {
     }
 
     #[test]
+    fn orthonormal_basis() {
+        for n in [Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.), Vec3::new(1., 2., 3.).normalize(), Vec3::new(-0.3, 0.1, -0.9).normalize()] {
+            let (t, b) = n.orthonormal_basis();
+            assert_float_absolute_eq!(t.length(), 1.0, 1e-12);
+            assert_float_absolute_eq!(b.length(), 1.0, 1e-12);
+            assert_float_absolute_eq!(t.dot(&n), 0.0, 1e-12);
+            assert_float_absolute_eq!(b.dot(&n), 0.0, 1e-12);
+            assert_float_absolute_eq!(t.dot(&b), 0.0, 1e-12);
+            assert!((t.cross(&b) - n).length() < 1e-12);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
     fn rotation_quat() {
         let a = Vec3::new(1., 0., 0.);
         let axis = Vec3::new(1., 1., 0.).normalize();
@@ -24,7 +24,7 @@ impl Vec4<f64> {
     }
 
     pub fn length_squared(&self) -> f64 {
-        self.dot(&self)
+        self.dot(self)
     }
 
     pub fn length(&self) -> f64 {
@@ -0,0 +1,111 @@
+use crate::{bsdf::Frame, hittable::Hittable, light::Punctual, ray::{Ray, SHADOW_GAP}, scene::Scene, spectrum::ColorMode, vec3::Vec3};
+
+/// whitted (1980) style radiance along `ray` with `Lights::punctual`, hard shadows and no noise.
+pub fn radiance(scene: &Scene, ray: &Ray, t_min: f64, max_depth: u32, lights: &[Punctual]) -> Vec3<f64> {
+    trace(scene, ray, t_min, max_depth, lights)
+}
+
+fn trace(scene: &Scene, ray: &Ray, t_min: f64, depth_left: u32, lights: &[Punctual]) -> Vec3<f64> {
+    let direction = ray.direction.normalize();
+    let Some(hit_return) = scene.hit(ray, t_min, f64::INFINITY) else {
+        return scene.sky().radiance(&direction) + scene.sky().sun_radiance(&direction);
+    };
+    let position = hit_return.hit_position;
+    let material = scene.material(hit_return.material);
+    let mut radiance = Vec3::new(0., 0., 0.);
+    let surface = hit_return.medium.is_none().then(|| (Frame::new(material.shading_normal(&hit_return), hit_return.tangent), material.bsdf(&hit_return, &ColorMode::Rgb)));
+    if let Some((frame, bsdf)) = &surface {
+        radiance = radiance + material.emission.evaluate(hit_return.uv, &position);
+        if depth_left > 0 {
+            for (local, weight) in bsdf.specular_directions(&frame.to_local(&-direction)) {
+                let next_direction = frame.to_world(&local);
+                // sent across the geometric surface by a bent shading normal.
+                if next_direction.dot(&hit_return.normal) * local.z <= 0.0 {
+                    continue;
+                }
+                let next = Ray{origin: position, direction: next_direction, time: ray.time};
+                radiance = radiance + weight * trace(scene, &next, t_min, depth_left - 1, lights);
+            }
+        }
+    }
+    for light in lights {
+        let (toward_light, distance, irradiance, object) = match light {
+            Punctual::Point{position: light_position, intensity, object} => {
+                let offset = *light_position - position;
+                let distance = offset.length();
+                (offset.clone().scale(1.0 / distance), distance, intensity.clone().scale(1.0 / (distance * distance)), Some(*object))
+            }
+            Punctual::Directional{direction, irradiance} => (*direction, f64::INFINITY, *irradiance, None),
+        };
+        // how much of the light gets scattered back along the ray, cosine and all.
+        let f = match (&surface, hit_return.medium) {
+            (Some((frame, bsdf)), _) => {
+                let (wo, wi) = (frame.to_local(&-direction), frame.to_local(&toward_light));
+                // the geometric surface decides which side either is on, whatever the shading normal says.
+                if hit_return.normal.dot(&-direction) * wo.z <= 0.0 || hit_return.normal.dot(&toward_light) * wi.z <= 0.0 {
+                    continue;
+                }
+                bsdf.evaluate(&wo, &wi)
+            }
+            (None, Some(scattering)) => scattering.albedo.clone().scale(scattering.phase.evaluate(&direction, &toward_light)),
+            (None, None) => continue,
+        };
+        if f == Vec3::new(0., 0., 0.) {
+            continue;
+        }
+        let shadow_ray = Ray{origin: position, direction: toward_light, time: ray.time};
+        // shadows only go as far as the light's own surface.
+        let distance = object.and_then(|object| scene.objects()[object].hit(&shadow_ray, t_min, distance)).map_or(distance, |hit_return| hit_return.t);
+        let visibility = scene.transmittance(&shadow_ray, t_min, distance * (1.0 - SHADOW_GAP));
+        radiance = radiance + (f * irradiance).scale(visibility);
+    }
+    radiance
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_float_eq::*;
+    use crate::{hittable::Sphere, material::Material, quad::Quad, scene::Object, sky::Sky};
+    use super::*;
+
+    // a floor through the origin under a small round lamp, lighting it with pi L sin^2 a from straight above.
+    fn lamp_over_floor() -> Scene {
+        let mut scene = Scene::new(4, 4);
+        scene.set_sky(Sky::Flat{color: Vec3::new(0., 0., 0.), sun_direction: Vec3::new(0., 1., 0.), sun_irradiance: 0.0});
+        let floor = scene.add_material(Material::diffuse(Vec3::new(0.5, 0.5, 0.5)));
+        let emission = 1.0 / (0.05f64 / 4.0).powi(2);
+        let lamp = scene.add_material(Material::emissive(Vec3::new(emission, emission, emission)));
+        scene.add_object(Object::Quad(Box::new(Quad{corner: Vec3::new(-50., 0., -50.), u: Vec3::new(0., 0., 100.), v: Vec3::new(100., 0., 0.), material: floor})));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.05, center: Vec3::new(0., 4., 0.), material: lamp}));
+        scene.update_lights();
+        scene
+    }
+
+    #[test]
+    fn small_lamps_and_hard_shadows() {
+        let mut scene = lamp_over_floor();
+        let ray = Ray{origin: Vec3::new(3., 0.5, 0.), direction: Vec3::new(-3., -0.5, 0.), time: 0.0};
+        assert_float_absolute_eq!(radiance(&scene, &ray, 1e-6, 1, &scene.lights().punctual(&scene)).y, 0.5, 1e-9);
+        // anything in the way blocks the lamp altogether.
+        let blocker = scene.add_material(Material::diffuse(Vec3::new(0.5, 0.5, 0.5)));
+        scene.add_object(Object::Sphere(Sphere{radius: 0.5, center: Vec3::new(0., 2., 0.), material: blocker}));
+        scene.update_lights();
+        assert_eq!(radiance(&scene, &ray, 1e-6, 1, &scene.lights().punctual(&scene)).y, 0.0);
+    }
+
+    #[test]
+    fn glass_loses_no_light() {
+        // all that goes into the ball comes out again somewhere, and the sky is the same everywhere.
+        let mut scene = Scene::new(4, 4);
+        scene.set_sky(Sky::Flat{color: Vec3::new(0.5, 0.5, 0.5), sun_direction: Vec3::new(0., 1., 0.), sun_irradiance: 0.0});
+        let glass = scene.add_material(Material::dielectric(1.5, 0.0));
+        scene.add_object(Object::Sphere(Sphere{radius: 1.0, center: Vec3::new(0., 0., 0.), material: glass}));
+        scene.update_lights();
+        for offset in [0.0, 0.5, 0.9] {
+            let ray = Ray{origin: Vec3::new(offset, 0., 5.), direction: Vec3::new(0., 0., -1.), time: 0.0};
+            assert_float_absolute_eq!(radiance(&scene, &ray, 1e-6, 12, &scene.lights().punctual(&scene)).y, 0.5, 1e-3);
+            // with no bounces left the ball only shows its own lighting, of which there is none.
+            assert_eq!(radiance(&scene, &ray, 1e-6, 0, &scene.lights().punctual(&scene)).y, 0.0);
+        }
+    }
+}
@@ -4,7 +4,7 @@ use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 use winit_input_helper::WinitInputHelper;
-use crate::scene::Scene;
+use ray_tracing_weekend_rs::{debug::DebugView, scene::{Integrator, Scene}};
 
 pub struct Window {
     pub width: u32,
@@ -62,6 +62,28 @@ impl Window {
                     *control_flow = ControlFlow::Exit;
                     return;
                 }
+                if input.key_pressed(VirtualKeyCode::B) {
+                    scene.integrator = if scene.integrator == Integrator::Bidirectional { Integrator::PathTracer } else { Integrator::Bidirectional };
+                }
+                if input.key_pressed(VirtualKeyCode::P) {
+                    scene.integrator = if scene.integrator == Integrator::PhotonMapping { Integrator::PathTracer } else { Integrator::PhotonMapping };
+                }
+                if input.key_pressed(VirtualKeyCode::R) {
+                    scene.integrator = if scene.integrator == Integrator::Whitted { Integrator::PathTracer } else { Integrator::Whitted };
+                }
+                // the number keys switch debug views on, and off again.
+                for (key, view) in [
+                    (VirtualKeyCode::Key1, DebugView::Normals),
+                    (VirtualKeyCode::Key2, DebugView::Depth),
+                    (VirtualKeyCode::Key3, DebugView::AmbientOcclusion),
+                    (VirtualKeyCode::Key4, DebugView::Uv),
+                    (VirtualKeyCode::Key5, DebugView::Bounces),
+                    (VirtualKeyCode::Key6, DebugView::IntersectionTests),
+                ] {
+                    if input.key_pressed(key) {
+                        scene.integrator = if scene.integrator == Integrator::Debug(view) { Integrator::PathTracer } else { Integrator::Debug(view) };
+                    }
+                }
                 
                 if input.mouse_held(1) {
                     if let Err(err) = window.set_cursor_grab(winit::window::CursorGrabMode::Confined) {